@@ -0,0 +1,255 @@
+use super::lexer::{ SyntaxTreeNode, ScopeHandle, ScopePool, Type };
+use super::needle::Loc;
+use super::vm::{ ByteVec, Instruction, LineTable, Program, Function, Constant };
+use super::vm::natives::{ NativeImport, Value };
+use std::collections::HashMap;
+
+/// What the debugger needs to find variables in a running program
+#[derive(Clone)]
+pub struct DebugInfo {
+    /// The local slot of every variable, by the scope it was declared in
+    pub locals: HashMap<(ScopeHandle, String), u32>,
+    /// Which scope the code is in from an address and on, sorted by address
+    pub scopes: Vec<(usize, ScopeHandle)>
+}
+
+impl DebugInfo {
+    pub fn get_scope(&self, address: usize) -> Option<ScopeHandle> {
+        self.scopes.iter().rev()
+            .find(|(start, _)| *start <= address)
+            .map(|(_, scope)| *scope)
+    }
+
+    pub fn get_local(&self, scope: ScopeHandle, scopes: &ScopePool, var_name: &str) -> Option<u32> {
+        let scope = scope.get_declaring_scope(scopes, var_name)?;
+        self.locals.get(&(scope, String::from(var_name))).copied()
+    }
+}
+
+/// Turns a syntax tree into bytecode for the virtual machine.
+/// The nodes compile themselves through ``SyntaxTreeNode::compile``,
+/// this just keeps track of the state they share.
+pub struct Compiler {
+    code: ByteVec,
+    line_table: LineTable,
+    functions: Vec<Function>,
+    constants: Vec<Constant>,
+    natives: Vec<NativeImport>,
+    locals: HashMap<(ScopeHandle, String), u32>,
+    n_locals: u32,
+    scopes: Vec<(usize, ScopeHandle)>,
+    /// The scopes of the modules whose code has been emitted
+    modules: Vec<ScopeHandle>,
+    /// Where the code is going to be loaded, addresses are offset by this
+    base_address: usize
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            code: ByteVec::new(),
+            line_table: LineTable::new(),
+            functions: Vec::new(),
+            constants: Vec::new(),
+            natives: Vec::new(),
+            locals: HashMap::new(),
+            n_locals: 0,
+            scopes: Vec::new(),
+            modules: Vec::new(),
+            base_address: 0
+        }
+    }
+
+    pub fn get_address(&self) -> usize {
+        self.base_address + self.code.get_index()
+    }
+
+    /// Pushes an instruction without operands, and remembers what code it came from
+    pub fn emit(&mut self, instruction: Instruction, loc: Loc) {
+        self.line_table.add(self.get_address(), loc);
+        self.code.push(instruction as u8);
+    }
+
+    /// Remembers that the code from here on is in this scope
+    pub fn enter_scope(&mut self, scope: ScopeHandle) {
+        let address = self.get_address();
+        if let Some(last) = self.scopes.last_mut() {
+            if last.0 == address {
+                last.1 = scope;
+                return;
+            }
+        }
+        self.scopes.push((address, scope));
+    }
+
+    pub fn emit_with<T: Sized>(&mut self, instruction: Instruction, operand: T, loc: Loc) {
+        self.emit(instruction, loc);
+        self.code.push(operand);
+    }
+
+    /// Adds a value to the constant pool, reusing an equal one if there is one
+    pub fn add_constant(&mut self, constant: Constant) -> u32 {
+        if let Some(index) = self.constants.iter().position(|c| *c == constant) {
+            return index as u32;
+        }
+
+        self.constants.push(constant);
+        (self.constants.len() - 1) as u32
+    }
+
+    /// Adds a native function to the ones the program imports, reusing the same one if it's there
+    pub fn add_native(&mut self, import: NativeImport) -> u32 {
+        if let Some(index) = self.natives.iter().position(|native| *native == import) {
+            return index as u32;
+        }
+
+        self.natives.push(import);
+        (self.natives.len() - 1) as u32
+    }
+
+    /// Gets the local slot of a variable, giving it a new slot the first time
+    pub fn get_local(&mut self, scope: ScopeHandle, scopes: &ScopePool, var_name: &str) -> u32 {
+        let scope = scope.get_declaring_scope(scopes, var_name)
+            .expect("Cannot compile a variable that isn't in the scope");
+        let key = (scope, String::from(var_name));
+        if let Some(slot) = self.locals.get(&key) {
+            return *slot;
+        }
+
+        let slot = self.n_locals;
+        self.locals.insert(key, slot);
+        self.n_locals += 1;
+        slot
+    }
+
+    /// Remembers that the code of a module is emitted, returns false if it already was
+    pub fn add_module(&mut self, scope: ScopeHandle) -> bool {
+        if self.modules.contains(&scope) {
+            return false;
+        }
+
+        self.modules.push(scope);
+        true
+    }
+
+    /// Emits a conversion if a value of type ``from`` has to be stored as a ``to``
+    pub fn emit_conversion(&mut self, from: Option<Type>, to: Option<Type>, loc: Loc) {
+        if let (Some(Type::Int), Some(Type::Float)) = (from, to) {
+            self.emit(Instruction::IntToFloat, loc);
+        }
+    }
+
+    /// Pushes a value the host gave, an array is made from its elements
+    pub fn emit_value(&mut self, value: &Value, loc: Loc) {
+        match value {
+            Value::Int(value) => self.emit_with(Instruction::PushInt, *value, loc),
+            Value::Float(value) => {
+                let index = self.add_constant(Constant::Float(*value));
+                self.emit_with(Instruction::PushConstant, index, loc);
+            },
+            Value::Str(string) => {
+                let index = self.add_constant(Constant::Str(string.clone()));
+                self.emit_with(Instruction::PushConstant, index, loc);
+            },
+            Value::Array { values, .. } => {
+                for value in values {
+                    self.emit_value(value, loc);
+                }
+                self.emit_with(Instruction::NewArray, values.len() as u32, loc);
+            }
+        }
+    }
+
+    pub fn finish(self) -> (Program, DebugInfo) {
+        let program = Program {
+            code: self.code,
+            functions: self.functions,
+            constants: self.constants,
+            natives: self.natives,
+            line_table: self.line_table
+        };
+        let debug_info = DebugInfo {
+            locals: self.locals,
+            scopes: self.scopes
+        };
+        (program, debug_info)
+    }
+}
+
+/// Compiles the top level code into a main function,
+/// the value it returns is left on the stack when the machine halts
+pub fn compile(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool) -> Program {
+    compile_with_debug_info(tree, scope, scopes).0
+}
+
+pub fn compile_with_debug_info(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool)
+        -> (Program, DebugInfo) {
+    compile_with_globals(tree, scope, scopes, &[])
+}
+
+/// Like ``compile_with_debug_info``, but the variables in ``globals`` are set before the code runs.
+/// They have to be declared in ``scope`` with the type of their value before the code is parsed
+pub fn compile_with_globals(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool,
+        globals: &[(String, Value)]) -> (Program, DebugInfo) {
+    compile_main(tree, scope, scopes, globals, Instruction::Return)
+}
+
+/// Like ``compile_with_globals``, but the machine halts at the end of main instead of returning from it,
+/// so the values of the top level variables can still be read from its locals afterwards
+pub fn compile_session(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool,
+        globals: &[(String, Value)]) -> (Program, DebugInfo) {
+    compile_main(tree, scope, scopes, globals, Instruction::Halt)
+}
+
+/// ``end`` is the instruction that ends the main function
+fn compile_main(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool,
+        globals: &[(String, Value)], end: Instruction) -> (Program, DebugInfo) {
+    let start = tree.get_start();
+    let mut compiler = Compiler::new();
+    compiler.emit_with(Instruction::Call, 0u32, start);
+    compiler.emit(Instruction::Halt, start);
+
+    let address = compiler.get_address();
+    compiler.enter_scope(scope);
+    for (name, value) in globals {
+        compiler.emit_value(value, start);
+        let slot = compiler.get_local(scope, scopes, &name[..]);
+        compiler.emit_with(Instruction::StoreLocal, slot, start);
+    }
+    tree.compile(scope, scopes, &mut compiler);
+    compiler.emit(end, start);
+
+    let returns = tree.has_value(scopes);
+    compiler.functions.insert(0, Function {
+        name: String::from("main"),
+        address: address,
+        n_args: 0,
+        n_locals: compiler.n_locals as usize,
+        returns: returns
+    });
+
+    compiler.finish()
+}
+
+/// Compiles code that runs inside of an already running program, with access to its locals.
+/// The code is meant to be loaded at ``base_address``, and halts with its value on the stack.
+/// It's an error to declare new variables, since there are no slots for them
+pub fn compile_in_context(tree: &dyn SyntaxTreeNode, scope: ScopeHandle, scopes: &ScopePool,
+        debug_info: &DebugInfo, base_address: usize) -> Result<Program, &'static str> {
+    let mut compiler = Compiler::new();
+    compiler.base_address = base_address;
+    compiler.locals = debug_info.locals.clone();
+    compiler.n_locals = compiler.locals.len() as u32;
+    let n_locals = compiler.n_locals;
+
+    tree.compile(scope, scopes, &mut compiler);
+    compiler.emit(Instruction::Halt, tree.get_start());
+
+    if compiler.n_locals != n_locals {
+        return Err("Cannot declare new variables here");
+    }
+    Ok(compiler.finish().0)
+}
+
+#[cfg(test)]
+mod tests;
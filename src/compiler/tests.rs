@@ -0,0 +1,62 @@
+//! Golden tests, every ``tests/golden/*.tlang`` file is compiled and disassembled,
+//! and the result has to match the ``.disasm`` file next to it.
+//! Run with ``UPDATE_GOLDEN=1`` to write the ``.disasm`` files instead.
+
+use super::compile;
+use super::super::{ tokenizer, lexer, vm };
+use super::super::needle::Needle;
+use std::path::Path;
+
+fn disassemble_source(code: &str) -> String {
+    let (tokens, errors, meta) = tokenizer::tokenize(code);
+    assert!(errors.is_empty(), "The golden file has tokenizer errors");
+
+    let mut scopes = lexer::ScopePool::new();
+    let root_scope = scopes.create_scope();
+    vm::natives::NativeRegistry::standard().declare(&mut scopes);
+    let tree = match lexer::parse_value(&mut Needle::new(tokens, 0), &meta, root_scope, &mut scopes) {
+        Ok(tree) => tree,
+        Err(_) => panic!("The golden file has parser errors")
+    };
+
+    let program = compile(&*tree, root_scope, &scopes);
+    if let Err(error) = vm::verifier::verify(&program) {
+        panic!("The compiled golden file doesn't verify: {}", error);
+    }
+    vm::disassembler::disassemble(&program)
+}
+
+#[test]
+fn golden_files() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let update = std::env::var("UPDATE_GOLDEN").is_ok();
+    let mut n_files = 0;
+    let mut failed = Vec::new();
+
+    let mut paths: Vec<_> = std::fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "tlang"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        n_files += 1;
+        let code = std::fs::read_to_string(&path).unwrap();
+        let disassembly = disassemble_source(&code);
+        let golden_path = path.with_extension("disasm");
+
+        if update {
+            std::fs::write(&golden_path, &disassembly).unwrap();
+            continue;
+        }
+
+        let expected = std::fs::read_to_string(&golden_path).unwrap_or_default();
+        if expected != disassembly {
+            println!("{} doesn't match, got:\n{}", path.display(), disassembly);
+            failed.push(path);
+        }
+    }
+
+    assert!(n_files > 0, "There are no golden files in {}", directory.display());
+    assert!(failed.is_empty(), "{} golden files don't match", failed.len());
+}
@@ -0,0 +1,295 @@
+//! Just enough JSON for the debug adapter protocol
+
+use std::fmt::Write;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// Keeps the order of the members, so messages are written the way they were built
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (String::from(key), value)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(string) => Some(&string[..]),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(number) if number.fract() == 0.0 => Some(*number as i64),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(&values[..]),
+            _ => None
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { chars: text.chars().collect(), index: 0 };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.index < parser.chars.len() {
+            return Err(format!("Unexpected '{}' after the value", parser.chars[parser.index]));
+        }
+        Ok(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json { Json::Bool(value) }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Json { Json::Number(value as f64) }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json { Json::Number(value as f64) }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json { Json::Str(String::from(value)) }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json { Json::Str(value) }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json { Json::Array(values) }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, string: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?
+        }
+    }
+    f.write_char('"')
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) => {
+                if number.fract() == 0.0 && number.abs() < 1e15 {
+                    write!(f, "{}", *number as i64)
+                }else if number.is_finite() {
+                    write!(f, "{}", number)
+                }else{
+                    write!(f, "null")
+                }
+            },
+            Json::Str(string) => write_string(f, string),
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 { f.write_char(',')?; }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            },
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 { f.write_char(',')?; }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.index < self.chars.len() && self.chars[self.index].is_whitespace() {
+            self.index += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.index += 1;
+                Ok(())
+            },
+            Some(found) => Err(format!("Expected '{}', found '{}'", c, found)),
+            None => Err(format!("Expected '{}', found the end of the text", c))
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for c in keyword.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.parse_keyword("null", Json::Null),
+            Some('t') => self.parse_keyword("true", Json::Bool(true)),
+            Some('f') => self.parse_keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::Str(self.parse_string()?)),
+            Some('[') => {
+                self.index += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.index += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    if self.peek() == Some(',') {
+                        self.index += 1;
+                    }else{
+                        self.expect(']')?;
+                        return Ok(Json::Array(values));
+                    }
+                }
+            },
+            Some('{') => {
+                self.index += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.index += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.parse_string()?;
+                    self.skip_whitespace();
+                    self.expect(':')?;
+                    members.push((key, self.parse_value()?));
+                    self.skip_whitespace();
+                    if self.peek() == Some(',') {
+                        self.index += 1;
+                    }else{
+                        self.expect('}')?;
+                        return Ok(Json::Object(members));
+                    }
+                }
+            },
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+            None => Err(String::from("Expected a value, found the end of the text"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.index;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                self.index += 1;
+            }else{
+                break;
+            }
+        }
+
+        let text: String = self.chars[start..self.index].iter().collect();
+        text.parse::<f64>().map(Json::Number).map_err(|_| format!("Invalid number '{}'", text))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| String::from("Unterminated string"))?;
+            self.index += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| String::from("Unterminated string"))?;
+                    self.index += 1;
+                    string.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let code = self.parse_hex()?;
+                            // Surrogate pairs come as two escapes
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect('\\')?;
+                                self.expect('u')?;
+                                let low = self.parse_hex()?;
+                                let combined = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                                char::from_u32(combined).unwrap_or('\u{fffd}')
+                            }else{
+                                char::from_u32(code).unwrap_or('\u{fffd}')
+                            }
+                        },
+                        other => return Err(format!("Invalid escape '\\{}'", other))
+                    });
+                },
+                c => string.push(c)
+            }
+        }
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        if self.index + 4 > self.chars.len() {
+            return Err(String::from("Unterminated unicode escape"));
+        }
+        let text: String = self.chars[self.index..self.index + 4].iter().collect();
+        self.index += 4;
+        u32::from_str_radix(&text, 16).map_err(|_| format!("Invalid unicode escape '{}'", text))
+    }
+}
@@ -0,0 +1,345 @@
+//! A debug adapter protocol server, so editors can drive the debugger.
+//! Messages are JSON with a ``Content-Length`` header, read from and written to any stream,
+//! ``tlang dap`` uses stdin and stdout.
+
+mod json;
+
+pub use json::Json;
+use super::debugger::{ Debugger, StepMode, StopReason };
+use super::vm::natives::NativeRegistry;
+use std::io::{ BufRead, Write };
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The program only ever has one thread
+const THREAD_ID: i64 = 1;
+/// The only variables reference, the locals of the top frame
+const LOCALS_REFERENCE: i64 = 1;
+/// The largest message body that's read, a bigger ``Content-Length`` is an error instead of an allocation
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+pub struct Server<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    seq: i64,
+    debugger: Option<Debugger>,
+    path: String,
+    stop_on_entry: bool,
+    /// What the program printed, it's sent as output events since stdout is taken by the protocol
+    program_output: Rc<RefCell<String>>
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    pub fn new(input: R, output: W) -> Server<R, W> {
+        Server {
+            input: input,
+            output: output,
+            seq: 1,
+            debugger: None,
+            path: String::new(),
+            stop_on_entry: false,
+            program_output: Rc::new(RefCell::new(String::new()))
+        }
+    }
+
+    /// Handles requests until a disconnect or the end of the input
+    pub fn run(&mut self) -> std::io::Result<()> {
+        while let Some(message) = self.read_message()? {
+            match Json::parse(&message[..]) {
+                Ok(request) => {
+                    if !self.handle(&request)? {
+                        break;
+                    }
+                },
+                Err(error) => self.event("output", Json::object(vec![
+                    ("category", Json::from("stderr")),
+                    ("output", Json::from(format!("Invalid message: {}\n", error)))
+                ]))?
+            }
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> std::io::Result<Option<String>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+
+            let header = header.trim();
+            if header.is_empty() {
+                // Blank lines between messages are skipped
+                if length.is_some() { break; } else { continue; }
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let length = length.unwrap();
+        if length > MAX_MESSAGE_SIZE {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("A message of {} bytes is more than the {} bytes allowed", length, MAX_MESSAGE_SIZE)));
+        }
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    fn send(&mut self, kind: &str, mut members: Vec<(&str, Json)>) -> std::io::Result<()> {
+        members.insert(0, ("seq", Json::from(self.seq)));
+        members.insert(1, ("type", Json::from(kind)));
+        self.seq += 1;
+
+        let body = format!("{}", Json::object(members));
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> std::io::Result<()> {
+        self.send("event", vec![("event", Json::from(event)), ("body", body)])
+    }
+
+    fn flush_program_output(&mut self) -> std::io::Result<()> {
+        let output = std::mem::take(&mut *self.program_output.borrow_mut());
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.event("output", Json::object(vec![
+            ("category", Json::from("stdout")),
+            ("output", Json::from(output))
+        ]))
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> std::io::Result<()> {
+        let mut members = vec![
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", Json::from(result.is_ok())),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null))
+        ];
+        match result {
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", Json::from(message)))
+        }
+        self.send("response", members)
+    }
+
+    /// Handles a single request, returns false if the session is over
+    fn handle(&mut self, request: &Json) -> std::io::Result<bool> {
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Object(Vec::new()));
+
+        match command {
+            "initialize" => {
+                self.respond(request, Ok(Json::object(vec![
+                    ("supportsConfigurationDoneRequest", Json::from(true)),
+                    ("supportsEvaluateForHovers", Json::from(true))
+                ])))?;
+                self.event("initialized", Json::object(Vec::new()))?;
+            },
+            "launch" => {
+                let result = self.launch(&arguments);
+                self.respond(request, result.map(|_| Json::object(Vec::new())))?;
+            },
+            "configurationDone" => {
+                let result = self.with_debugger(|_| Ok(Json::object(Vec::new())));
+                let launched = result.is_ok();
+                self.respond(request, result)?;
+                if launched {
+                    if self.stop_on_entry {
+                        self.resume(StepMode::Into, "entry")?;
+                    }else{
+                        self.resume(StepMode::Continue, "step")?;
+                    }
+                }
+            },
+            "setBreakpoints" => {
+                let result = self.set_breakpoints(&arguments);
+                self.respond(request, result)?;
+            },
+            "threads" => self.respond(request, Ok(Json::object(vec![
+                ("threads", Json::Array(vec![Json::object(vec![
+                    ("id", Json::from(THREAD_ID)),
+                    ("name", Json::from("main"))
+                ])]))
+            ])))?,
+            "stackTrace" => {
+                let result = self.stack_trace();
+                self.respond(request, result)?;
+            },
+            "scopes" => {
+                // Only the variables of the innermost frame can be read
+                let frame = arguments.get("frameId").and_then(Json::as_i64).unwrap_or(0);
+                let scopes = if frame == 0 {
+                    vec![Json::object(vec![
+                        ("name", Json::from("Locals")),
+                        ("variablesReference", Json::from(LOCALS_REFERENCE)),
+                        ("expensive", Json::from(false))
+                    ])]
+                }else{
+                    Vec::new()
+                };
+                self.respond(request, Ok(Json::object(vec![("scopes", Json::Array(scopes))])))?;
+            },
+            "variables" => {
+                let reference = arguments.get("variablesReference").and_then(Json::as_i64);
+                let result = self.with_debugger(|debugger| {
+                    let variables = if reference == Some(LOCALS_REFERENCE) {
+                        debugger.get_variables().into_iter().map(|(name, value)| Json::object(vec![
+                            ("name", Json::from(name)),
+                            ("value", Json::from(value)),
+                            ("variablesReference", Json::from(0i64))
+                        ])).collect()
+                    }else{
+                        Vec::new()
+                    };
+                    Ok(Json::object(vec![("variables", Json::Array(variables))]))
+                });
+                self.respond(request, result)?;
+            },
+            "next" | "stepIn" | "stepOut" | "continue" => {
+                let result = self.with_debugger(|debugger| {
+                    if debugger.is_finished() {
+                        Err(String::from("The program has finished"))
+                    }else{
+                        Ok(Json::object(Vec::new()))
+                    }
+                });
+                let launched = result.is_ok();
+                self.respond(request, result)?;
+                if launched {
+                    match command {
+                        "next" => self.resume(StepMode::Over, "step")?,
+                        "stepIn" => self.resume(StepMode::Into, "step")?,
+                        "stepOut" => self.resume(StepMode::Out, "step")?,
+                        _ => self.resume(StepMode::Continue, "step")?
+                    }
+                }
+            },
+            "evaluate" => {
+                let expression = String::from(arguments.get("expression").and_then(Json::as_str).unwrap_or(""));
+                let result = self.with_debugger(|debugger| {
+                    let value = debugger.evaluate(&expression[..])?;
+                    Ok(Json::object(vec![
+                        ("result", Json::from(value)),
+                        ("variablesReference", Json::from(0i64))
+                    ]))
+                });
+                self.flush_program_output()?;
+                self.respond(request, result)?;
+            },
+            "disconnect" => {
+                self.respond(request, Ok(Json::object(Vec::new())))?;
+                return Ok(false);
+            },
+            _ => self.respond(request, Err(format!("Unknown command '{}'", command)))?
+        }
+
+        Ok(true)
+    }
+
+    fn with_debugger(&mut self, f: impl FnOnce(&mut Debugger) -> Result<Json, String>) -> Result<Json, String> {
+        match &mut self.debugger {
+            Some(debugger) => f(debugger),
+            None => Err(String::from("No program has been launched"))
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<(), String> {
+        let path = arguments.get("program").and_then(Json::as_str)
+            .ok_or_else(|| String::from("Expected the path of the program"))?;
+        let code = std::fs::read_to_string(path)
+            .map_err(|error| format!("Error opening file! {}", error))?;
+
+        let program_output = self.program_output.clone();
+        let natives = NativeRegistry::with_output(move |text| {
+            let mut program_output = program_output.borrow_mut();
+            program_output.push_str(text);
+            program_output.push('\n');
+        });
+        self.debugger = Some(Debugger::new(&code, Rc::new(natives))?);
+        self.path = String::from(path);
+        self.stop_on_entry = arguments.get("stopOnEntry").and_then(Json::as_bool).unwrap_or(false);
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let lines: Vec<i64> = arguments.get("breakpoints").and_then(Json::as_array).unwrap_or(&[])
+            .iter()
+            .filter_map(|breakpoint| breakpoint.get("line").and_then(Json::as_i64))
+            .collect();
+
+        self.with_debugger(|debugger| {
+            debugger.clear_breakpoints();
+            let breakpoints = lines.into_iter().map(|line| {
+                let verified = line > 0 && debugger.has_code(line as usize - 1);
+                if verified {
+                    debugger.set_breakpoint(line as usize - 1);
+                }
+                Json::object(vec![
+                    ("verified", Json::from(verified)),
+                    ("line", Json::from(line))
+                ])
+            }).collect();
+            Ok(Json::object(vec![("breakpoints", Json::Array(breakpoints))]))
+        })
+    }
+
+    fn stack_trace(&mut self) -> Result<Json, String> {
+        let path = self.path.clone();
+        self.with_debugger(|debugger| {
+            let frames: Vec<Json> = debugger.get_machine().stack_trace().into_iter().enumerate()
+                .map(|(i, entry)| {
+                    let (line, column) = entry.loc.map(|loc| (loc.line + 1, loc.character + 1)).unwrap_or((0, 0));
+                    Json::object(vec![
+                        ("id", Json::from(i)),
+                        ("name", Json::from(entry.function)),
+                        ("source", Json::object(vec![("path", Json::from(&path[..]))])),
+                        ("line", Json::from(line)),
+                        ("column", Json::from(column))
+                    ])
+                })
+                .collect();
+            let n_frames = frames.len();
+            Ok(Json::object(vec![
+                ("stackFrames", Json::Array(frames)),
+                ("totalFrames", Json::from(n_frames))
+            ]))
+        })
+    }
+
+    /// Runs the program and tells the client why it stopped
+    fn resume(&mut self, mode: StepMode, reason: &str) -> std::io::Result<()> {
+        let stop = match &mut self.debugger {
+            Some(debugger) => debugger.resume(mode),
+            None => return Ok(())
+        };
+        self.flush_program_output()?;
+
+        let stopped = |reason: &str| Json::object(vec![
+            ("reason", Json::from(reason)),
+            ("threadId", Json::from(THREAD_ID))
+        ]);
+        match stop {
+            StopReason::Step => self.event("stopped", stopped(reason)),
+            StopReason::Breakpoint(_) => self.event("stopped", stopped("breakpoint")),
+            StopReason::Finished => {
+                self.event("exited", Json::object(vec![("exitCode", Json::from(0i64))]))?;
+                self.event("terminated", Json::object(Vec::new()))
+            },
+            StopReason::Error(error) => {
+                self.event("output", Json::object(vec![
+                    ("category", Json::from("stderr")),
+                    ("output", Json::from(format!("{}\n", error)))
+                ]))?;
+                self.event("exited", Json::object(vec![("exitCode", Json::from(1i64))]))?;
+                self.event("terminated", Json::object(Vec::new()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+//! Transcript tests, every ``tests/dap/*.in`` file has one request per line,
+//! and the messages the server sends back have to match the ``.out`` file next to it, one per line.
+//! ``$DIR`` stands for the ``tests/dap`` directory, so transcripts can launch the programs there.
+//! Run with ``UPDATE_GOLDEN=1`` to write the ``.out`` files instead.
+
+use super::{ Server, Json };
+use std::path::Path;
+
+fn run_transcript(requests: &str, directory: &str) -> String {
+    let mut input = String::new();
+    for line in requests.lines().filter(|line| !line.trim().is_empty()) {
+        let line = line.replace("$DIR", directory);
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{}", line.len(), line));
+    }
+
+    let mut output = Vec::new();
+    Server::new(input.as_bytes(), &mut output).run().unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // Take the framing apart again, one message per line
+    let mut transcript = String::new();
+    let mut rest = &output[..];
+    while !rest.is_empty() {
+        let header_end = rest.find("\r\n\r\n").expect("A message without a header");
+        let length: usize = rest[..header_end].trim_start_matches("Content-Length:").trim().parse().unwrap();
+        let body = &rest[header_end + 4..header_end + 4 + length];
+        Json::parse(body).expect("The server sent invalid json");
+        transcript.push_str(&body.replace(directory, "$DIR"));
+        transcript.push('\n');
+        rest = &rest[header_end + 4 + length..];
+    }
+    transcript
+}
+
+#[test]
+fn transcripts() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("dap");
+    let directory_name = directory.to_str().unwrap();
+    let update = std::env::var("UPDATE_GOLDEN").is_ok();
+    let mut n_files = 0;
+    let mut failed = Vec::new();
+
+    let mut paths: Vec<_> = std::fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "in"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        n_files += 1;
+        let requests = std::fs::read_to_string(&path).unwrap();
+        let transcript = run_transcript(&requests, directory_name);
+        let golden_path = path.with_extension("out");
+
+        if update {
+            std::fs::write(&golden_path, &transcript).unwrap();
+            continue;
+        }
+
+        let expected = std::fs::read_to_string(&golden_path).unwrap_or_default();
+        if expected != transcript {
+            println!("{} doesn't match, got:\n{}", path.display(), transcript);
+            failed.push(path);
+        }
+    }
+
+    assert!(n_files > 0, "There are no transcripts in {}", directory.display());
+    assert!(failed.is_empty(), "{} transcripts don't match", failed.len());
+}
+
+#[test]
+fn json_round_trip() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"quote \" and \\ and \n","c":{}}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(format!("{}", value), text);
+    assert_eq!(value.get("a").and_then(Json::as_array).map(|values| values.len()), Some(4));
+    assert_eq!(Json::parse(r#""å😀""#).unwrap(), Json::from("å😀"));
+    assert!(Json::parse("{\"a\":1,}").is_err());
+    assert!(Json::parse("[1] 2").is_err());
+}
+
+#[test]
+fn refuses_huge_messages() {
+    let input = "Content-Length: 1000000000000\r\n\r\n{}";
+    let mut output = Vec::new();
+    let error = Server::new(input.as_bytes(), &mut output).run().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(output.is_empty());
+}
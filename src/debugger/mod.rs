@@ -0,0 +1,400 @@
+use super::{ tokenizer, lexer, compiler, vm };
+use super::needle::{ Needle, Loc };
+use lexer::{ ScopePool, ScopeHandle, Type };
+use compiler::DebugInfo;
+use vm::{ VirtualMachine, ByteVec, Instruction };
+use vm::natives::NativeRegistry;
+use std::rc::Rc;
+use std::collections::HashSet;
+
+const HELP: &str = "\
+Commands:
+    break <line>, b <line>   Stop when the line is reached
+    delete <line>            Remove a breakpoint
+    step, s                  Run until the next line, going into calls
+    next, n                  Run until the next line, stepping over calls
+    out, o                   Run until the current function returns
+    continue, c              Run until a breakpoint is hit
+    print <name>, p <name>   Print a variable
+    locals                   Print all the variables that are in scope
+    watch <expression>       Print the expression every time the program stops
+    unwatch <index>          Remove a watch expression
+    where, bt                Print the stack trace
+    list, l                  Print the current line
+    help, h                  Print this
+    quit, q                  Stop debugging";
+
+/// Where the program was before a step, to know when a step is done
+struct Position {
+    line: Option<usize>,
+    depth: usize
+}
+
+pub enum StepMode {
+    Into,
+    Over,
+    Out,
+    Continue
+}
+
+/// Why the program stopped running
+pub enum StopReason {
+    Step,
+    /// The line of the breakpoint, starting at 0
+    Breakpoint(usize),
+    Finished,
+    Error(String)
+}
+
+pub struct Debugger {
+    source_lines: Vec<String>,
+    scopes: ScopePool,
+    debug_info: DebugInfo,
+    machine: VirtualMachine,
+    /// The code of the program, to see which local the next instruction stores to
+    code: ByteVec,
+    /// The local slots that have been stored to, the others don't have a value yet
+    initialised: HashSet<u32>,
+    breakpoints: HashSet<usize>,
+    finished: bool
+}
+
+impl Debugger {
+    /// ``natives`` are the native functions the code can call
+    pub fn new(code: &str, natives: Rc<NativeRegistry>) -> Result<Debugger, String> {
+        let (tokens, errors, meta) = tokenizer::tokenize(code);
+        if let Some(error) = errors.first() {
+            return Err(format!("({}): {}", meta.index_to_loc(error.loc), error.msg));
+        }
+
+        let mut scopes = ScopePool::new();
+        let root_scope = scopes.create_scope();
+        natives.declare(&mut scopes);
+        let tree = lexer::parse_program(&mut Needle::new(tokens, 0), &meta, root_scope, &mut scopes)
+            .map_err(|error| format!("({}): The program has errors in it", error.get_start()))?;
+
+        let (program, debug_info) = compiler::compile_with_debug_info(&*tree, root_scope, &scopes);
+        let bytecode = program.code.clone();
+        let mut machine = VirtualMachine::new(program);
+        machine.link_natives(natives).expect("The natives the code was parsed with are missing");
+        Ok(Debugger {
+            source_lines: code.lines().map(String::from).collect(),
+            scopes: scopes,
+            debug_info: debug_info,
+            machine: machine,
+            code: bytecode,
+            initialised: HashSet::new(),
+            breakpoints: HashSet::new(),
+            finished: false
+        })
+    }
+
+    pub fn get_loc(&self) -> Option<Loc> {
+        self.machine.get_loc(self.machine.get_instruction_pointer())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn get_machine(&self) -> &VirtualMachine {
+        &self.machine
+    }
+
+    fn get_position(&self) -> Position {
+        Position {
+            line: self.get_loc().map(|loc| loc.line),
+            depth: self.machine.get_frames().len()
+        }
+    }
+
+    fn get_scope(&self) -> Option<ScopeHandle> {
+        self.debug_info.get_scope(self.machine.get_instruction_pointer())
+    }
+
+    /// The local slot the next instruction stores to, if it's a ``StoreLocal``
+    fn get_stored_local(&self) -> Option<u32> {
+        let address = self.machine.get_instruction_pointer();
+        if self.code.read::<u8>(address)? != Instruction::StoreLocal as u8 {
+            return None;
+        }
+        self.code.read::<u32>(address + 1)
+    }
+
+    /// Lines start at 0
+    pub fn set_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) {
+        self.breakpoints.remove(&line);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// If any code comes from the line, so a breakpoint there can be hit
+    pub fn has_code(&self, line: usize) -> bool {
+        self.machine.get_line_table().entries().iter().any(|(_, loc)| loc.line == line)
+    }
+
+    /// Runs until the step is done, a breakpoint is hit or the program halts
+    pub fn resume(&mut self, mode: StepMode) -> StopReason {
+        if self.finished {
+            return StopReason::Finished;
+        }
+
+        let start = self.get_position();
+        loop {
+            let before = self.get_position();
+            let stored = self.get_stored_local();
+            let result = self.machine.step();
+            if let (Ok(_), Some(slot)) = (&result, stored) {
+                self.initialised.insert(slot);
+            }
+            match result {
+                Ok(true) => {},
+                Ok(false) => {
+                    self.finished = true;
+                    return StopReason::Finished;
+                },
+                Err(error) => {
+                    self.finished = true;
+                    return StopReason::Error(format!("Runtime error {}", error));
+                }
+            }
+
+            let now = self.get_position();
+            let new_line = now.line.is_some() && (now.line != before.line || now.depth != before.depth);
+            if new_line && self.breakpoints.contains(&now.line.unwrap()) {
+                return StopReason::Breakpoint(now.line.unwrap());
+            }
+
+            let done = match mode {
+                StepMode::Into => new_line && (now.line != start.line || now.depth != start.depth),
+                StepMode::Over => new_line
+                    && (now.depth < start.depth || now.depth == start.depth && now.line != start.line),
+                StepMode::Out => now.depth < start.depth && now.line.is_some(),
+                StepMode::Continue => false
+            };
+            if done {
+                return StopReason::Step;
+            }
+        }
+    }
+
+    pub fn get_source_line(&self, line: usize) -> &str {
+        self.source_lines.get(line).map(|line| &line[..]).unwrap_or("")
+    }
+
+    fn format_value(&self, value: u64, value_type: Option<Type>) -> String {
+        match value_type {
+            Some(Type::Int) => format!("{}", value as i64),
+            Some(Type::Float) => format!("{}", f64::from_bits(value)),
+            Some(Type::Str) => format!("{:?}", self.machine.get_string(value).unwrap_or_default()),
+            Some(other) => format!("<{}>", other),
+            None => String::from("undef")
+        }
+    }
+
+    /// The value of a variable in the current scope, formatted by its type
+    pub fn get_variable(&self, name: &str) -> Result<String, String> {
+        let scope = self.get_scope().ok_or_else(|| String::from("There are no variables here"))?;
+        let slot = self.debug_info.get_local(scope, &self.scopes, name)
+            .ok_or_else(|| format!("There is no variable '{}' here", name))?;
+        if !self.initialised.contains(&slot) {
+            return Err(format!("'{}' doesn't have a value yet", name));
+        }
+        let value_type = scope.get(&self.scopes, name).and_then(|scheme| self.scopes.types.get_type(&scheme.term));
+
+        match self.machine.get_local(slot) {
+            Some(value) => Ok(self.format_value(value, value_type)),
+            None => Err(format!("'{}' doesn't have a value yet", name))
+        }
+    }
+
+    /// All the variables in the current scope that have a value, sorted by name
+    pub fn get_variables(&self) -> Vec<(String, String)> {
+        let scope = match self.get_scope() {
+            Some(scope) => scope,
+            None => return Vec::new()
+        };
+
+        let mut names: Vec<&String> = self.debug_info.locals.keys()
+            .map(|(_, name)| name)
+            .filter(|name| scope.get(&self.scopes, &name[..]).is_some())
+            .collect();
+        names.sort();
+        names.dedup();
+
+        names.into_iter()
+            .filter_map(|name| self.get_variable(&name[..]).ok().map(|value| (name.clone(), value)))
+            .collect()
+    }
+
+    /// Compiles the expression in the current scope and runs it on top of the program
+    pub fn evaluate(&mut self, expression: &str) -> Result<String, String> {
+        let scope = self.get_scope().ok_or_else(|| String::from("There are no variables here"))?;
+        let (tokens, errors, meta) = tokenizer::tokenize(expression);
+        if let Some(error) = errors.first() {
+            return Err(String::from(error.msg));
+        }
+
+        // Parsed in a copy of the scopes, so the expression can't change the variables or types the program has
+        let mut scopes = self.scopes.clone();
+        let expression_scope = scope.create_subscope(&mut scopes);
+        let tree = lexer::parse_program(&mut Needle::new(tokens, 0), &meta, expression_scope, &mut scopes)
+            .map_err(|_| String::from("Invalid expression"))?;
+
+        let program = compiler::compile_in_context(&*tree, expression_scope, &scopes,
+            &self.debug_info, self.machine.get_heap_end())?;
+        let value_type = tree.get_type(&scopes);
+
+        match self.machine.evaluate(&program) {
+            Ok(Some(value)) => Ok(self.format_value(value, value_type)),
+            Ok(None) if value_type == Some(Type::Nil) => Ok(String::from("nil")),
+            Ok(None) => Ok(String::from("undef")),
+            Err(error) => Err(format!("Runtime error {}", error))
+        }
+    }
+}
+
+/// The command prompt around a ``Debugger``
+pub struct Prompt {
+    debugger: Debugger,
+    watches: Vec<String>
+}
+
+impl Prompt {
+    pub fn new(debugger: Debugger) -> Prompt {
+        Prompt {
+            debugger: debugger,
+            watches: Vec::new()
+        }
+    }
+
+    fn run(&mut self, mode: StepMode) {
+        if self.debugger.is_finished() {
+            println!("The program has finished");
+            return;
+        }
+
+        match self.debugger.resume(mode) {
+            StopReason::Step => {},
+            StopReason::Breakpoint(line) => println!("Breakpoint at line {}", line + 1),
+            StopReason::Finished => return println!("The program finished"),
+            StopReason::Error(error) => return println!("{}", error)
+        }
+
+        self.print_location();
+        self.print_watches();
+    }
+
+    fn print_location(&self) {
+        match self.debugger.get_loc() {
+            Some(loc) => println!("{:>4} | {}", loc.line + 1, self.debugger.get_source_line(loc.line)),
+            None => println!("No source location at {:04x}", self.debugger.get_machine().get_instruction_pointer())
+        }
+    }
+
+    fn print_watch(&mut self, index: usize) {
+        let expression = self.watches[index].clone();
+        match self.debugger.evaluate(&expression[..]) {
+            Ok(value) => println!("[{}] {} = {}", index, expression, value),
+            Err(error) => println!("[{}] {}: {}", index, expression, error)
+        }
+    }
+
+    fn print_watches(&mut self) {
+        for i in 0..self.watches.len() {
+            self.print_watch(i);
+        }
+    }
+
+    fn print_trace(&self) {
+        for entry in self.debugger.get_machine().stack_trace() {
+            match entry.loc {
+                Some(loc) => println!("in '{}' at ({})", entry.function, loc),
+                None => println!("in '{}'", entry.function)
+            }
+        }
+    }
+
+    /// Runs a single command, returns false if the debugger should quit
+    pub fn command(&mut self, input: &str) -> bool {
+        let input = input.trim();
+        let (command, argument) = match input.find(' ') {
+            Some(index) => (&input[..index], input[index..].trim()),
+            None => (input, "")
+        };
+
+        match command {
+            "break" | "b" | "delete" => match argument.parse::<usize>() {
+                Ok(line) if line > 0 => {
+                    if command == "delete" {
+                        self.debugger.remove_breakpoint(line - 1);
+                    }else{
+                        self.debugger.set_breakpoint(line - 1);
+                    }
+                },
+                _ => println!("Expected a line number")
+            },
+            "step" | "s" => self.run(StepMode::Into),
+            "next" | "n" => self.run(StepMode::Over),
+            "out" | "o" => self.run(StepMode::Out),
+            "continue" | "c" => self.run(StepMode::Continue),
+            "print" | "p" => match self.debugger.get_variable(argument) {
+                Ok(value) => println!("{} = {}", argument, value),
+                Err(error) => println!("{}", error)
+            },
+            "locals" => {
+                for (name, value) in self.debugger.get_variables() {
+                    println!("{} = {}", name, value);
+                }
+            },
+            "watch" => {
+                if argument.is_empty() {
+                    self.print_watches();
+                }else{
+                    self.watches.push(String::from(argument));
+                    self.print_watch(self.watches.len() - 1);
+                }
+            },
+            "unwatch" => match argument.parse::<usize>() {
+                Ok(index) if index < self.watches.len() => { self.watches.remove(index); },
+                _ => println!("Expected the index of a watch expression")
+            },
+            "where" | "bt" => self.print_trace(),
+            "list" | "l" => self.print_location(),
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" | "exit" => return false,
+            "" => {},
+            _ => println!("Unknown command '{}', 'help' lists the commands", command)
+        }
+
+        true
+    }
+
+    /// Reads commands until quit or the end of the input
+    pub fn run_prompt(&mut self) {
+        println!("-- TROLLEDLANG DEBUGGER --\n'help' for a list of commands\n");
+
+        // Run to the first line of the program, so there's something to look at
+        self.run(StepMode::Into);
+        loop {
+            print!("(debug) ");
+            use std::io::Write;
+            std::io::stdout().flush().ok();
+
+            let mut input = String::new();
+            match std::io::stdin().read_line(&mut input) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => if !self.command(&input[..]) { break; }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,53 @@
+use super::{ Debugger, StepMode, StopReason };
+use super::super::vm::natives::NativeRegistry;
+use std::rc::Rc;
+
+const PROGRAM: &str = "(\n    x = 1;\n    y = \"text\";\n    x = 2;\n    y\n)";
+
+fn debugger(code: &str) -> Debugger {
+    Debugger::new(code, Rc::new(NativeRegistry::with_output(|_| {}))).ok().unwrap()
+}
+
+/// Steps into the next line and gives back which one it is
+fn step(debugger: &mut Debugger) -> usize {
+    assert!(matches!(debugger.resume(StepMode::Into), StopReason::Step));
+    debugger.get_loc().unwrap().line
+}
+
+fn variables(debugger: &Debugger) -> Vec<String> {
+    debugger.get_variables().into_iter().map(|(name, value)| format!("{} = {}", name, value)).collect()
+}
+
+#[test]
+fn only_shows_variables_with_values() {
+    let mut debugger = debugger(PROGRAM);
+    assert_eq!(step(&mut debugger), 1);
+    assert!(variables(&debugger).is_empty());
+    assert_eq!(debugger.get_variable("y"), Err(String::from("'y' doesn't have a value yet")));
+
+    assert_eq!(step(&mut debugger), 2);
+    assert_eq!(variables(&debugger), vec!["x = 1"]);
+    assert_eq!(debugger.get_variable("y"), Err(String::from("'y' doesn't have a value yet")));
+
+    assert_eq!(step(&mut debugger), 3);
+    assert_eq!(variables(&debugger), vec!["x = 1", "y = \"text\""]);
+
+    assert_eq!(step(&mut debugger), 4);
+    assert_eq!(debugger.get_variable("x"), Ok(String::from("2")));
+    assert_eq!(debugger.get_variable("z"), Err(String::from("There is no variable 'z' here")));
+}
+
+#[test]
+fn stops_at_breakpoints() {
+    let mut debugger = debugger(PROGRAM);
+    assert!(debugger.has_code(3));
+    assert!(!debugger.has_code(5));
+    debugger.set_breakpoint(3);
+    assert!(matches!(debugger.resume(StepMode::Continue), StopReason::Breakpoint(3)));
+    assert_eq!(debugger.evaluate("x"), Ok(String::from("1")));
+    // The variables of an expression are gone afterwards
+    assert_eq!(debugger.evaluate("(w = 5; w)"), Err(String::from("Cannot declare new variables here")));
+    assert_eq!(debugger.get_variable("w"), Err(String::from("There is no variable 'w' here")));
+    assert!(matches!(debugger.resume(StepMode::Continue), StopReason::Finished));
+    assert!(debugger.is_finished());
+}
@@ -0,0 +1,304 @@
+use super::{ tokenizer, lexer, compiler, vm };
+use super::needle::{ Needle, Loc };
+use lexer::{ ScopePool, ScopeHandle, SyntaxTreeNode, ParserError, Type, Term, Scheme };
+use vm::{ Program, VirtualMachine, Limits, RuntimeError };
+use vm::natives::{ NativeRegistry, NativeImport, NativeResult, Value };
+use vm::conversion::{ IntoValue, FromValue, IntoNative, ConversionError };
+use std::rc::Rc;
+use std::path::{ Path, PathBuf };
+
+mod session;
+pub use session::{ Session, Evaluation };
+
+pub enum Error {
+    /// The characters that couldn't be turned into tokens, and why
+    Tokenize(Vec<(Loc, &'static str)>),
+    Parse(Box<dyn ParserError>),
+    /// The code parses, but values have the wrong types
+    Type(Box<dyn ParserError>),
+    /// The program calls a native function that isn't registered
+    Link(NativeImport),
+    Runtime(RuntimeError),
+    /// The code resulted in a value that isn't what the host asked for
+    Conversion(ConversionError)
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Tokenize(errors) => {
+                for (i, (loc, message)) in errors.iter().enumerate() {
+                    if i > 0 { writeln!(f)?; }
+                    write!(f, "({}): {}", loc, message)?;
+                }
+                Ok(())
+            },
+            Error::Parse(error) => write!(f, "({}): The code has errors in it", error.get_start()),
+            Error::Type(error) => write!(f, "({}): The code has type errors in it", error.get_start()),
+            Error::Link(import) => write!(f, "The program calls '{}', which doesn't exist", import),
+            Error::Runtime(error) => write!(f, "Runtime error {}", error),
+            Error::Conversion(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+/// Code that has been parsed but not compiled, for looking at the syntax tree and the scopes
+pub struct Parsed {
+    pub scopes: ScopePool,
+    pub root_scope: ScopeHandle,
+    pub tree: Box<dyn SyntaxTreeNode>
+}
+
+impl Parsed {
+    /// The type of the value the code results in, if it results in one
+    pub fn get_result_type(&self) -> Option<Type> {
+        self.tree.get_type(&self.scopes)
+    }
+
+    /// The files of the modules that were parsed, the ones the code imports directly or through other modules
+    pub fn get_module_paths(&self) -> Vec<PathBuf> {
+        self.scopes.modules.get_paths()
+    }
+}
+
+/// A compiled program, ready to be run any number of times
+pub struct Script {
+    pub program: Program,
+    /// Unknown for programs that were loaded from bytecode, then the result is thrown away
+    pub result_type: Option<Type>
+}
+
+impl Script {
+    pub fn from_program(program: Program) -> Script {
+        Script {
+            program: program,
+            result_type: None
+        }
+    }
+
+    /// Takes the value that a machine that ran the script left on the stack
+    pub fn get_result(&self, machine: &mut VirtualMachine) -> Option<Value> {
+        let result_type = self.result_type.clone().filter(Type::is_value)?;
+        let slot = machine.pop_result::<u64>()?;
+        machine.get_value(slot, result_type)
+    }
+}
+
+/// Runs TrolledLang code for a host program.
+/// The host functions, globals and limits set on it apply to everything it compiles and runs afterwards
+#[derive(Clone)]
+pub struct Engine {
+    natives: Rc<NativeRegistry>,
+    globals: Vec<(String, Value)>,
+    /// Struct types the code can use without declaring them
+    structs: Vec<Type>,
+    /// The file the code comes from, imports are relative to it
+    source_path: Option<PathBuf>,
+    /// Where imports look for modules that aren't next to the importing file
+    module_paths: Vec<PathBuf>,
+    pub limits: Limits
+}
+
+impl Engine {
+    /// An engine with the standard native functions, like ``print``
+    pub fn new() -> Engine {
+        Engine::with_natives(NativeRegistry::standard())
+    }
+
+    pub fn with_natives(natives: NativeRegistry) -> Engine {
+        Engine {
+            natives: Rc::new(natives),
+            globals: Vec::new(),
+            structs: Vec::new(),
+            source_path: None,
+            module_paths: Vec::new(),
+            limits: Limits::default()
+        }
+    }
+
+    /// Makes a Rust function callable from the code by name. ``args`` and ``returns`` are checked
+    /// when the code is compiled, so the function always gets the arguments it asks for.
+    /// Machines that were already created keep the functions they were linked with
+    pub fn register<F>(&mut self, name: &str, args: Vec<Type>, returns: Option<Type>, function: F)
+            where F: Fn(&[Value]) -> NativeResult + 'static {
+        Rc::make_mut(&mut self.natives).register(name, args, returns, function);
+    }
+
+    /// Makes a Rust function callable from the code by name, its argument and return types
+    /// become the signature, see ``IntoNative``
+    pub fn register_fn<Args, F: IntoNative<Args>>(&mut self, name: &str, function: F) {
+        Rc::make_mut(&mut self.natives).register_fn(name, function);
+    }
+
+    /// Gives the code the arguments of the program, ``arg_count[]`` is how many there are
+    /// and ``arg[i]`` is one of them, starting at 0
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let count = args.len() as i64;
+        self.register_fn("arg_count", move || count);
+        self.register_fn("arg", move |index: i64| {
+            let arg = if index < 0 { None } else { args.get(index as usize) };
+            arg.cloned().ok_or_else(|| format!("There is no argument {}, there are {}", index, count))
+        });
+    }
+
+    pub fn get_natives(&self) -> Rc<NativeRegistry> {
+        self.natives.clone()
+    }
+
+    /// Makes a variable with the value available to the code, replacing it if it's already set
+    pub fn set_global<T: IntoValue>(&mut self, name: &str, value: T) {
+        self.globals.retain(|(global, _)| global != name);
+        self.globals.push((String::from(name), value.into_value()));
+    }
+
+    pub fn get_globals(&self) -> &[(String, Value)] {
+        &self.globals[..]
+    }
+
+    /// Makes a struct type available to the code by its name, replacing one with the same name.
+    /// Types that aren't structs are ignored
+    pub fn declare_struct(&mut self, struct_type: Type) {
+        if let Type::Struct { name, .. } = &struct_type {
+            self.structs.retain(|declared| !matches!(declared, Type::Struct { name: other, .. } if other == name));
+            self.structs.push(struct_type);
+        }
+    }
+
+    pub fn get_structs(&self) -> &[Type] {
+        &self.structs[..]
+    }
+
+    /// Sets the file the code comes from, ``import`` looks for modules next to it.
+    /// Without one, they're looked for in the current directory
+    pub fn set_source_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.source_path = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Adds a directory for ``import`` to look for modules in,
+    /// when they aren't next to the file that imports them
+    pub fn add_module_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.module_paths.push(path.as_ref().to_path_buf());
+    }
+
+    /// The scopes code is parsed in, with the natives, the structs and the globals declared in the root scope
+    pub fn create_scopes(&self) -> (ScopePool, ScopeHandle) {
+        let mut scopes = ScopePool::new();
+        if let Some(path) = &self.source_path {
+            scopes.modules.set_source(path);
+        }
+        for path in self.module_paths.iter() {
+            scopes.modules.add_search_path(path);
+        }
+        let root_scope = scopes.create_scope();
+        self.natives.declare(&mut scopes);
+        for struct_type in self.structs.iter() {
+            if let Type::Struct { name, .. } = struct_type {
+                root_scope.insert_struct(&mut scopes, &name[..], struct_type.clone());
+            }
+        }
+        for (name, value) in self.globals.iter() {
+            root_scope.insert(&mut scopes, &name[..], Scheme::mono(Term::from(value.get_type())));
+        }
+        (scopes, root_scope)
+    }
+
+    pub fn parse(&self, code: &str) -> Result<Parsed, Error> {
+        let (tokens, errors, meta) = tokenizer::tokenize(code);
+        if !errors.is_empty() {
+            return Err(Error::Tokenize(errors.iter()
+                .map(|error| (meta.index_to_loc(error.loc), error.msg))
+                .collect()));
+        }
+
+        let (mut scopes, root_scope) = self.create_scopes();
+        let tree = lexer::parse_program(&mut Needle::new(tokens, 0), &meta, root_scope, &mut scopes)
+            .map_err(|error| if error.is_type_error() { Error::Type(error) } else { Error::Parse(error) })?;
+        Ok(Parsed {
+            scopes: scopes,
+            root_scope: root_scope,
+            tree: tree
+        })
+    }
+
+    /// Parses a module file on its own. The module is imported by the tree, so compiling it
+    /// gives a program that evaluates the ``def``s
+    pub fn parse_module<P: AsRef<Path>>(&self, path: P) -> Result<Parsed, Error> {
+        let (mut scopes, root_scope) = self.create_scopes();
+        let tree = lexer::parse_module_file(path.as_ref(), root_scope, &mut scopes)
+            .map_err(|error| if error.is_type_error() { Error::Type(error) } else { Error::Parse(error) })?;
+        Ok(Parsed {
+            scopes: scopes,
+            root_scope: root_scope,
+            tree: tree
+        })
+    }
+
+    /// Whether the code ends before a block is closed, so more lines could complete it
+    pub fn is_incomplete(&self, code: &str) -> bool {
+        let (tokens, _, meta) = tokenizer::tokenize(code);
+        let (mut scopes, root_scope) = self.create_scopes();
+        match lexer::parse_program(&mut Needle::new(tokens, 0), &meta, root_scope, &mut scopes) {
+            Ok(_) => false,
+            Err(error) => error.is_incomplete(meta.get_end())
+        }
+    }
+
+    /// The files of the modules the code imports, directly or through other modules.
+    /// It's the ones that could be read, even if the code or the modules have errors
+    pub fn find_imports(&self, code: &str) -> Vec<PathBuf> {
+        let (tokens, _, meta) = tokenizer::tokenize(code);
+        let (mut scopes, root_scope) = self.create_scopes();
+        lexer::parse_program(&mut Needle::new(tokens, 0), &meta, root_scope, &mut scopes).ok();
+        scopes.modules.get_paths()
+    }
+
+    pub fn compile_parsed(&self, parsed: &Parsed) -> Script {
+        let (program, _) = compiler::compile_with_globals(&*parsed.tree, parsed.root_scope,
+            &parsed.scopes, &self.globals[..]);
+        Script {
+            program: program,
+            result_type: parsed.get_result_type()
+        }
+    }
+
+    pub fn compile(&self, code: &str) -> Result<Script, Error> {
+        let parsed = self.parse(code)?;
+        Ok(self.compile_parsed(&parsed))
+    }
+
+    /// A machine that's ready to run the script, with the natives linked and the limits set
+    pub fn create_machine(&self, script: &Script) -> Result<VirtualMachine, Error> {
+        let mut machine = VirtualMachine::new(script.program.clone());
+        machine.link_natives(self.natives.clone()).map_err(Error::Link)?;
+        machine.limits = self.limits.clone();
+        Ok(machine)
+    }
+
+    /// Runs a script until it halts, and gives back the value it results in
+    pub fn run(&self, script: &Script) -> Result<Option<Value>, Error> {
+        let mut machine = self.create_machine(script)?;
+        machine.run().map_err(Error::Runtime)?;
+        Ok(script.get_result(&mut machine))
+    }
+
+    /// Like ``run``, but the value is converted to a Rust type
+    pub fn run_as<T: FromValue>(&self, script: &Script) -> Result<T, Error> {
+        let value = self.run(script)?.ok_or(Error::Conversion(ConversionError::NoValue))?;
+        T::from_value(value).map_err(Error::Conversion)
+    }
+
+    /// Compiles and runs the code
+    pub fn eval(&self, code: &str) -> Result<Option<Value>, Error> {
+        let script = self.compile(code)?;
+        self.run(&script)
+    }
+
+    /// Like ``eval``, but the value is converted to a Rust type
+    pub fn eval_as<T: FromValue>(&self, code: &str) -> Result<T, Error> {
+        let script = self.compile(code)?;
+        self.run_as(&script)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+use super::{ Engine, Error, Script };
+use super::super::compiler;
+use super::super::lexer::{ Type, ScopeHandle };
+use super::super::vm::natives::Value;
+
+/// What an input to a session resulted in
+pub struct Evaluation {
+    pub value: Option<Value>,
+    /// The type the parser inferred for the value
+    pub value_type: Option<Type>,
+    /// Why the variables and imports that the next inputs won't have are gone
+    pub forgotten: Vec<String>
+}
+
+/// Runs one input after another, like a shell. The top level variables an input sets
+/// are globals of the engine afterwards, and the structs it declares are declared in the engine,
+/// so later inputs can use them. An input that is a block, like ``(a = 1; b = 2)``, is top level too. Variables holding structs and imports can't be kept,
+/// ``Evaluation::forgotten`` says when an input had some. An input that fails doesn't change anything
+pub struct Session {
+    engine: Engine,
+    /// The globals the engine had to begin with, for ``reset``
+    initial_globals: Vec<(String, Value)>,
+    initial_structs: Vec<Type>
+}
+
+impl Session {
+    pub fn new(engine: Engine) -> Session {
+        Session {
+            initial_globals: engine.globals.clone(),
+            initial_structs: engine.structs.clone(),
+            engine: engine
+        }
+    }
+
+    /// Forgets the variables and structs the inputs have set
+    pub fn reset(&mut self) {
+        self.engine.globals = self.initial_globals.clone();
+        self.engine.structs = self.initial_structs.clone();
+    }
+
+    pub fn get_engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn get_engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// The top level variables and their values, in the order they were first set
+    pub fn get_variables(&self) -> &[(String, Value)] {
+        self.engine.get_globals()
+    }
+
+    pub fn eval(&mut self, code: &str) -> Result<Evaluation, Error> {
+        let parsed = self.engine.parse(code)?;
+        let value_type = parsed.get_result_type();
+        let (program, debug_info) = compiler::compile_session(&*parsed.tree, parsed.root_scope,
+            &parsed.scopes, self.engine.get_globals());
+        let script = Script {
+            program: program,
+            result_type: value_type.clone()
+        };
+
+        let mut machine = self.engine.create_machine(&script)?;
+        machine.run().map_err(Error::Runtime)?;
+        let value = script.get_result(&mut machine);
+
+        // The input's outer block is where its statements go, so what it declares is kept like the top level
+        let mut top_scopes = vec![parsed.root_scope];
+        top_scopes.extend(parsed.tree.get_block_scope());
+
+        // The machine halted in main, so its locals are still there to be read
+        let mut variables: Vec<(ScopeHandle, &String, u32)> = debug_info.locals.iter()
+            .filter(|((scope, _), _)| top_scopes.contains(scope))
+            .map(|((scope, name), slot)| (*scope, name, *slot))
+            .collect();
+        variables.sort_by_key(|(_, _, slot)| *slot);
+        let mut forgotten = Vec::new();
+        for (scope, name, slot) in variables {
+            let var_type = scope.get(&parsed.scopes, &name[..])
+                .and_then(|scheme| parsed.scopes.types.get_type(&scheme.term));
+            match var_type {
+                Some(var_type) if var_type.is_value() => {
+                    if let Some(value) = machine.get_local(slot).and_then(|slot| machine.get_value(slot, var_type)) {
+                        self.engine.set_global(&name[..], value);
+                    }
+                },
+                Some(var_type) => forgotten.push(format!("'{}' isn't kept, a {} can't be passed to the next input", name, var_type)),
+                None => {}
+            }
+        }
+
+        for scope in top_scopes {
+            for struct_type in scope.get_structs(&parsed.scopes) {
+                self.engine.declare_struct(struct_type);
+            }
+            for module in scope.get_module_names(&parsed.scopes) {
+                forgotten.push(format!("The import of '{}' isn't kept, import it again in the input that uses it", module));
+            }
+        }
+
+        Ok(Evaluation {
+            value: value,
+            value_type: value_type,
+            forgotten: forgotten
+        })
+    }
+}
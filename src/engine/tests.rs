@@ -0,0 +1,474 @@
+use super::{ Engine, Error, Session };
+use super::super::vm::conversion::ConversionError;
+use super::super::vm::{ Limits, Limit, RuntimeErrorKind };
+use super::super::vm::natives::Value;
+use super::super::lexer::{ Type, Loc };
+use std::rc::Rc;
+use std::cell::RefCell;
+
+#[test]
+fn evaluates_code() {
+    let engine = Engine::new();
+    assert_eq!(engine.eval("(x = 3; x)").ok().unwrap(), Some(Value::Int(3)));
+    assert_eq!(engine.eval("2.5").ok().unwrap(), Some(Value::Float(2.5)));
+    assert_eq!(engine.eval("\"hello\"").ok().unwrap(), Some(Value::Str(String::from("hello"))));
+    assert_eq!(engine.eval("()").ok().unwrap(), None);
+}
+
+#[test]
+fn rejects_code_after_the_value() {
+    let engine = Engine::new();
+    for code in ["\"a\" garbage ) ) )", "print \"a\";\nprint \"b\";", "x = 3; y = 4"].iter() {
+        match engine.parse(code) {
+            Err(Error::Parse(error)) => assert!(error.dump().contains("Expected the end of the code"), "{}", error.dump()),
+            _ => panic!("'{}' parsed", code)
+        }
+    }
+    assert!(engine.parse("(x = 3; y = 4)").is_ok());
+}
+
+#[test]
+fn runs_a_script_more_than_once() {
+    let engine = Engine::new();
+    let script = engine.compile("(x = 1; y = x; y)").ok().unwrap();
+    assert_eq!(engine.run(&script).ok().unwrap(), Some(Value::Int(1)));
+    assert_eq!(engine.run(&script).ok().unwrap(), Some(Value::Int(1)));
+}
+
+#[test]
+fn calls_host_functions() {
+    let mut engine = Engine::new();
+    engine.register("add", vec![Type::Int, Type::Int], Some(Type::Int), |args| {
+        match (&args[0], &args[1]) {
+            (Value::Int(a), Value::Int(b)) => Ok(Some(Value::Int(a + b))),
+            _ => unreachable!()
+        }
+    });
+
+    let logged = Rc::new(RefCell::new(Vec::new()));
+    let log = logged.clone();
+    engine.register("log", vec![Type::Str], None, move |args| {
+        log.borrow_mut().push(format!("{}", args[0]));
+        Ok(None)
+    });
+
+    assert_eq!(engine.eval("(log \"adding\"; add[2, 3])").ok().unwrap(), Some(Value::Int(5)));
+    assert_eq!(*logged.borrow(), vec![String::from("adding")]);
+}
+
+#[test]
+fn passes_arrays() {
+    let mut engine = Engine::new();
+    engine.register_fn("range", |n: i64| (0..n).collect::<Vec<i64>>());
+    engine.register_fn("sum", |values: Vec<i64>| values.iter().sum::<i64>());
+    engine.register_fn("join", |words: Vec<String>| words.join(" "));
+    engine.set_global("words", vec![String::from("in"), String::from("the"), String::from("heap")]);
+
+    assert_eq!(engine.eval_as::<Vec<i64>>("range[3]").ok(), Some(vec![0, 1, 2]));
+    assert_eq!(engine.eval_as::<Vec<i64>>("range[0]").ok(), Some(Vec::new()));
+    assert_eq!(engine.eval_as::<i64>("sum[range[5]]").ok(), Some(10));
+    assert_eq!(engine.eval_as::<String>("join[words]").ok(), Some(String::from("in the heap")));
+    assert_eq!(engine.eval_as::<Vec<String>>("words").ok().map(|words| words.len()), Some(3));
+    match engine.eval("sum[words]") {
+        Err(Error::Type(_)) => {},
+        _ => panic!("An array of strings was summed")
+    }
+}
+
+#[test]
+fn registers_functions_while_a_machine_has_them() {
+    let mut engine = Engine::new();
+    let script = engine.compile("1").ok().unwrap();
+    let machine = engine.create_machine(&script).ok().unwrap();
+    engine.register_fn("seven", || 7i64);
+    assert_eq!(engine.eval_as::<i64>("seven[]").ok(), Some(7));
+    drop(machine);
+}
+
+#[test]
+fn calls_rust_functions() {
+    let mut engine = Engine::new();
+    engine.register_fn("half", |value: f64| value / 2.0);
+    engine.register_fn("check", |value: i64| if value < 0 { Err(String::from("negative")) } else { Ok(value) });
+
+    assert_eq!(engine.eval_as::<f64>("half[3]").ok(), Some(1.5));
+    assert_eq!(engine.eval_as::<i64>("check[4]").ok(), Some(4));
+    engine.set_global("n", -4);
+    match engine.eval("check[n]") {
+        Err(Error::Runtime(error)) => assert!(format!("{}", error).contains("negative")),
+        _ => panic!("The error didn't stop the program")
+    }
+}
+
+#[test]
+fn runs_scripts() {
+    let mut engine = Engine::new();
+    engine.set_args(vec![String::from("first"), String::from("second")]);
+
+    assert_eq!(engine.eval_as::<i64>("#!/usr/bin/env tlang\narg_count[]").ok(), Some(2));
+    assert_eq!(engine.eval_as::<String>("arg[1]").ok(), Some(String::from("second")));
+    match engine.eval("arg[2]") {
+        Err(Error::Runtime(error)) => assert!(format!("{}", error).contains("There is no argument 2")),
+        _ => panic!("Reading a missing argument didn't stop the program")
+    }
+    match engine.eval("(exit[3]; 1)") {
+        Err(Error::Runtime(error)) => assert_eq!(error.get_exit_status(), Some(3)),
+        _ => panic!("Calling exit didn't stop the program")
+    }
+}
+
+fn module_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_source_path(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/modules/main.tlang"));
+    engine
+}
+
+#[test]
+fn imports_modules() {
+    let engine = module_engine();
+    assert_eq!(engine.eval_as::<i64>("(import geometry; geometry.sides)").ok(), Some(4));
+    assert_eq!(engine.eval_as::<String>("(import geometry; geometry.name)").ok(), Some(String::from("square")));
+    // Modules the module imports are parsed once, and can be imported again
+    assert_eq!(engine.eval_as::<f64>("(import geometry; import units; x = units.meter; geometry.size)").ok(), Some(1.0));
+
+    let imports = engine.find_imports("(import geometry; 1)");
+    let names: Vec<_> = imports.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(names, vec!["geometry.tlang", "units.tlang"]);
+}
+
+#[test]
+fn reports_import_errors() {
+    let engine = module_engine();
+    let dump = |code| match engine.parse(code) {
+        Err(Error::Parse(error)) => error.dump(),
+        _ => panic!("The code should have an error in it")
+    };
+    assert!(dump("(import missing; 1)").contains("(1, 2): Unknown module 'missing'"));
+    assert!(dump("(import first; 1)").contains("first imports second imports first"));
+    assert!(dump("(import invalid; 1)").contains("The module 'invalid' has errors in it"));
+    assert!(dump("(import geometry; geometry.color)").contains("(1, 28): The module 'geometry' doesn't define 'color'"));
+    assert!(dump("(import geometry; geometry.color.x = 1; 1)").contains("The module 'geometry' doesn't define 'color'"));
+    assert!(dump("(geometry.sides)").contains("Unknown module"));
+
+    // The uses of a module that failed to import don't add errors of their own
+    let errors = |code| dump(code).lines().count();
+    assert_eq!(errors("(import first; first.value)"), errors("(import first; 1)"));
+    assert_eq!(errors("(import missing; print[missing.name]; missing.origin.x = 2; missing.size)"), errors("(import missing; 1)"));
+}
+
+#[test]
+fn uses_structs() {
+    let engine = module_engine();
+    let declarations = "def Point = struct [x: Float, y: Float]; def Line = struct [from: Point, to: Point, name: String];";
+    let eval = |code: &str| engine.eval(&format!("({} {})", declarations, code)[..]).ok().unwrap();
+
+    assert_eq!(eval("p = Point[y: 2, x: 1.5]; p.x"), Some(Value::Float(1.5)));
+    assert_eq!(eval("line = Line[from: Point[x: 1, y: 2], to: Point[x: 3, y: 4], name: \"a\"]; line.to.y"), Some(Value::Float(4.0)));
+    assert_eq!(eval("line = Line[from: Point[x: 1, y: 2], to: Point[x: 3, y: 4], name: \"a\"]; line.from.x = 5; line.from.x"),
+        Some(Value::Float(5.0)));
+    // Structs are shared, not copied
+    assert_eq!(eval("p = Point[x: 1, y: 2]; q = p; q.y = 7.5; p.y"), Some(Value::Float(7.5)));
+    assert_eq!(eval("p: Point = Point[x: 1, y: 2]; (Point[x: p.y, y: 0]).x"), Some(Value::Float(2.0)));
+
+    assert_eq!(engine.eval_as::<f64>("(import geometry; c = geometry.Corner[x: 1, y: 2]; c.y)").ok(), Some(2.0));
+    assert_eq!(engine.eval_as::<f64>("(import geometry; geometry.origin.x = 3; geometry.origin.x)").ok(), Some(3.0));
+
+    // The field after an argument without brackets is the argument's
+    let mut engine = Engine::new();
+    engine.register_fn("half", |x: f64| x / 2.0);
+    assert_eq!(engine.eval_as::<f64>(&format!("({} p = Point[x: 1, y: 3]; half (p).y)", declarations)[..]).ok(), Some(1.5));
+}
+
+#[test]
+fn reports_field_errors() {
+    let engine = Engine::new();
+    let dump = |code: &str| match engine.parse(&format!("(def Point = struct [x: Float, y: Float]; {})", code)[..]) {
+        Err(Error::Parse(error)) | Err(Error::Type(error)) => error.dump(),
+        _ => panic!("The code should have an error in it")
+    };
+    // Fields that don't fit are type errors, and the code after them is still read
+    let type_error = |code: &str| match engine.parse(&format!("(def Point = struct [x: Float, y: Float]; {})", code)[..]) {
+        Err(Error::Type(error)) => {
+            let text = error.dump();
+            assert!(!text.contains("Expected ')'"), "{}", text);
+            text
+        },
+        _ => panic!("The code should have a type error in it")
+    };
+
+    assert!(type_error("p = Point[x: 1, y: 2, z: 3]; 1").contains("'Point' has no field 'z'"));
+    assert!(type_error("p = Point[x: 1, z: 3, y: 2]; 1").contains("'Point' has no field 'z'"));
+    assert!(dump("p = Point[x: 1]; 1").contains("'Point' also needs the field 'y'"));
+    assert!(dump("p = Point[]; 1").contains("'Point' also needs the fields 'x', 'y'"));
+    assert!(dump("p = Point[x: 1, x: 2, y: 3]; 1").contains("The field 'x' of 'Point' is given more than once"));
+    assert!(type_error("Point[x: \"a\", y: 2]").contains("The field 'x' of 'Point' is float but is given string"));
+    assert!(type_error("p = Point[x: \"a\", y: 2]; 1").contains("The field 'x' of 'Point' is float but is given string"));
+    assert!(type_error("p = Point[x: 1, y: 2]; p.z").contains("'Point' has no field 'z'"));
+    assert!(type_error("p = Point[x: 1, y: 2]; p.y = \"a\"").contains("The field 'y' of 'Point' is float but is given string"));
+    assert!(type_error("p = Point[x: 1, y: 2]; p.z = 3; 1").contains("'Point' has no field 'z'"));
+    assert!(type_error("p = 2.5; p.x = 2; 1").contains("float has no field 'x', only structs have fields"));
+    assert!(type_error("x = 2.5; x.y").contains("float has no field 'y', only structs have fields"));
+    // A field after an argument without brackets is the argument's
+    assert!(type_error("print 1.5.y").contains("float has no field 'y', only structs have fields"));
+    assert!(type_error("print 1.5.y").contains("Invalid call to 'print'"));
+    assert!(dump("def Size = struct [width: Int, width: Int]; 1").contains("The struct has a field with this name already"));
+    assert!(dump("def Point = struct [z: Int]; 1").contains("There is a struct with this name already"));
+    assert!(dump("Point").contains("Expected the fields of the struct"));
+    // Names that are something other than a module aren't reported as one
+    assert!(!dump("p = Point[x: 1]; p.x").contains("Unknown module"));
+    assert!(dump("Point.x").contains("Invalid variable name"));
+    assert!(!dump("Point.x").contains("Unknown module"));
+}
+
+#[test]
+fn converts_results() {
+    let mut engine = Engine::new();
+    engine.set_global("flag", true);
+    engine.set_global("name", "world");
+
+    assert_eq!(engine.eval_as::<bool>("flag").ok(), Some(true));
+    assert_eq!(engine.eval_as::<String>("name").ok(), Some(String::from("world")));
+    assert!(matches!(engine.eval_as::<u8>("name"),
+        Err(Error::Conversion(ConversionError::WrongType { expected: Type::Int, found: Type::Str }))));
+    assert!(matches!(engine.eval_as::<i64>("()"), Err(Error::Conversion(ConversionError::NoValue))));
+}
+
+#[test]
+fn sets_globals() {
+    let mut engine = Engine::new();
+    engine.set_global("greeting", Value::Str(String::from("hi")));
+    engine.set_global("count", Value::Int(1));
+    engine.set_global("count", Value::Int(2));
+
+    assert_eq!(engine.eval("greeting").ok().unwrap(), Some(Value::Str(String::from("hi"))));
+    assert_eq!(engine.eval("count").ok().unwrap(), Some(Value::Int(2)));
+}
+
+#[test]
+fn runs_with_limits() {
+    let mut engine = Engine::new();
+    engine.limits = Limits { max_instructions: Some(3), ..Limits::default() };
+
+    match engine.eval("(x = 1; x = 2; x = 3; x)") {
+        Err(Error::Runtime(error)) => assert_eq!(error.get_limit(), Some(Limit::Instructions(3))),
+        _ => panic!("The program didn't hit the instruction limit")
+    }
+}
+
+#[test]
+fn reports_errors() {
+    let engine = Engine::new();
+    assert!(matches!(engine.eval("(x = 1"), Err(Error::Parse(_))));
+    assert!(matches!(engine.eval("(x = 1; missing[x])"), Err(Error::Parse(_))));
+}
+
+#[test]
+fn reports_runtime_errors_where_they_happened() {
+    // Arithmetic errors and casts only come from bytecode, the compiler doesn't emit those instructions.
+    // Errors from code come from natives, and have the line of the call
+    let mut engine = Engine::new();
+    engine.set_args(vec![String::from("first")]);
+    let error = match engine.eval("(\n    x = 1;\n    arg[x]\n)") {
+        Err(Error::Runtime(error)) => error,
+        _ => panic!("The code should fail when it runs")
+    };
+    assert!(matches!(&error.kind, RuntimeErrorKind::NativeError { function, .. } if function == "arg"));
+    assert_eq!(format!("{}", error.kind), "Error in 'arg': There is no argument 1, there are 1");
+    // Lines and characters start at 0, they're shown as (3, 5)
+    assert!(error.loc == Some(Loc::new(2, 4)));
+    assert_eq!(error.trace.len(), 1);
+    assert!(error.trace[0].loc == Some(Loc::new(2, 4)));
+}
+
+#[test]
+fn infers_types_from_later_code() {
+    let engine = Engine::new();
+    // The literal is a float, because of what the variable is used as afterwards
+    assert_eq!(engine.eval("(x = 3; y = 2.5; y = x; x)").ok().unwrap(), Some(Value::Float(3.0)));
+    assert_eq!(engine.eval("(x = 3; x)").ok().unwrap(), Some(Value::Int(3)));
+    // Once it's an int, it's converted
+    assert_eq!(engine.eval("(x = 3; print[x]; y = 2.5; y = x; y)").ok().unwrap(), Some(Value::Float(3.0)));
+
+    assert!(matches!(engine.eval("(x = \"a\"; x = 3; x)"), Err(Error::Type(_))));
+    assert!(matches!(engine.eval("(x = 3; print[x]; x = 2.5; x)"), Err(Error::Type(_))));
+}
+
+#[test]
+fn reports_type_conflicts() {
+    let mut engine = Engine::new();
+    engine.set_global("count", 1);
+    let dump = |engine: &Engine, code| match engine.parse(code) {
+        Err(Error::Type(error)) => error.dump(),
+        _ => panic!("The code should have a type error in it")
+    };
+
+    assert!(dump(&engine, "(x = \"a\";\n    x = 2.5; x)").contains("(2, 5): 'x' was string at (1, 2) but assigned float at (2, 5)"));
+    assert!(dump(&engine, "(count = \"a\"; count)").contains("'count' was int as a global but assigned string at (1, 2)"));
+    // The type that's been inferred for the variable is the one it was
+    assert!(dump(&engine, "(x = 3; print[x]; x = 2.5; x)").contains("'x' was int at (1, 2) but assigned float"));
+}
+
+#[test]
+fn keeps_defs_constant() {
+    let mut engine = Engine::new();
+    engine.declare_struct(Type::Struct { name: String::from("Size"), fields: vec![(String::from("width"), Type::Int)] });
+    let dump = |engine: &Engine, code| match engine.parse(code) {
+        Err(Error::Parse(error)) => error.dump(),
+        _ => panic!("The code should have an error in it")
+    };
+
+    assert!(dump(&engine, "(def f = 3; f = 4; f)").contains("(1, 13): 'f' is defined at (1, 6) and can't be assigned"));
+    assert!(dump(&engine, "(def f = 3; def f = 4; f)").contains("'f' is defined at (1, 6) and can't be assigned"));
+    assert!(dump(&engine, "(def f = 3; (f = 4; f))").contains("'f' is defined at (1, 6) and can't be assigned"));
+    assert!(dump(&engine, "(def P = struct [a: Int]; P = 3; P[a: 1])").contains("'P' is defined at (1, 6) and can't be assigned"));
+    assert!(dump(&engine, "(Size = 3; 1)").contains("'Size' is a struct of the engine and can't be assigned"));
+
+    // The fields of a def's struct can still change
+    assert_eq!(engine.eval("(def s = Size[width: 1]; s.width = 2; s.width)").ok().unwrap(), Some(Value::Int(2)));
+}
+
+#[test]
+fn results_in_nil() {
+    let mut engine = Engine::new();
+    engine.register("log", vec![Type::Str], None, |_| Ok(None));
+
+    assert_eq!(engine.parse("(3; 4;)").ok().unwrap().get_result_type(), Some(Type::Nil));
+    assert_eq!(engine.parse("(x = 3)").ok().unwrap().get_result_type(), Some(Type::Nil));
+    assert_eq!(engine.eval("(x = (3; 4); x)").ok().unwrap(), Some(Value::Int(4)));
+    assert_eq!(engine.eval("(x = (3; 4;); x)").ok().unwrap(), None);
+    assert_eq!(engine.eval("(x = (); y: Nil = x; 2.5)").ok().unwrap(), Some(Value::Float(2.5)));
+    // A call that doesn't give back a value can end a block
+    assert_eq!(engine.eval("(log \"a\")").ok().unwrap(), None);
+
+    match engine.parse("(x = 3; x = ())") {
+        Err(Error::Type(error)) => assert!(error.dump().contains("'x' was number at (1, 2) but assigned nil")),
+        _ => panic!("Nil can't be assigned to a number")
+    }
+}
+
+#[test]
+fn checks_type_annotations() {
+    let engine = Engine::new();
+    assert_eq!(engine.eval("(x: Float = 3; x)").ok().unwrap(), Some(Value::Float(3.0)));
+    assert_eq!(engine.eval("(x: Int = 3; y: Float = x; y)").ok().unwrap(), Some(Value::Float(3.0)));
+    assert_eq!(engine.eval("(x: String = \"a\"; x: String = \"b\"; x)").ok().unwrap(), Some(Value::Str(String::from("b"))));
+
+    let dump = |code| match engine.parse(code) {
+        Err(Error::Type(error)) => error.dump(),
+        _ => panic!("The code should have a type error in it")
+    };
+    assert!(dump("(x: Float = \"a\"; x)").contains("(1, 2): 'x' is annotated as float but assigned string"));
+    assert!(dump("(x: Int = 2.5; x)").contains("'x' is annotated as int but assigned float"));
+    assert!(dump("(x: [Int] = 3; x)").contains("'x' is annotated as [int] but assigned number"));
+    assert!(dump("(f: func[Int, [Float]] -> String = 3; f)").contains("annotated as func[int, [float]] -> string"));
+    assert!(dump("(x = 2.5; x: Int = 3; x)").contains("'x' was float at (1, 2) but assigned int"));
+
+    match engine.parse("(x: Integer = 3; x)") {
+        Err(Error::Parse(error)) => assert!(error.dump().contains("Unknown type")),
+        _ => panic!("The annotation should be a parse error")
+    }
+}
+
+#[test]
+fn keeps_variables_between_inputs() {
+    let mut session = Session::new(Engine::new());
+    assert_eq!(session.eval("x = 3").ok().unwrap().value, None);
+    assert_eq!(session.eval("x").ok().unwrap().value, Some(Value::Int(3)));
+    session.eval("y = 2.5").ok().unwrap();
+    session.eval("greeting = \"hi\"").ok().unwrap();
+    session.eval("y = x").ok().unwrap();
+
+    let evaluation = session.eval("y").ok().unwrap();
+    assert_eq!(evaluation.value, Some(Value::Float(3.0)));
+    assert_eq!(evaluation.value_type, Some(Type::Float));
+    assert_eq!(session.eval("greeting").ok().unwrap().value, Some(Value::Str(String::from("hi"))));
+}
+
+#[test]
+fn keeps_variables_of_blocks_between_inputs() {
+    let mut session = Session::new(Engine::new());
+    assert!(session.eval("(a = 1; b = 2.5)").ok().unwrap().forgotten.is_empty());
+    assert_eq!(session.eval("a").ok().unwrap().value, Some(Value::Int(1)));
+    assert_eq!(session.eval("b").ok().unwrap().value, Some(Value::Float(2.5)));
+    assert_eq!(session.eval("(\n    y = 5;\n    y\n)").ok().unwrap().value, Some(Value::Int(5)));
+    assert_eq!(session.eval("y").ok().unwrap().value, Some(Value::Int(5)));
+
+    // Blocks inside the input's block are scopes of their own
+    session.eval("(z = 1; (w = 2; w))").ok().unwrap();
+    assert_eq!(session.eval("z").ok().unwrap().value, Some(Value::Int(1)));
+    assert!(session.eval("w").is_err());
+
+    let evaluation = session.eval("(def P = struct [a: Int]; p = P[a: 2]; 1)").ok().unwrap();
+    assert_eq!(evaluation.forgotten, vec![String::from("'p' isn't kept, a P can't be passed to the next input")]);
+    assert_eq!(session.eval("(P[a: 3]).a").ok().unwrap().value, Some(Value::Int(3)));
+}
+
+#[test]
+fn keeps_arrays_in_sessions() {
+    let mut engine = Engine::new();
+    engine.register_fn("range", |n: i64| (0..n).collect::<Vec<i64>>());
+    let mut session = Session::new(engine);
+    assert!(session.eval("values = range[3]").ok().unwrap().forgotten.is_empty());
+    let expected = Value::Array { element: Type::Int, values: vec![Value::Int(0), Value::Int(1), Value::Int(2)] };
+    assert_eq!(session.eval("values").ok().unwrap().value, Some(expected));
+}
+
+#[test]
+fn keeps_structs_in_sessions() {
+    let mut session = Session::new(Engine::new());
+    session.eval("def P = struct [a: Int]").ok().unwrap();
+    assert_eq!(session.eval("(p = P[a: 2]; p.a)").ok().unwrap().value, Some(Value::Int(2)));
+    assert!(session.eval("def P = struct [b: Int]").is_err());
+
+    // The struct is in the heap of the machine that ran the input, so the variable can't be kept
+    let evaluation = session.eval("p = P[a: 3]").ok().unwrap();
+    assert_eq!(evaluation.forgotten, vec![String::from("'p' isn't kept, a P can't be passed to the next input")]);
+    assert!(session.eval("p").is_err());
+
+    session.reset();
+    assert!(session.eval("P[a: 1]").is_err());
+}
+
+#[test]
+fn reports_imports_that_are_not_kept() {
+    let mut session = Session::new(module_engine());
+    let evaluation = session.eval("import geometry").ok().unwrap();
+    assert_eq!(evaluation.forgotten, vec![String::from("The import of 'geometry' isn't kept, import it again in the input that uses it")]);
+    assert!(session.eval("geometry.sides").is_err());
+    assert_eq!(session.eval("(import geometry; geometry.sides)").ok().unwrap().value, Some(Value::Int(4)));
+}
+
+#[test]
+fn keeps_variables_after_errors() {
+    let mut engine = Engine::new();
+    engine.limits = Limits { max_instructions: Some(6), ..Limits::default() };
+    let mut session = Session::new(engine);
+    session.eval("x = 1").ok().unwrap();
+
+    assert!(matches!(session.eval("(x = 2"), Err(Error::Parse(_))));
+    assert!(matches!(session.eval("(x = 2; x = 3; x = 4; x = 5; x)"), Err(Error::Runtime(_))));
+    assert_eq!(session.eval("x").ok().unwrap().value, Some(Value::Int(1)));
+}
+
+#[test]
+fn finds_incomplete_code() {
+    let engine = Engine::new();
+    assert!(engine.is_incomplete("("));
+    assert!(engine.is_incomplete("(x = 1;\n"));
+    assert!(engine.is_incomplete("x = (\n  y = 2;\n  (y"));
+    assert!(!engine.is_incomplete("(x = 1; x)"));
+    assert!(!engine.is_incomplete("(x = ; x)"));
+    assert!(!engine.is_incomplete("missing"));
+}
+
+#[test]
+fn resets_sessions() {
+    let mut engine = Engine::new();
+    engine.set_global("x", 1);
+    let mut session = Session::new(engine);
+    session.eval("x = 2").ok().unwrap();
+    session.eval("y = 3").ok().unwrap();
+
+    session.reset();
+    assert_eq!(session.get_variables(), &[(String::from("x"), Value::Int(1))]);
+}
@@ -1,9 +1,12 @@
-use super::{ TreeDump, SyntaxTreeNode, CodeLocation, Loc };
+use std::fmt::Write;
+use super::{ TreeDump, SyntaxTreeNode, CodeLocation, Loc, ScopeHandle, ScopePool };
+use super::super::compiler::Compiler;
+use super::super::vm::Instruction;
 
 pub struct AssignmentNode {
     pub start: Loc,
     pub identifier: String,
-    pub data: Box<SyntaxTreeNode>
+    pub data: Box<dyn SyntaxTreeNode>
 }
 
 impl CodeLocation for AssignmentNode {
@@ -11,11 +14,26 @@ impl CodeLocation for AssignmentNode {
 }
 
 impl TreeDump for AssignmentNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Assignment of '{}' to", 
-            indent_style.repeat(indent), self.start, self.identifier);
-        self.data.print_with_indent(indent + 1, indent_style);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Assignment of '{}' to", 
+            indent_style.repeat(indent), self.start, self.identifier).unwrap();
+        self.data.dump_with_indent(text, indent + 1, indent_style);
     }
 }
 
-impl SyntaxTreeNode for AssignmentNode {}
+impl SyntaxTreeNode for AssignmentNode {
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        self.data.compile(scope, scopes, compiler);
+        // A nil variable has nothing to store, so it doesn't get a slot
+        if !self.data.has_value(scopes) {
+            return;
+        }
+
+        let var_type = scope.get(scopes, &self.identifier[..])
+            .expect("An AssignmentNode's variable name does not fit the scope");
+        compiler.emit_conversion(self.data.get_type(scopes), scopes.types.get_type(&var_type.term), self.start);
+
+        let slot = compiler.get_local(scope, scopes, &self.identifier[..]);
+        compiler.emit_with(Instruction::StoreLocal, slot, self.start);
+    }
+}
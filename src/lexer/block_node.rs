@@ -1,10 +1,13 @@
-use super::{ SyntaxTreeNode, TreeDump, CodeLocation, Loc, ScopeHandle, ScopePool, TypeCollection };
+use std::fmt::Write;
+use super::{ SyntaxTreeNode, TreeDump, CodeLocation, Loc, ScopeHandle, ScopePool, Term, Type };
+use super::super::compiler::Compiler;
+use super::super::vm::Instruction;
 
 pub struct BlockNode {
     pub start: Loc,
     pub scope: ScopeHandle,
-    pub contents: Vec<Box<SyntaxTreeNode>>,
-    pub _return: Option<Box<SyntaxTreeNode>>
+    pub contents: Vec<Box<dyn SyntaxTreeNode>>,
+    pub _return: Option<Box<dyn SyntaxTreeNode>>
 }
 
 impl CodeLocation for BlockNode {
@@ -12,27 +15,47 @@ impl CodeLocation for BlockNode {
 }
 
 impl TreeDump for BlockNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Block", indent_style.repeat(indent), self.start);
-        if self.contents.len() > 0 {
-            println!("{}Contents:", indent_style.repeat(indent + 1));
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Block", indent_style.repeat(indent), self.start).unwrap();
+        if !self.contents.is_empty() {
+            writeln!(text, "{}Contents:", indent_style.repeat(indent + 1)).unwrap();
             for content in &self.contents {
-                content.print_with_indent(indent + 2, indent_style);
+                content.dump_with_indent(text, indent + 2, indent_style);
             }
         }
         if let Some(node) = &self._return {
-            println!("{}Returns:", indent_style.repeat(indent + 1));
-            node.print_with_indent(indent + 2, indent_style);
+            writeln!(text, "{}Returns:", indent_style.repeat(indent + 1)).unwrap();
+            node.dump_with_indent(text, indent + 2, indent_style);
         }
     }
 }
 
 impl SyntaxTreeNode for BlockNode {
-    fn get_possible_returns(&self, scope: ScopeHandle, scopes: &ScopePool) -> TypeCollection {
+    fn get_term(&self) -> Term {
+        match &self._return {
+            Some(node) => node.get_term(),
+            None => Term::Known(Type::Nil)
+        }
+    }
+
+    fn get_block_scope(&self) -> Option<ScopeHandle> {
+        Some(self.scope)
+    }
+
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        compiler.enter_scope(self.scope);
+        for content in &self.contents {
+            content.compile(self.scope, scopes, compiler);
+
+            // Values that aren't returned are thrown away
+            if content.has_value(scopes) {
+                compiler.emit(Instruction::Pop, content.get_start());
+            }
+        }
+
         if let Some(node) = &self._return {
-            node.get_possible_returns(self.scope, scopes)
-        }else {
-            TypeCollection::undef()
+            node.compile(self.scope, scopes, compiler);
         }
+        compiler.enter_scope(scope);
     }
 }
\ No newline at end of file
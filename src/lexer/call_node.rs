@@ -0,0 +1,45 @@
+use std::fmt::Write;
+use super::{ SyntaxTreeNode, TreeDump, CodeLocation, Loc, ScopeHandle, ScopePool, Term, Type };
+use super::super::compiler::Compiler;
+use super::super::vm::Instruction;
+use super::super::vm::natives::{ NativeSignature, NativeImport };
+
+/// A call to a native function, the overload is picked when parsing
+pub struct CallNode {
+    pub start: Loc,
+    pub function: String,
+    pub signature: NativeSignature,
+    pub args: Vec<Box<dyn SyntaxTreeNode>>
+}
+
+impl CodeLocation for CallNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for CallNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Call to '{}' {}", indent_style.repeat(indent), self.start, self.function, self.signature).unwrap();
+        for arg in &self.args {
+            arg.dump_with_indent(text, indent + 1, indent_style);
+        }
+    }
+}
+
+impl SyntaxTreeNode for CallNode {
+    fn get_term(&self) -> Term {
+        Term::from(self.signature.returns.clone().unwrap_or(Type::Nil))
+    }
+
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        for (arg, arg_type) in self.args.iter().zip(self.signature.args.iter()) {
+            arg.compile(scope, scopes, compiler);
+            compiler.emit_conversion(arg.get_type(scopes), Some(arg_type.clone()), arg.get_start());
+        }
+
+        let index = compiler.add_native(NativeImport {
+            name: self.function.clone(),
+            signature: self.signature.clone()
+        });
+        compiler.emit_with(Instruction::CallNative, index, self.start);
+    }
+}
@@ -1,14 +1,28 @@
+use std::fmt::Write;
 use super::{ CodeLocation, SyntaxTreeNode };
+use super::super::vm::natives::NativeSignature;
 use super::super::TreeDump;
 use super::super::needle::Loc;
+use std::path::PathBuf;
 
 pub trait ParserError: CodeLocation + TreeDump {
-    fn get_causes(&self) -> &[Box<ParserError>] {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
         &[]
     }
 
+    /// Whether the code is fine apart from values having the wrong types
+    fn is_type_error(&self) -> bool {
+        let causes = self.get_causes();
+        !causes.is_empty() && causes.iter().all(|cause| cause.is_type_error())
+    }
+
+    /// Whether the code ended at ``end`` while a block was still open, so more code could fix it
+    fn is_incomplete(&self, end: Loc) -> bool {
+        self.get_causes().iter().any(|cause| cause.is_incomplete(end))
+    }
+
     fn get_strength(&self) -> u8;
-    fn cmp_strength(&self, other: &Option<Box<ParserError>>) -> bool {
+    fn cmp_strength(&self, other: &Option<Box<dyn ParserError>>) -> bool {
         match other {
             None => true,
             Some(value) => value.get_strength() <= self.get_strength()
@@ -35,13 +49,13 @@ impl CodeLocation for LiteralError {
 }
 
 impl TreeDump for LiteralError {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Invalid literal", indent_style.repeat(indent), self.start);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Invalid literal", indent_style.repeat(indent), self.start).unwrap();
     }
 }
 
 impl ParserError for LiteralError {
-    fn get_causes(&self) -> &[Box<ParserError>] {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
         &[]
     }
 
@@ -56,7 +70,29 @@ pub enum SimpleError {
     ExpectedIdentifier(Loc, u8),
     ExpectedEquals(Loc, u8),
     InvalidVariableName(Loc, u8),
-    ExpectedExpression(Loc, u8)
+    ExpectedExpression(Loc, u8),
+    ExpectedArrayClose(Loc),
+    ExpectedArguments(Loc, u8),
+    ExpectedImport(Loc),
+    ExpectedDef(Loc),
+    /// Modules only have ``def``s and ``import``s in them
+    ExpectedDefinition(Loc),
+    ExpectedSeparator(Loc),
+    UnknownModule(Loc),
+    ExpectedType(Loc),
+    /// A name in a type that isn't one of the types
+    UnknownType(Loc),
+    ExpectedColon(Loc),
+    /// A struct's name has to be followed by its fields to make a value
+    ExpectedFields(Loc),
+    /// A struct declares the same field twice
+    RepeatedField(Loc),
+    /// Nil fields would have no value to store
+    NilField(Loc),
+    /// The scope declares a struct with the name already
+    StructExists(Loc),
+    /// There's more code after the value a program is
+    ExpectedEnd(Loc)
 }
 
 impl CodeLocation for SimpleError {
@@ -69,21 +105,51 @@ impl CodeLocation for SimpleError {
             ExpectedEquals(loc, _) => *loc,
             InvalidVariableName(loc, _) => *loc,
             ExpectedExpression(loc, _) => *loc,
+            ExpectedArrayClose(loc) => *loc,
+            ExpectedArguments(loc, _) => *loc,
+            ExpectedImport(loc) => *loc,
+            ExpectedDef(loc) => *loc,
+            ExpectedDefinition(loc) => *loc,
+            ExpectedSeparator(loc) => *loc,
+            UnknownModule(loc) => *loc,
+            ExpectedType(loc) => *loc,
+            UnknownType(loc) => *loc,
+            ExpectedColon(loc) => *loc,
+            ExpectedFields(loc) => *loc,
+            RepeatedField(loc) => *loc,
+            NilField(loc) => *loc,
+            StructExists(loc) => *loc,
+            ExpectedEnd(loc) => *loc,
         }
     }
 }
 
 impl TreeDump for SimpleError {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
         use SimpleError::*;
-        println!("{}({}): {}", indent_style.repeat(indent), self.get_start(), match self {
+        writeln!(text, "{}({}): {}", indent_style.repeat(indent), self.get_start(), match self {
             ExpectedBlockOpen(_) => "Expected '('",
             ExpectedBlockClose(_) => "Expected ')'",
             ExpectedIdentifier(_, _) => "Expected identifier",
             ExpectedEquals(_, _) => "Expected equals",
             InvalidVariableName(_, _) => "Invalid variable name",
             ExpectedExpression(_, _) => "Expected expression",
-        });
+            ExpectedArrayClose(_) => "Expected ']'",
+            ExpectedArguments(_, _) => "Expected arguments, in '[]' or a single literal or block",
+            ExpectedImport(_) => "Expected 'import'",
+            ExpectedDef(_) => "Expected 'def'",
+            ExpectedDefinition(_) => "Expected 'def' or 'import'",
+            ExpectedSeparator(_) => "Expected ';'",
+            UnknownModule(_) => "Unknown module, it has to be imported first",
+            ExpectedType(_) => "Expected a type",
+            UnknownType(_) => "Unknown type, expected 'Int', 'Float', 'String', 'Nil', 'func', '[]' or a struct",
+            ExpectedColon(_) => "Expected ':'",
+            ExpectedFields(_) => "Expected the fields of the struct, in '[]'",
+            RepeatedField(_) => "The struct has a field with this name already",
+            NilField(_) => "A field can't be Nil",
+            StructExists(_) => "There is a struct with this name already",
+            ExpectedEnd(_) => "Expected the end of the code, several values go in a block like '( a; b )'",
+        }).unwrap();
     }
 }
 
@@ -97,6 +163,28 @@ impl ParserError for SimpleError {
             ExpectedEquals(_, strength) => *strength,
             InvalidVariableName(_, strength) => *strength,
             ExpectedExpression(_, strength) => *strength,
+            ExpectedArrayClose(_) => 4,
+            ExpectedArguments(_, strength) => *strength,
+            ExpectedImport(_) => 0,
+            ExpectedDef(_) => 0,
+            ExpectedDefinition(_) => 4,
+            ExpectedSeparator(_) => 4,
+            UnknownModule(_) => 3,
+            ExpectedType(_) => 3,
+            UnknownType(_) => 3,
+            ExpectedColon(_) => 3,
+            ExpectedFields(_) => 2,
+            RepeatedField(_) => 3,
+            NilField(_) => 3,
+            StructExists(_) => 3,
+            ExpectedEnd(_) => 4,
+        }
+    }
+
+    fn is_incomplete(&self, end: Loc) -> bool {
+        match self {
+            SimpleError::ExpectedBlockClose(loc) => *loc == end,
+            _ => false
         }
     }
 }
@@ -104,7 +192,7 @@ impl ParserError for SimpleError {
 pub struct AssignmentDataError {
     pub start: Loc,
     pub strength: u8,
-    pub cause: Box<ParserError>,
+    pub cause: Box<dyn ParserError>,
     pub var_name: String
 }
 
@@ -115,15 +203,320 @@ impl CodeLocation for AssignmentDataError {
 }
 
 impl TreeDump for AssignmentDataError {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Invalid assignment for '{}'", indent_style.repeat(indent), self.start, self.var_name);
-        self.cause.print_with_indent(indent + 1, indent_style);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Invalid assignment for '{}'", indent_style.repeat(indent), self.start, self.var_name).unwrap();
+        self.cause.dump_with_indent(text, indent + 1, indent_style);
     }
 }
 
 impl ParserError for AssignmentDataError {
-    fn get_causes(&self) -> &[Box<ParserError>] {
-        &[] // Bah
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
+        std::slice::from_ref(&self.cause)
+    }
+
+    fn get_strength(&self) -> u8 {
+        self.strength
+    }
+}
+
+/// A variable is assigned a value of a type it can't have
+pub struct TypeConflictError {
+    /// Where the assignment is
+    pub start: Loc,
+    pub var_name: String,
+    /// Where the variable was first assigned, None if it is a global of the engine
+    pub declared: Option<Loc>,
+    pub var_type: String,
+    pub value_type: String
+}
+
+impl CodeLocation for TypeConflictError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for TypeConflictError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        write!(text, "{}({}): '{}' was {}", indent_style.repeat(indent), self.start, self.var_name, self.var_type).unwrap();
+        match self.declared {
+            Some(declared) => write!(text, " at ({})", declared).unwrap(),
+            None => write!(text, " as a global").unwrap()
+        }
+        writeln!(text, " but assigned {} at ({})", self.value_type, self.start).unwrap();
+    }
+}
+
+impl ParserError for TypeConflictError {
+    fn is_type_error(&self) -> bool {
+        true
+    }
+
+    fn get_strength(&self) -> u8 {
+        3
+    }
+}
+
+/// The module doesn't ``def`` the name after the dot
+pub struct UnknownDefinitionError {
+    /// Where the name is
+    pub start: Loc,
+    pub module: String,
+    pub name: String
+}
+
+impl CodeLocation for UnknownDefinitionError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for UnknownDefinitionError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): The module '{}' doesn't define '{}'", indent_style.repeat(indent), self.start, self.module, self.name).unwrap();
+    }
+}
+
+impl ParserError for UnknownDefinitionError {
+    fn get_strength(&self) -> u8 {
+        3
+    }
+}
+
+/// An assignment to the name of a def or a struct, which are constant
+pub struct DefinitionAssignedError {
+    /// Where the assignment is
+    pub start: Loc,
+    pub var_name: String,
+    /// Where the def is, None for a struct the engine declared
+    pub defined: Option<Loc>
+}
+
+impl CodeLocation for DefinitionAssignedError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for DefinitionAssignedError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        write!(text, "{}({}): '{}' is ", indent_style.repeat(indent), self.start, self.var_name).unwrap();
+        match self.defined {
+            Some(defined) => write!(text, "defined at ({})", defined).unwrap(),
+            None => write!(text, "a struct of the engine").unwrap()
+        }
+        writeln!(text, " and can't be assigned").unwrap();
+    }
+}
+
+impl ParserError for DefinitionAssignedError {
+    fn get_strength(&self) -> u8 {
+        3
+    }
+}
+
+/// The value of an annotated assignment doesn't have the annotated type
+pub struct AnnotationError {
+    pub start: Loc,
+    pub var_name: String,
+    pub annotation: String,
+    pub found: String
+}
+
+impl CodeLocation for AnnotationError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for AnnotationError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): '{}' is annotated as {} but assigned {}",
+            indent_style.repeat(indent), self.start, self.var_name, self.annotation, self.found).unwrap();
+    }
+}
+
+impl ParserError for AnnotationError {
+    fn is_type_error(&self) -> bool {
+        true
+    }
+
+    fn get_strength(&self) -> u8 {
+        3
+    }
+}
+
+pub enum CallProblem {
+    Argument(Box<dyn ParserError>),
+    /// None of the versions of the function take these arguments, ``found`` are their types
+    NoOverload { found: Vec<String>, overloads: Vec<NativeSignature> }
+}
+
+pub struct CallError {
+    pub start: Loc,
+    pub strength: u8,
+    pub function: String,
+    pub problem: CallProblem
+}
+
+impl CodeLocation for CallError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for CallError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Invalid call to '{}'", indent_style.repeat(indent), self.start, self.function).unwrap();
+        match &self.problem {
+            CallProblem::Argument(cause) => cause.dump_with_indent(text, indent + 1, indent_style),
+            CallProblem::NoOverload { found, overloads } => {
+                writeln!(text, "{}It can't take the arguments [{}], only", indent_style.repeat(indent + 1), found.join(", ")).unwrap();
+                for signature in overloads {
+                    writeln!(text, "{}{}", indent_style.repeat(indent + 2), signature).unwrap();
+                }
+            }
+        }
+    }
+}
+
+impl ParserError for CallError {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
+        match &self.problem {
+            CallProblem::Argument(cause) => std::slice::from_ref(cause),
+            CallProblem::NoOverload { .. } => &[]
+        }
+    }
+
+    fn is_type_error(&self) -> bool {
+        match &self.problem {
+            CallProblem::Argument(cause) => cause.is_type_error(),
+            CallProblem::NoOverload { .. } => true
+        }
+    }
+
+    fn get_strength(&self) -> u8 {
+        self.strength
+    }
+}
+
+pub enum FieldProblem {
+    /// The value given for the field has errors in it
+    Value { field: String, cause: Box<dyn ParserError> },
+    /// The struct doesn't have the field
+    Unknown(String),
+    /// Only structs have fields, the owner is the type of the value
+    NotAStruct(String),
+    /// A construction gives the field more than once
+    Repeated(String),
+    /// A construction leaves these fields out
+    Missing(Vec<String>),
+    Mismatch { field: String, expected: String, found: String }
+}
+
+/// Getting, setting or giving the fields of a struct went wrong
+pub struct FieldError {
+    pub start: Loc,
+    pub strength: u8,
+    /// The name of the struct, or the type of a value that isn't one
+    pub owner: String,
+    pub problem: FieldProblem
+}
+
+impl CodeLocation for FieldError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for FieldError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        write!(text, "{}({}): ", indent_style.repeat(indent), self.start).unwrap();
+        match &self.problem {
+            FieldProblem::Value { field, cause } => {
+                writeln!(text, "Invalid value for the field '{}' of '{}'", field, self.owner).unwrap();
+                cause.dump_with_indent(text, indent + 1, indent_style);
+            },
+            FieldProblem::Unknown(field) => writeln!(text, "'{}' has no field '{}'", self.owner, field).unwrap(),
+            FieldProblem::NotAStruct(field) =>
+                writeln!(text, "{} has no field '{}', only structs have fields", self.owner, field).unwrap(),
+            FieldProblem::Repeated(field) =>
+                writeln!(text, "The field '{}' of '{}' is given more than once", field, self.owner).unwrap(),
+            FieldProblem::Missing(fields) if fields.len() == 1 =>
+                writeln!(text, "'{}' also needs the field '{}'", self.owner, fields[0]).unwrap(),
+            FieldProblem::Missing(fields) =>
+                writeln!(text, "'{}' also needs the fields '{}'", self.owner, fields.join("', '")).unwrap(),
+            FieldProblem::Mismatch { field, expected, found } =>
+                writeln!(text, "The field '{}' of '{}' is {} but is given {}", field, self.owner, expected, found).unwrap()
+        }
+    }
+}
+
+impl ParserError for FieldError {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
+        match &self.problem {
+            FieldProblem::Value { cause, .. } => std::slice::from_ref(cause),
+            _ => &[]
+        }
+    }
+
+    fn is_type_error(&self) -> bool {
+        match &self.problem {
+            FieldProblem::Value { cause, .. } => cause.is_type_error(),
+            FieldProblem::Repeated(_) | FieldProblem::Missing(_) => false,
+            FieldProblem::Unknown(_) | FieldProblem::NotAStruct(_) | FieldProblem::Mismatch { .. } => true
+        }
+    }
+
+    fn get_strength(&self) -> u8 {
+        self.strength
+    }
+}
+
+pub enum ImportProblem {
+    /// The file the module would be in, it couldn't be read
+    NotFound(PathBuf),
+    /// The names of the modules that import each other, starting and ending with the same one
+    Cycle(Vec<String>),
+    /// The module has errors in it, their locations are in the module's file
+    Invalid(Box<dyn ParserError>)
+}
+
+pub struct ImportError {
+    pub start: Loc,
+    pub strength: u8,
+    pub module: String,
+    pub problem: ImportProblem
+}
+
+impl CodeLocation for ImportError {
+    fn get_start(&self) -> Loc {
+        self.start
+    }
+}
+
+impl TreeDump for ImportError {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        write!(text, "{}({}): ", indent_style.repeat(indent), self.start).unwrap();
+        match &self.problem {
+            ImportProblem::NotFound(path) =>
+                writeln!(text, "Unknown module '{}', there's no file '{}'", self.module, path.display()).unwrap(),
+            ImportProblem::Cycle(modules) =>
+                writeln!(text, "Modules can't import each other, {}", modules.join(" imports ")).unwrap(),
+            ImportProblem::Invalid(cause) => {
+                writeln!(text, "The module '{}' has errors in it", self.module).unwrap();
+                cause.dump_with_indent(text, indent + 1, indent_style);
+            }
+        }
+    }
+}
+
+impl ParserError for ImportError {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
+        match &self.problem {
+            ImportProblem::Invalid(cause) => std::slice::from_ref(cause),
+            _ => &[]
+        }
     }
 
     fn get_strength(&self) -> u8 {
@@ -134,8 +527,8 @@ impl ParserError for AssignmentDataError {
 pub struct BlockError {
     pub start: Loc,
     pub strength: u8,
-    pub causes: Vec<Box<ParserError>>,
-    pub recover: Option<Box<SyntaxTreeNode>>
+    pub causes: Vec<Box<dyn ParserError>>,
+    pub recover: Option<Box<dyn SyntaxTreeNode>>
 }
 
 impl CodeLocation for BlockError {
@@ -145,16 +538,16 @@ impl CodeLocation for BlockError {
 }
 
 impl TreeDump for BlockError {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Invalid code block", indent_style.repeat(indent), self.start);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Invalid code block", indent_style.repeat(indent), self.start).unwrap();
         for cause in self.causes.iter() {
-            cause.print_with_indent(indent + 1, indent_style);
+            cause.dump_with_indent(text, indent + 1, indent_style);
         }
     }
 }
 
 impl ParserError for BlockError {
-    fn get_causes(&self) -> &[Box<ParserError>] {
+    fn get_causes(&self) -> &[Box<dyn ParserError>] {
         self.causes.as_slice()
     }
 
@@ -163,4 +556,4 @@ impl ParserError for BlockError {
     }
 }
 
-pub type ParseResult<T> = Result<T, Box<ParserError>>;
+pub type ParseResult<T> = Result<T, Box<dyn ParserError>>;
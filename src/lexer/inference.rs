@@ -0,0 +1,312 @@
+//! Type inference by unification, in the style of Hindley–Milner.
+//!
+//! Every node gets a ``Term`` when it's parsed, which may have type variables in it.
+//! Using values together unifies their terms, binding the variables, so a variable's type
+//! can be decided by code that comes after it. The nodes look their terms up in the
+//! ``Inference`` when they're compiled, by then everything that could bind them has been parsed.
+//!
+//! Integer literals are numbers that can be an int or a float, the way they're used decides.
+//! Numbers that nothing decides default to int.
+
+use super::Type;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TypeVar(u32);
+
+/// What a type variable can be bound to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Any,
+    /// An int or a float
+    Number
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Term {
+    Var(TypeVar),
+    /// An int, a float, a string, nil or a struct. The fields of a struct are declared with their types,
+    /// the other types that are made of others are terms of their own
+    Known(Type),
+    Array(Box<Term>),
+    /// Without ``returns`` the function doesn't give back a value
+    Function { args: Vec<Term>, returns: Option<Box<Term>> }
+}
+
+impl From<Type> for Term {
+    fn from(t: Type) -> Term {
+        match t {
+            Type::Array(element) => Term::Array(Box::new(Term::from(*element))),
+            Type::Function { args, returns } => Term::Function {
+                args: args.into_iter().map(Term::from).collect(),
+                returns: returns.map(|returns| Box::new(Term::from(*returns)))
+            },
+            t => Term::Known(t)
+        }
+    }
+}
+
+/// The type of a name. The variables in ``vars`` are made fresh every time the name is used,
+/// so a ``def`` can be used with different types. Variables have no ``vars``
+#[derive(Clone, PartialEq, Debug)]
+pub struct Scheme {
+    pub vars: Vec<TypeVar>,
+    pub term: Term
+}
+
+impl Scheme {
+    pub fn mono(term: Term) -> Scheme {
+        Scheme {
+            vars: Vec::new(),
+            term: term
+        }
+    }
+}
+
+/// Two terms that can't be unified, with the variables in them resolved
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mismatch {
+    pub expected: Term,
+    pub found: Term
+}
+
+#[derive(Clone)]
+enum Binding {
+    Unbound(Kind),
+    Bound(Term)
+}
+
+/// The type variables and what they're bound to
+#[derive(Clone)]
+pub struct Inference {
+    bindings: Vec<Binding>
+}
+
+impl Inference {
+    pub fn new() -> Inference {
+        Inference {
+            bindings: Vec::new()
+        }
+    }
+
+    pub fn fresh(&mut self, kind: Kind) -> Term {
+        self.bindings.push(Binding::Unbound(kind));
+        Term::Var(TypeVar((self.bindings.len() - 1) as u32))
+    }
+
+    /// Follows the variables in the term to what they're bound to, all the way down
+    pub fn resolve(&self, term: &Term) -> Term {
+        match term {
+            Term::Var(var) => match &self.bindings[var.0 as usize] {
+                Binding::Bound(bound) => self.resolve(bound),
+                Binding::Unbound(_) => term.clone()
+            },
+            Term::Known(_) => term.clone(),
+            Term::Array(element) => Term::Array(Box::new(self.resolve(element))),
+            Term::Function { args, returns } => Term::Function {
+                args: args.iter().map(|arg| self.resolve(arg)).collect(),
+                returns: returns.as_ref().map(|returns| Box::new(self.resolve(returns)))
+            }
+        }
+    }
+
+    /// Only follows the variable at the top of the term
+    fn shallow_resolve(&self, term: &Term) -> Term {
+        match term {
+            Term::Var(var) => match &self.bindings[var.0 as usize] {
+                Binding::Bound(bound) => self.shallow_resolve(bound),
+                Binding::Unbound(_) => term.clone()
+            },
+            _ => term.clone()
+        }
+    }
+
+    fn get_kind(&self, var: TypeVar) -> Kind {
+        match self.bindings[var.0 as usize] {
+            Binding::Unbound(kind) => kind,
+            Binding::Bound(_) => panic!("Expected an unbound type variable")
+        }
+    }
+
+    /// Makes the two terms the same type, binding the variables in them
+    pub fn unify(&mut self, expected: &Term, found: &Term) -> Result<(), Mismatch> {
+        let mismatch = |inference: &Inference| Mismatch {
+            expected: inference.resolve(expected),
+            found: inference.resolve(found)
+        };
+
+        match (self.shallow_resolve(expected), self.shallow_resolve(found)) {
+            (Term::Var(a), Term::Var(b)) if a == b => Ok(()),
+            (Term::Var(a), Term::Var(b)) => {
+                // The variable that's left keeps the stricter kind
+                let kind = match (self.get_kind(a), self.get_kind(b)) {
+                    (Kind::Any, Kind::Any) => Kind::Any,
+                    _ => Kind::Number
+                };
+                self.bindings[b.0 as usize] = Binding::Unbound(kind);
+                self.bindings[a.0 as usize] = Binding::Bound(Term::Var(b));
+                Ok(())
+            },
+            (Term::Var(var), other) | (other, Term::Var(var)) => {
+                if !self.fits_kind(&other, self.get_kind(var)) || self.occurs(var, &other) {
+                    return Err(mismatch(self));
+                }
+                self.bindings[var.0 as usize] = Binding::Bound(other);
+                Ok(())
+            },
+            (Term::Known(a), Term::Known(b)) if a == b => Ok(()),
+            (Term::Array(expected_element), Term::Array(found_element)) =>
+                self.unify(&expected_element, &found_element).map_err(|_| mismatch(self)),
+            (Term::Function { args: expected_args, returns: expected_returns },
+                    Term::Function { args: found_args, returns: found_returns }) => {
+                if expected_args.len() != found_args.len() || expected_returns.is_some() != found_returns.is_some() {
+                    return Err(mismatch(self));
+                }
+                for (expected_arg, found_arg) in expected_args.iter().zip(found_args.iter()) {
+                    self.unify(expected_arg, found_arg).map_err(|_| mismatch(self))?;
+                }
+                if let (Some(expected_returns), Some(found_returns)) = (expected_returns, found_returns) {
+                    self.unify(&expected_returns, &found_returns).map_err(|_| mismatch(self))?;
+                }
+                Ok(())
+            },
+            _ => Err(mismatch(self))
+        }
+    }
+
+    /// Like ``unify``, but an int can be stored where a float is expected, it's converted when it's compiled
+    pub fn coerce(&mut self, value: &Term, target: &Term) -> Result<(), Mismatch> {
+        match (self.shallow_resolve(value), self.shallow_resolve(target)) {
+            (Term::Known(Type::Int), Term::Known(Type::Float)) => Ok(()),
+            _ => self.unify(target, value)
+        }
+    }
+
+    /// Whether ``coerce`` would accept a value of the term where a ``target`` is expected
+    pub fn can_coerce(&self, value: &Term, target: &Type) -> bool {
+        match (self.shallow_resolve(value), target) {
+            (Term::Known(Type::Int), Type::Float) => true,
+            (Term::Known(t), target) => t == *target,
+            (Term::Var(var), target) => self.fits_kind(&Term::from(target.clone()), self.get_kind(var)),
+            // Arrays and functions only fit if all of their parts do
+            (value, target) => self.clone().unify(&Term::from(target.clone()), &value).is_ok()
+        }
+    }
+
+    fn fits_kind(&self, term: &Term, kind: Kind) -> bool {
+        match kind {
+            Kind::Any => true,
+            Kind::Number => matches!(term, Term::Known(Type::Int) | Term::Known(Type::Float))
+        }
+    }
+
+    fn occurs(&self, var: TypeVar, term: &Term) -> bool {
+        self.free_vars(term).contains(&var)
+    }
+
+    /// The unbound variables in the term
+    pub fn free_vars(&self, term: &Term) -> Vec<TypeVar> {
+        fn collect(inference: &Inference, term: &Term, vars: &mut Vec<TypeVar>) {
+            match inference.shallow_resolve(term) {
+                Term::Var(var) => if !vars.contains(&var) {
+                    vars.push(var);
+                },
+                Term::Known(_) => {},
+                Term::Array(element) => collect(inference, &element, vars),
+                Term::Function { args, returns } => {
+                    for arg in args.iter() {
+                        collect(inference, arg, vars);
+                    }
+                    if let Some(returns) = returns {
+                        collect(inference, &returns, vars);
+                    }
+                }
+            }
+        }
+
+        let mut vars = Vec::new();
+        collect(self, term, &mut vars);
+        vars
+    }
+
+    /// Binds the numbers in the term that nothing decided to int
+    pub fn default_numbers(&mut self, term: &Term) {
+        for var in self.free_vars(term) {
+            if self.get_kind(var) == Kind::Number {
+                self.bindings[var.0 as usize] = Binding::Bound(Term::Known(Type::Int));
+            }
+        }
+    }
+
+    /// The scheme of a ``def``, the variables that aren't in ``environment`` can be different
+    /// every time it's used. Numbers are defaulted first, since a value has only one representation
+    pub fn generalize(&mut self, term: &Term, environment: &[TypeVar]) -> Scheme {
+        self.default_numbers(term);
+        Scheme {
+            vars: self.free_vars(term).into_iter().filter(|var| !environment.contains(var)).collect(),
+            term: self.resolve(term)
+        }
+    }
+
+    /// The term of a use of the scheme, with fresh variables for the ones it generalizes
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Term {
+        fn replace(term: &Term, fresh: &[(TypeVar, Term)]) -> Term {
+            match term {
+                Term::Var(var) => match fresh.iter().find(|(old, _)| old == var) {
+                    Some((_, new)) => new.clone(),
+                    None => term.clone()
+                },
+                Term::Known(_) => term.clone(),
+                Term::Array(element) => Term::Array(Box::new(replace(element, fresh))),
+                Term::Function { args, returns } => Term::Function {
+                    args: args.iter().map(|arg| replace(arg, fresh)).collect(),
+                    returns: returns.as_ref().map(|returns| Box::new(replace(returns, fresh)))
+                }
+            }
+        }
+
+        if scheme.vars.is_empty() {
+            return scheme.term.clone();
+        }
+        let fresh: Vec<(TypeVar, Term)> = scheme.vars.iter()
+            .map(|var| (*var, self.fresh(self.get_kind(*var))))
+            .collect();
+        replace(&self.resolve(&scheme.term), &fresh[..])
+    }
+
+    /// The type a value of the term has when the program runs. Numbers that nothing decided are ints,
+    /// None if there's a variable in it that could still be anything
+    pub fn get_type(&self, term: &Term) -> Option<Type> {
+        match self.shallow_resolve(term) {
+            Term::Known(t) => Some(t),
+            Term::Var(var) if self.get_kind(var) == Kind::Number => Some(Type::Int),
+            Term::Var(_) => None,
+            Term::Array(element) => Some(Type::Array(Box::new(self.get_type(&element)?))),
+            Term::Function { args, returns } => Some(Type::Function {
+                args: args.iter().map(|arg| self.get_type(arg)).collect::<Option<Vec<Type>>>()?,
+                returns: match returns {
+                    Some(returns) => Some(Box::new(self.get_type(&returns)?)),
+                    None => None
+                }
+            })
+        }
+    }
+
+    /// The term as it's written in messages, like ``int`` or ``func[number] -> string``
+    pub fn describe(&self, term: &Term) -> String {
+        match self.shallow_resolve(term) {
+            Term::Known(t) => format!("{}", t),
+            Term::Array(element) => format!("[{}]", self.describe(&element)),
+            Term::Var(var) => match self.get_kind(var) {
+                Kind::Number => String::from("number"),
+                Kind::Any => format!("'t{}", var.0)
+            },
+            Term::Function { args, returns } => {
+                let args: Vec<String> = args.iter().map(|arg| self.describe(arg)).collect();
+                match returns {
+                    Some(returns) => format!("func[{}] -> {}", args.join(", "), self.describe(&returns)),
+                    None => format!("func[{}]", args.join(", "))
+                }
+            }
+        }
+    }
+}
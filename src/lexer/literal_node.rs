@@ -1,9 +1,14 @@
-use super::{ CodeLocation, TreeDump, SyntaxTreeNode, Loc, ScopeHandle, ScopePool, TypeCollection, Type };
+use std::fmt::Write;
+use super::{ CodeLocation, TreeDump, SyntaxTreeNode, Loc, ScopeHandle, ScopePool, Term, Type };
 use super::super::tokenizer::LiteralType;
+use super::super::compiler::Compiler;
+use super::super::vm::{ Instruction, Constant };
 
 pub struct LiteralNode {
     pub start: Loc,
-    pub literal: LiteralType
+    pub literal: LiteralType,
+    /// Integers are a number variable, they can turn out to be floats
+    pub term: Term
 }
 
 impl CodeLocation for LiteralNode {
@@ -11,18 +16,32 @@ impl CodeLocation for LiteralNode {
 }
 
 impl TreeDump for LiteralNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): literal {}", indent_style.repeat(indent), self.start, self.literal);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): literal {}", indent_style.repeat(indent), self.start, self.literal).unwrap();
     }   
 }
 
 impl SyntaxTreeNode for LiteralNode {
-    fn get_possible_returns(&self, scope: ScopeHandle, scopes: &ScopePool) -> TypeCollection {
+    fn get_term(&self) -> Term {
+        self.term.clone()
+    }
+
+    fn compile(&self, _scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
         use LiteralType::*;
-        match self.literal {
-            _String(_) => TypeCollection::from(vec![Type::Str]),
-            Integer(_) => TypeCollection::from(vec![Type::Int, Type::Float]),
-            Float(_) => TypeCollection::from(vec![Type::Float])
+        match &self.literal {
+            _String(string) => {
+                let index = compiler.add_constant(Constant::Str(string.clone()));
+                compiler.emit_with(Instruction::PushConstant, index, self.start);
+            },
+            Integer(value) if self.get_type(scopes) == Some(Type::Float) => {
+                let index = compiler.add_constant(Constant::Float(*value as f64));
+                compiler.emit_with(Instruction::PushConstant, index, self.start);
+            },
+            Integer(value) => compiler.emit_with(Instruction::PushInt, *value as i64, self.start),
+            Float(value) => {
+                let index = compiler.add_constant(Constant::Float(*value));
+                compiler.emit_with(Instruction::PushConstant, index, self.start);
+            }
         }
     }
 }
@@ -1,21 +1,35 @@
+use std::fmt::Write;
 use super::tokenizer::{ Token };
 use super::tokenizer;
 pub use super::needle::{ Needle, Loc, TextMetaData };
 use super::TreeDump;
-use std::collections::HashMap;
+use super::compiler::Compiler;
+use super::vm::Instruction;
+use super::vm::natives::NativeSignature;
+use std::path::Path;
 
 mod type_handler;
+mod inference;
 mod errors;
-pub use type_handler::{ Type, TypeCollection, ScopePool, ScopeHandle };
-pub use errors::{ BlockError, LiteralError, AssignmentDataError };
+pub use type_handler::{ Type, ScopePool, ScopeHandle };
+pub use inference::{ Inference, Term, TypeVar, Kind, Scheme, Mismatch };
+pub use errors::{ BlockError, LiteralError, AssignmentDataError, CallError, CallProblem, ImportError, ImportProblem, TypeConflictError, DefinitionAssignedError, UnknownDefinitionError, AnnotationError, FieldError, FieldProblem };
 use errors::SimpleError::*;
 pub use errors::SimpleError;
 pub use errors::ParseResult;
+pub use errors::ParserError;
 
 mod block_node;
 mod literal_node;
 mod assignment_node;
+mod call_node;
+mod module_node;
+mod struct_node;
+pub use module_node::{ Module, ModuleLoader };
+use module_node::{ ImportNode, MemberNode };
+use struct_node::{ StructNode, ConstructionNode, FieldNode, FieldAssignmentNode };
 use block_node::BlockNode;
+use call_node::CallNode;
 use assignment_node::AssignmentNode;
 use literal_node::LiteralNode;
 
@@ -24,13 +38,36 @@ pub trait CodeLocation {
 }
 
 pub trait SyntaxTreeNode: CodeLocation + TreeDump {
-    fn get_possible_returns(&self, scope: ScopeHandle, scopes: &ScopePool) -> TypeCollection {
-        TypeCollection::from(Vec::new())
+    /// The type of the value the node results in, as it was inferred when it was parsed.
+    /// Nodes that are only there for what they do, like assignments, result in nil
+    fn get_term(&self) -> Term {
+        Term::Known(Type::Nil)
     }
+
+    /// The type of the value when the program runs, once every node is parsed
+    fn get_type(&self, scopes: &ScopePool) -> Option<Type> {
+        scopes.types.get_type(&self.get_term())
+    }
+
+    /// Whether the node's code leaves a value on the stack, which it doesn't if it results in nil
+    fn has_value(&self, scopes: &ScopePool) -> bool {
+        self.get_type(scopes) != Some(Type::Nil)
+    }
+
+    /// The scope of the block the node is, None for anything other than a block
+    fn get_block_scope(&self) -> Option<ScopeHandle> {
+        None
+    }
+
+    /// Emits the instructions for the node. If the node ``has_value``,
+    /// the code has to leave exactly one value on the stack, otherwise none.
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler);
 }
 
+/// Stands in for code that has errors, as what the code after it can use it as
 pub struct ErrorNode {
-    start: Loc
+    start: Loc,
+    term: Term
 }
 
 impl CodeLocation for ErrorNode {
@@ -38,12 +75,20 @@ impl CodeLocation for ErrorNode {
 }
 
 impl TreeDump for ErrorNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Error", indent_style.repeat(indent), self.start);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Error", indent_style.repeat(indent), self.start).unwrap();
     }
 }
 
-impl SyntaxTreeNode for ErrorNode {}
+impl SyntaxTreeNode for ErrorNode {
+    fn get_term(&self) -> Term {
+        self.term.clone()
+    }
+
+    fn compile(&self, _scope: ScopeHandle, _scopes: &ScopePool, _compiler: &mut Compiler) {
+        panic!("Cannot compile a syntax tree with errors in it");
+    }
+}
 
 pub struct NilNode {
     start: Loc
@@ -54,30 +99,34 @@ impl CodeLocation for NilNode {
 }
 
 impl TreeDump for NilNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Nil", indent_style.repeat(indent), self.start);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Nil", indent_style.repeat(indent), self.start).unwrap();
     }
 }
 
-impl SyntaxTreeNode for NilNode {}
+impl SyntaxTreeNode for NilNode {
+    fn compile(&self, _scope: ScopeHandle, _scopes: &ScopePool, _compiler: &mut Compiler) {}
+}
 
 pub struct VariableNode {
     start: Loc,
-    identifier: String
+    identifier: String,
+    term: Term
 }
 
 impl VariableNode {
-    fn new(start: Loc, identifier: String) -> VariableNode {
+    fn new(start: Loc, identifier: String, term: Term) -> VariableNode {
         VariableNode {
             start: start,
-            identifier: identifier
+            identifier: identifier,
+            term: term
         }
     }
 }
 
 impl TreeDump for VariableNode {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}({}): Variable '{}'", indent_style.repeat(indent), self.start, self.identifier);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Variable '{}'", indent_style.repeat(indent), self.start, self.identifier).unwrap();
     }
 }
 
@@ -86,23 +135,45 @@ impl CodeLocation for VariableNode {
 }
 
 impl SyntaxTreeNode for VariableNode {
-    fn get_possible_returns(&self, scope: ScopeHandle, scopes: &ScopePool) -> TypeCollection {
-        scope.get(scopes, &self.identifier[..])
-            .expect("A VariableNode's variable name does not fit the scope")
-            .clone()
+    fn get_term(&self) -> Term {
+        self.term.clone()
+    }
+
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        if !self.has_value(scopes) {
+            return;
+        }
+        let slot = compiler.get_local(scope, scopes, &self.identifier[..]);
+        compiler.emit_with(Instruction::LoadLocal, slot, self.start);
     }
 }
 
-fn parse_variable(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &ScopePool)
-        -> ParseResult<Box<SyntaxTreeNode>> {
+fn parse_variable(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
     let next = match tokens.read() {
-        Some(token) => token,
+        Some(token) => token.clone(),
         None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 0)))
     };
 
     if let tokenizer::TokenType::Identifier(string) = &next.token_type {
-        if scope.get(scopes, &string[..]).is_some() {
-            Ok(Box::new(VariableNode::new(next.start, string.clone())))
+        // A variable with a dot after it is getting a field, that's left to ``parse_value``
+        let is_variable = scope.get(scopes, &string[..]).is_some() && scope.get_module(scopes, &string[..]).is_none();
+        if !is_variable && tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::Dot)) {
+            // Only a name that isn't anything else can be a module that wasn't imported
+            if scope.get_module(scopes, &string[..]).is_none() && scope.get_struct(scopes, &string[..]).is_some() {
+                tokens.next();
+                if tokens.match_func_offset(0, |t| matches!(t.token_type, tokenizer::TokenType::Identifier(_))) {
+                    tokens.next();
+                }
+                return Err(Box::new(SimpleError::InvalidVariableName(next.start, 3)));
+            }
+            tokens.next();
+            return parse_member(tokens, meta, next.start, string.clone(), scope, scopes);
+        }
+
+        if let Some(scheme) = scope.get(scopes, &string[..]).cloned() {
+            let term = scopes.types.instantiate(&scheme);
+            Ok(Box::new(VariableNode::new(next.start, string.clone(), term)))
         }else{
             Err(Box::new(SimpleError::InvalidVariableName(next.start, 1)))
         }
@@ -111,8 +182,270 @@ fn parse_variable(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeH
     }
 }
 
+/// The part after the dot of ``module.name``
+fn parse_member(tokens: &mut Needle<Token>, meta: &TextMetaData, start: Loc, module_name: String,
+        scope: ScopeHandle, scopes: &mut ScopePool) -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let next = match tokens.read() {
+        Some(token) => token,
+        None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 3)))
+    };
+    let name = match &next.token_type {
+        tokenizer::TokenType::Identifier(name) => name.clone(),
+        _ => return Err(Box::new(SimpleError::ExpectedIdentifier(next.start, 3)))
+    };
+    let name_start = next.start;
+    // The name is read first, so the code after it doesn't cause more errors
+    let module = match scope.get_module(scopes, &module_name[..]) {
+        Some(module) => module,
+        // The import's error is the only one, the member could be anything
+        None if scope.has_failed_import(scopes, &module_name[..]) => return Ok(Box::new(ErrorNode {
+            start: start,
+            term: scopes.types.fresh(Kind::Any)
+        })),
+        None => return Err(Box::new(SimpleError::UnknownModule(start)))
+    };
+    let scheme = match scopes.modules.get(module).scope.get(scopes, &name[..]) {
+        Some(scheme) => scheme.clone(),
+        None => return Err(Box::new(UnknownDefinitionError {
+            start: name_start,
+            module: module_name,
+            name: name
+        }))
+    };
+
+    Ok(Box::new(MemberNode {
+        start: start,
+        module_name: module_name,
+        module: module,
+        name: name,
+        term: scopes.types.instantiate(&scheme)
+    }))
+}
+
+fn parse_import(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let start = match tokens.read() {
+        Some(token) if token.is_keyword(tokenizer::KeywordType::Import) => token.start,
+        Some(token) => return Err(Box::new(SimpleError::ExpectedImport(token.start))),
+        None => return Err(Box::new(SimpleError::ExpectedImport(meta.get_end())))
+    };
+
+    let name = match tokens.read() {
+        Some(token) => match &token.token_type {
+            tokenizer::TokenType::Identifier(name) => name.clone(),
+            _ => return Err(Box::new(SimpleError::ExpectedIdentifier(token.start, 3)))
+        },
+        None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 3)))
+    };
+
+    let module = match load_module(start, &name[..], scopes) {
+        Ok(module) => module,
+        Err(error) => {
+            scope.insert_failed_import(scopes, &name[..]);
+            return Err(error);
+        }
+    };
+    scope.insert_module(scopes, &name[..], module);
+    Ok(Box::new(ImportNode {
+        start: start,
+        name: name,
+        module: module
+    }))
+}
+
+/// Parses the module the first time it's imported, and gives back its index in the ``ModuleLoader``
+fn load_module(start: Loc, name: &str, scopes: &mut ScopePool) -> ParseResult<usize> {
+    let path = scopes.modules.resolve(name);
+    load_module_file(start, name, &path, scopes)
+}
+
+fn load_module_file(start: Loc, name: &str, path: &Path, scopes: &mut ScopePool) -> ParseResult<usize> {
+    let error = |problem| -> Box<dyn ParserError> { Box::new(ImportError {
+        start: start,
+        strength: 4,
+        module: String::from(name),
+        problem: problem
+    }) };
+
+    let (path, code) = match std::fs::canonicalize(path).and_then(|full_path| {
+        std::fs::read_to_string(&full_path).map(|code| (full_path, code))
+    }) {
+        Ok(file) => file,
+        Err(_) => return Err(error(ImportProblem::NotFound(path.to_path_buf())))
+    };
+    if let Some(cycle) = scopes.modules.find_cycle(&path, name) {
+        return Err(error(ImportProblem::Cycle(cycle)));
+    }
+    if let Some(module) = scopes.modules.find(&path) {
+        return Ok(module);
+    }
+
+    let (tokens, _, meta) = tokenizer::tokenize(&code[..]);
+    let module_scope = scopes.create_scope();
+    scopes.modules.start_loading(path.clone(), name);
+    let body = parse_module(&mut Needle::new(tokens, 0), &meta, module_scope, scopes);
+    scopes.modules.finish_loading();
+
+    let body = body.map_err(|cause| error(ImportProblem::Invalid(cause)))?;
+    Ok(scopes.modules.add(Module {
+        name: String::from(name),
+        path: path,
+        scope: module_scope,
+        body: std::rc::Rc::new(body)
+    }))
+}
+
+/// Parses a module file on its own, as if the scope imports it.
+/// Compiling the node evaluates the ``def``s of the module
+pub fn parse_module_file(path: &Path, scope: ScopeHandle, scopes: &mut ScopePool) -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let name = path.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let start = Loc::new(0, 0);
+    let module = load_module_file(start, &name[..], path, scopes)?;
+    scope.insert_module(scopes, &name[..], module);
+    Ok(Box::new(ImportNode {
+        start: start,
+        name: name,
+        module: module
+    }))
+}
+
+/// A module is ``def name = value`` and ``import name`` elements, separated by ';'
+pub fn parse_module(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Vec<Box<dyn SyntaxTreeNode>>> {
+    use tokenizer::KeywordType;
+
+    let mut body = Vec::new();
+    while let Some(token) = tokens.peek() {
+        if token.is_keyword(KeywordType::Def) {
+            tokens.next();
+            body.push(parse_definition(tokens, meta, scope, scopes)?);
+        }else if token.is_keyword(KeywordType::Import) {
+            body.push(parse_import(tokens, meta, scope, scopes)?);
+        }else{
+            return Err(Box::new(SimpleError::ExpectedDefinition(token.start)));
+        }
+
+        match tokens.read() {
+            Some(token) if token.is_keyword(KeywordType::BlockSeparator) => {},
+            Some(token) => return Err(Box::new(SimpleError::ExpectedSeparator(token.start))),
+            None => break
+        }
+    }
+
+    Ok(body)
+}
+
+/// Parses code that is a single value, like a file or an input in the shell, so nothing can come after it
+pub fn parse_program(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let tree = parse_value(tokens, meta, scope, scopes)?;
+    match tokens.peek() {
+        Some(token) => Err(Box::new(SimpleError::ExpectedEnd(token.start))),
+        None => Ok(tree)
+    }
+}
+
+/// A ``def`` is an assignment whose type is generalized, so every use of it gets its own copy
+fn parse_definition(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let name = match tokens.peek().map(|token| &token.token_type) {
+        Some(tokenizer::TokenType::Identifier(name)) => name.clone(),
+        _ => return parse_assignment(tokens, meta, scope, scopes)
+    };
+    if tokens.match_func_offset(1, |t| t.is_keyword(tokenizer::KeywordType::Assign))
+            && tokens.match_func_offset(2, |t| matches!(&t.token_type, tokenizer::TokenType::Identifier(word) if word == "struct")) {
+        return parse_struct(tokens, meta, scope, scopes);
+    }
+
+    let assignment = parse_assignment(tokens, meta, scope, scopes)?;
+    let declaring_scope = scope.get_declaring_scope(scopes, &name[..])
+        .expect("A definition's name is not in the scope after it's assigned");
+    let term = declaring_scope.get(scopes, &name[..]).unwrap().term.clone();
+    let environment = declaring_scope.get_environment(scopes, &name[..]);
+    let scheme = scopes.types.generalize(&term, &environment[..]);
+    declaring_scope.insert(scopes, &name[..], scheme);
+    declaring_scope.define(scopes, &name[..], assignment.get_start());
+    Ok(assignment)
+}
+
+/// ``def`` in code, the same as in a module
+fn parse_def(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    match tokens.read() {
+        Some(token) if token.is_keyword(tokenizer::KeywordType::Def) => parse_definition(tokens, meta, scope, scopes),
+        Some(token) => Err(Box::new(SimpleError::ExpectedDef(token.start))),
+        None => Err(Box::new(SimpleError::ExpectedDef(meta.get_end())))
+    }
+}
+
+/// ``name = struct [field: Type, ...]``, after the ``def``. The struct can be used from here on
+fn parse_struct(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    use tokenizer::KeywordType;
+
+    let next = tokens.read().expect("A struct has to start with its name");
+    let start = next.start;
+    let name = match &next.token_type {
+        tokenizer::TokenType::Identifier(name) => name.clone(),
+        _ => panic!("A struct has to start with its name")
+    };
+    // The '=' and 'struct'
+    tokens.skip(2);
+    if scope.declares_struct(scopes, &name[..]) {
+        return Err(Box::new(SimpleError::StructExists(start)));
+    }
+
+    match tokens.read() {
+        Some(token) if token.is_keyword(KeywordType::ArrayOpen) => {},
+        Some(token) => return Err(Box::new(SimpleError::ExpectedFields(token.start))),
+        None => return Err(Box::new(SimpleError::ExpectedFields(meta.get_end())))
+    }
+    let mut fields: Vec<(String, Type)> = Vec::new();
+    if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayClose)) {
+        tokens.next();
+    }else{
+        loop {
+            let (field_start, field) = match tokens.read() {
+                Some(token) => match &token.token_type {
+                    tokenizer::TokenType::Identifier(field) => (token.start, field.clone()),
+                    _ => return Err(Box::new(SimpleError::ExpectedIdentifier(token.start, 3)))
+                },
+                None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 3)))
+            };
+            if fields.iter().any(|(name, _)| *name == field) {
+                return Err(Box::new(SimpleError::RepeatedField(field_start)));
+            }
+            match tokens.read() {
+                Some(token) if token.is_keyword(KeywordType::Colon) => {},
+                Some(token) => return Err(Box::new(SimpleError::ExpectedColon(token.start))),
+                None => return Err(Box::new(SimpleError::ExpectedColon(meta.get_end())))
+            }
+            let field_type = parse_type(tokens, meta, scope, scopes)?;
+            if field_type == Type::Nil {
+                return Err(Box::new(SimpleError::NilField(field_start)));
+            }
+            fields.push((field, field_type));
+
+            match tokens.read() {
+                Some(token) if token.is_keyword(KeywordType::ArraySeparator) => {},
+                Some(token) if token.is_keyword(KeywordType::ArrayClose) => break,
+                Some(token) => return Err(Box::new(ExpectedArrayClose(token.start))),
+                None => return Err(Box::new(ExpectedArrayClose(meta.get_end())))
+            }
+        }
+    }
+
+    let struct_type = Type::Struct { name: name.clone(), fields: fields };
+    scope.insert_struct(scopes, &name[..], struct_type.clone());
+    scope.define(scopes, &name[..], start);
+    Ok(Box::new(StructNode {
+        start: start,
+        struct_type: struct_type
+    }))
+}
+
 fn parse_assignment(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool) 
-        -> ParseResult<Box<SyntaxTreeNode>> {
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
     // Identifier
     let next = match tokens.read() {
         Some(token) => token,
@@ -125,34 +458,105 @@ fn parse_assignment(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: Scop
         _ => return Err(Box::new(SimpleError::ExpectedIdentifier(next.start, 0)))
     }.clone();
 
+    // Fields, to assign a field of a struct
+    let mut path = Vec::new();
+    while tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::Dot)) {
+        tokens.next();
+        match tokens.read() {
+            Some(token) => match &token.token_type {
+                tokenizer::TokenType::Identifier(field) => path.push((token.start, field.clone())),
+                _ => return Err(Box::new(SimpleError::ExpectedIdentifier(token.start, 0)))
+            },
+            None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 0)))
+        }
+    }
+
+    // Annotation
+    let annotation = if path.is_empty() && tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::Colon)) {
+        tokens.next();
+        match parse_type(tokens, meta, scope, scopes) {
+            Ok(annotation) => Some(annotation),
+            Err(error) => return Err(Box::new(AssignmentDataError {
+                start: start,
+                strength: 3,
+                cause: error,
+                var_name: identifier
+            }))
+        }
+    }else{
+        None
+    };
+    // Nothing else has a colon after a name, so an annotated name has to be an assignment
+    let strength = if annotation.is_some() { 3 } else { 0 };
+
     // Equals
     let next = match tokens.read() {
         Some(token) => token,
-        None => return Err(Box::new(SimpleError::ExpectedEquals(meta.get_end(), 0)))
+        None => return Err(Box::new(SimpleError::ExpectedEquals(meta.get_end(), strength)))
     };
     if !next.is_keyword(tokenizer::KeywordType::Assign) {
-        return Err(Box::new(SimpleError::ExpectedEquals(next.start, 0)));
+        return Err(Box::new(SimpleError::ExpectedEquals(next.start, strength)));
+    }
+    if !path.is_empty() {
+        return parse_field_assignment(tokens, meta, start, identifier, path, scope, scopes);
     }
 
     // Assign to
+    let constructed = peek_construction(tokens, scope, scopes);
     let data = parse_value(tokens, meta, scope, scopes);
     match data {
         Ok(data) => {
-            let possible_returns = data.get_possible_returns(scope, scopes);
-            if possible_returns.is_undef() {
-                return Err(Box::new(AssignmentDataError {
+            let term = data.get_term();
+
+            if let Some(annotation) = &annotation {
+                let annotation_term = Term::from(annotation.clone());
+                if scopes.types.coerce(&term, &annotation_term).is_err() {
+                    // The code after it can still use the variable as what it's annotated as
+                    if scope.get(scopes, &identifier[..]).is_none() {
+                        scope.declare(scopes, &identifier[..], Scheme::mono(annotation_term), start);
+                    }
+                    return Err(Box::new(AnnotationError {
+                        start: start,
+                        annotation: format!("{}", annotation),
+                        found: scopes.types.describe(&term),
+                        var_name: identifier
+                    }));
+                }
+            }
+
+            // Defs and structs keep what they were defined as
+            let definition = scope.get_definition(scopes, &identifier[..]);
+            if definition.is_some() || scope.get_struct(scopes, &identifier[..]).is_some() {
+                return Err(Box::new(DefinitionAssignedError {
                     start: start,
-                    strength: 3,
-                    cause: Box::new(SimpleError::ExpectedExpression(data.get_start(), 3)),
-                    var_name: identifier
+                    var_name: identifier,
+                    defined: definition
                 }));
             }
 
-            if scope.get(scopes, &identifier[..]).is_none() {
-                scope.insert(scopes, &identifier[..], possible_returns);
-            }else{
-                let element = scope.get_mut(scopes, &identifier[..]).unwrap();
-                element.constrain(&possible_returns);
+            match scope.get(scopes, &identifier[..]).cloned() {
+                None => {
+                    // The variable has the annotated type, even if the value is an int that's converted
+                    let var_term = annotation.map(Term::from).unwrap_or(term);
+                    scope.declare(scopes, &identifier[..], Scheme::mono(var_term), start);
+                },
+                Some(scheme) => {
+                    let var_term = scopes.types.instantiate(&scheme);
+                    // An annotation has to be the variable's type exactly, the value was already converted to it
+                    let checked = match annotation {
+                        Some(annotation) => scopes.types.unify(&var_term, &Term::from(annotation)),
+                        None => scopes.types.coerce(&term, &var_term)
+                    };
+                    if let Err(mismatch) = checked {
+                        return Err(Box::new(TypeConflictError {
+                            start: start,
+                            declared: scope.get_declaration(scopes, &identifier[..]),
+                            var_type: scopes.types.describe(&mismatch.expected),
+                            value_type: scopes.types.describe(&mismatch.found),
+                            var_name: identifier
+                        }));
+                    }
+                }
             }
             Ok(Box::new(AssignmentNode {
                 start: start,
@@ -160,12 +564,20 @@ fn parse_assignment(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: Scop
                 data: data
             }))
         },
-        Err(error) => Err(Box::new(AssignmentDataError {
-            start: start,
-            strength: 3,
-            cause: error,
-            var_name: identifier
-        }))
+        Err(error) => {
+            // A construction results in its struct even if its fields are wrong, so the code after it can use the variable
+            if let Some(struct_type) = constructed {
+                if scope.get(scopes, &identifier[..]).is_none() {
+                    scope.declare(scopes, &identifier[..], Scheme::mono(Term::Known(struct_type)), start);
+                }
+            }
+            Err(Box::new(AssignmentDataError {
+                start: start,
+                strength: 3,
+                cause: error,
+                var_name: identifier
+            }))
+        }
     } 
 
     
@@ -181,97 +593,516 @@ fn parse_assignment(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: Scop
     
 }
 
-fn parse_literal(tokens: &mut Needle<Token>, meta: &TextMetaData) -> ParseResult<Box<SyntaxTreeNode>> {
-    if let Some(token) = tokens.read() {
-        if let Some(literal) = token.as_literal() {
-            Ok(Box::new(LiteralNode { start: token.start, literal: literal }))
-        }else {
-            Err(Box::new(LiteralError::new(token.start)))
-        }
-    }else{
-        Err(Box::new(LiteralError::new(meta.get_end())))
+/// The rest of ``name.field = value``, after the '='
+fn parse_field_assignment(tokens: &mut Needle<Token>, meta: &TextMetaData, start: Loc, identifier: String,
+        mut path: Vec<(Loc, String)>, scope: ScopeHandle, scopes: &mut ScopePool) -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let var_name = path.iter().fold(identifier.clone(), |name, (_, field)| format!("{}.{}", name, field));
+    let target: Box<dyn SyntaxTreeNode> = match (scope.get(scopes, &identifier[..]).cloned(), scope.get_module(scopes, &identifier[..])) {
+        (_, Some(module)) => {
+            // The fields of a module's def, the def itself can't be assigned
+            let (member_start, name) = path.remove(0);
+            let scheme = match scopes.modules.get(module).scope.get(scopes, &name[..]) {
+                Some(scheme) if !path.is_empty() => scheme.clone(),
+                Some(_) => return Err(Box::new(SimpleError::InvalidVariableName(start, 3))),
+                None => return Err(Box::new(UnknownDefinitionError {
+                    start: member_start,
+                    module: identifier,
+                    name: name
+                }))
+            };
+            Box::new(MemberNode {
+                start: start,
+                module_name: identifier,
+                module: module,
+                name: name,
+                term: scopes.types.instantiate(&scheme)
+            })
+        },
+        (Some(scheme), None) => {
+            let term = scopes.types.instantiate(&scheme);
+            Box::new(VariableNode::new(start, identifier, term))
+        },
+        (None, None) if scope.has_failed_import(scopes, &identifier[..]) => {
+            // Only the import reports an error, the value is read so the code after it doesn't either
+            parse_value(tokens, meta, scope, scopes)?;
+            return Ok(Box::new(ErrorNode { start: start, term: Term::Known(Type::Nil) }));
+        },
+        (None, None) => return Err(Box::new(SimpleError::InvalidVariableName(start, 3)))
+    };
+    let (field_start, field) = path.pop().expect("A field assignment has to have a field");
+    let found = path.into_iter()
+        .try_fold(target, |target, (loc, name)| -> ParseResult<Box<dyn SyntaxTreeNode>> {
+            Ok(Box::new(get_field(target, loc, name, scopes)?))
+        })
+        .and_then(|target| get_field(target, field_start, field, scopes));
+
+    // The value is read even if the field is wrong, so the code after it doesn't cause more errors
+    let data = parse_value(tokens, meta, scope, scopes).map_err(|cause| -> Box<dyn ParserError> { Box::new(AssignmentDataError {
+        start: start,
+        strength: 3,
+        cause: cause,
+        var_name: var_name
+    }) })?;
+    let FieldNode { value: target, field, index, field_type, .. } = found?;
+    let term = data.get_term();
+    if scopes.types.coerce(&term, &Term::from(field_type.clone())).is_err() {
+        return Err(Box::new(FieldError {
+            start: field_start,
+            strength: 3,
+            owner: scopes.types.describe(&target.get_term()),
+            problem: FieldProblem::Mismatch {
+                field: field,
+                expected: format!("{}", field_type),
+                found: scopes.types.describe(&term)
+            }
+        }));
     }
+
+    Ok(Box::new(FieldAssignmentNode {
+        start: start,
+        target: target,
+        field: field,
+        index: index,
+        field_type: field_type,
+        data: data
+    }))
 }
 
-pub fn parse_value(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool) 
-        -> ParseResult<Box<SyntaxTreeNode>> {
-    let mut current_error = None;
-    let mut current_error_end = 0;
-    
-    tokens.push_state();
-    let result = parse_block(tokens, meta, scope, scopes);
-    match result {
-        Ok(value) => {
-            tokens.pop_state_no_revert();
-            return Ok(value);
+/// Checks that the value is a struct with the field, ``start`` is where the field's name is
+fn get_field(value: Box<dyn SyntaxTreeNode>, start: Loc, field: String, scopes: &ScopePool) -> Result<FieldNode, Box<dyn ParserError>> {
+    let error = |owner, problem| -> Box<dyn ParserError> { Box::new(FieldError {
+        start: start,
+        strength: 3,
+        owner: owner,
+        problem: problem
+    }) };
+
+    let value_term = scopes.types.resolve(&value.get_term());
+    let (index, field_type) = match &value_term {
+        Term::Known(struct_type @ Type::Struct { .. }) => match struct_type.get_field(&field[..]) {
+            Some((index, field_type)) => (index, field_type.clone()),
+            None => return Err(error(format!("{}", struct_type), FieldProblem::Unknown(field)))
         },
-        Err(error) => {
-            if error.cmp_strength(&current_error) {
-                current_error_end = tokens.get_index();
-                current_error = Some(error);
+        other => return Err(error(scopes.types.describe(other), FieldProblem::NotAStruct(field)))
+    };
+
+    Ok(FieldNode {
+        start: start,
+        value: value,
+        field: field,
+        index: index,
+        field_type: field_type
+    })
+}
+
+/// ``.field`` after a value, as many as there are
+fn parse_fields(tokens: &mut Needle<Token>, meta: &TextMetaData, value: Box<dyn SyntaxTreeNode>, scopes: &ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let mut value = value;
+    while tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::Dot)) {
+        tokens.next();
+        let (start, field) = match tokens.read() {
+            Some(token) => match &token.token_type {
+                tokenizer::TokenType::Identifier(field) => (token.start, field.clone()),
+                _ => return Err(Box::new(SimpleError::ExpectedIdentifier(token.start, 3)))
+            },
+            None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 3)))
+        };
+        value = Box::new(get_field(value, start, field, scopes)?);
+    }
+    Ok(value)
+}
+
+/// ``Name[field: value, ...]``, every field of the struct has to be given once
+fn parse_construction(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    use tokenizer::KeywordType;
+
+    // Struct name
+    let next = match tokens.read() {
+        Some(token) => token,
+        None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 0)))
+    };
+    let start = next.start;
+    let name = match &next.token_type {
+        tokenizer::TokenType::Identifier(name) => name.clone(),
+        _ => return Err(Box::new(SimpleError::ExpectedIdentifier(start, 0)))
+    };
+    let struct_type = match find_struct(tokens, &name[..], scope, scopes) {
+        Some(struct_type) => struct_type,
+        None => return Err(Box::new(SimpleError::ExpectedIdentifier(start, 0)))
+    };
+    let owner = format!("{}", struct_type);
+    let error = |start, problem| -> Box<dyn ParserError> { Box::new(FieldError {
+        start: start,
+        strength: 3,
+        owner: owner.clone(),
+        problem: problem
+    }) };
+
+    // Fields
+    match tokens.read() {
+        Some(token) if token.is_keyword(KeywordType::ArrayOpen) => {},
+        Some(token) => return Err(Box::new(SimpleError::ExpectedFields(token.start))),
+        None => return Err(Box::new(SimpleError::ExpectedFields(meta.get_end())))
+    }
+    let mut given: Vec<(String, Box<dyn SyntaxTreeNode>)> = Vec::new();
+    // The first wrong field, the rest of the fields are still read so the code after them doesn't cause more errors
+    let mut problem: Option<Box<dyn ParserError>> = None;
+    if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayClose)) {
+        tokens.next();
+    }else{
+        loop {
+            let (field_start, field) = match tokens.read() {
+                Some(token) => match &token.token_type {
+                    tokenizer::TokenType::Identifier(field) => (token.start, field.clone()),
+                    _ => return Err(Box::new(SimpleError::ExpectedIdentifier(token.start, 3)))
+                },
+                None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 3)))
+            };
+            let field_type = struct_type.get_field(&field[..]).map(|(_, field_type)| field_type.clone());
+            let repeated = given.iter().any(|(name, _)| *name == field);
+            match tokens.read() {
+                Some(token) if token.is_keyword(KeywordType::Colon) => {},
+                Some(token) => return Err(Box::new(SimpleError::ExpectedColon(token.start))),
+                None => return Err(Box::new(SimpleError::ExpectedColon(meta.get_end())))
+            }
+
+            let value = match parse_value(tokens, meta, scope, scopes) {
+                Ok(value) => value,
+                Err(cause) => return Err(error(field_start, FieldProblem::Value { field: field, cause: cause }))
+            };
+            let term = value.get_term();
+            match field_type {
+                _ if problem.is_some() => {},
+                None => problem = Some(error(field_start, FieldProblem::Unknown(field))),
+                Some(_) if repeated => problem = Some(error(field_start, FieldProblem::Repeated(field))),
+                Some(field_type) => if scopes.types.coerce(&term, &Term::from(field_type.clone())).is_err() {
+                    problem = Some(error(field_start, FieldProblem::Mismatch {
+                        field: field,
+                        expected: format!("{}", field_type),
+                        found: scopes.types.describe(&term)
+                    }));
+                }else{
+                    given.push((field, value));
+                }
+            }
+
+            match tokens.read() {
+                Some(token) if token.is_keyword(KeywordType::ArraySeparator) => {},
+                Some(token) if token.is_keyword(KeywordType::ArrayClose) => break,
+                Some(token) => return Err(Box::new(ExpectedArrayClose(token.start))),
+                None => return Err(Box::new(ExpectedArrayClose(meta.get_end())))
             }
         }
     }
-    tokens.pop_state();
 
-    tokens.push_state();
-    let result = parse_assignment(tokens, meta, scope, scopes);
-    match result {
-        Ok(value) => {
-            tokens.pop_state_no_revert();
-            return Ok(value);
-        },
-        Err(error) => {
-            if error.cmp_strength(&current_error) {
-                current_error_end = tokens.get_index();
-                current_error = Some(error);
+    if let Some(problem) = problem {
+        return Err(problem);
+    }
+
+    // The values go in the order of the fields
+    let fields = match &struct_type {
+        Type::Struct { fields, .. } => fields.clone(),
+        _ => unreachable!()
+    };
+    let missing: Vec<String> = fields.iter()
+        .filter(|(name, _)| !given.iter().any(|(given_name, _)| given_name == name))
+        .map(|(name, _)| name.clone())
+        .collect();
+    if !missing.is_empty() {
+        return Err(error(start, FieldProblem::Missing(missing)));
+    }
+    let values = fields.iter().map(|(name, _)| {
+        let index = given.iter().position(|(given_name, _)| given_name == name).unwrap();
+        given.remove(index).1
+    }).collect();
+
+    Ok(Box::new(ConstructionNode {
+        start: start,
+        struct_type: struct_type,
+        values: values
+    }))
+}
+
+/// Parses a type as it's written in annotations, like ``Int``, ``[String]`` or ``func[Int, Float] -> String``
+fn parse_type(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &ScopePool) -> ParseResult<Type> {
+    use tokenizer::KeywordType;
+
+    let next = match tokens.read() {
+        Some(token) => token,
+        None => return Err(Box::new(SimpleError::ExpectedType(meta.get_end())))
+    };
+    let start = next.start;
+
+    if next.is_keyword(KeywordType::ArrayOpen) {
+        let element = parse_type(tokens, meta, scope, scopes)?;
+        return match tokens.read() {
+            Some(token) if token.is_keyword(KeywordType::ArrayClose) => Ok(Type::Array(Box::new(element))),
+            Some(token) => Err(Box::new(ExpectedArrayClose(token.start))),
+            None => Err(Box::new(ExpectedArrayClose(meta.get_end())))
+        };
+    }
+
+    let name = match &next.token_type {
+        tokenizer::TokenType::Identifier(name) => name.clone(),
+        _ => return Err(Box::new(SimpleError::ExpectedType(start)))
+    };
+    match &name[..] {
+        "Int" => Ok(Type::Int),
+        "Float" => Ok(Type::Float),
+        "String" => Ok(Type::Str),
+        "Nil" => Ok(Type::Nil),
+        "func" => {
+            // The arguments and the return type can both be left out
+            let mut args = Vec::new();
+            if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayOpen)) {
+                tokens.next();
+                if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayClose)) {
+                    tokens.next();
+                }else{
+                    loop {
+                        args.push(parse_type(tokens, meta, scope, scopes)?);
+
+                        match tokens.read() {
+                            Some(token) if token.is_keyword(KeywordType::ArraySeparator) => {},
+                            Some(token) if token.is_keyword(KeywordType::ArrayClose) => break,
+                            Some(token) => return Err(Box::new(ExpectedArrayClose(token.start))),
+                            None => return Err(Box::new(ExpectedArrayClose(meta.get_end())))
+                        }
+                    }
+                }
             }
+
+            // Returning nil is the same as not returning anything
+            let returns = if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::Arrow)) {
+                tokens.next();
+                Some(parse_type(tokens, meta, scope, scopes)?).filter(|returns| *returns != Type::Nil).map(Box::new)
+            }else{
+                None
+            };
+            Ok(Type::Function { args: args, returns: returns })
+        },
+        _ => match find_struct(tokens, &name[..], scope, scopes) {
+            Some(struct_type) => Ok(struct_type),
+            None => Err(Box::new(SimpleError::UnknownType(start)))
         }
     }
-    tokens.pop_state();
+}
+
+/// The struct that ``name`` refers to. If it's ``module.Name``, the rest of it is read as well
+fn find_struct(tokens: &mut Needle<Token>, name: &str, scope: ScopeHandle, scopes: &ScopePool) -> Option<Type> {
+    let module = match scope.get_module(scopes, name) {
+        Some(module) if tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::Dot)) => module,
+        _ => return scope.get_struct(scopes, name).cloned()
+    };
 
     tokens.push_state();
-    let result = parse_literal(tokens, meta);
-    match result {
-        Ok(value) => {
-            tokens.pop_state_no_revert();
-            return Ok(value);
-        },
-        Err(error) => {
-            if error.cmp_strength(&current_error) {
-                current_error_end = tokens.get_index();
-                current_error = Some(error);
+    tokens.next();
+    let found = match tokens.read().map(|token| &token.token_type) {
+        Some(tokenizer::TokenType::Identifier(member)) => scopes.modules.get(module).scope.get_struct(scopes, &member[..]).cloned(),
+        _ => None
+    };
+    if found.is_some() {
+        tokens.pop_state_no_revert();
+    }else{
+        tokens.pop_state();
+    }
+    found
+}
+
+/// The struct of the value after the tokens if it's a ``Name[...]`` construction, without reading anything
+fn peek_construction(tokens: &mut Needle<Token>, scope: ScopeHandle, scopes: &ScopePool) -> Option<Type> {
+    tokens.push_state();
+    let name = match tokens.read().map(|token| &token.token_type) {
+        Some(tokenizer::TokenType::Identifier(name)) => Some(name.clone()),
+        _ => None
+    };
+    let found = name.and_then(|name| find_struct(tokens, &name[..], scope, scopes))
+        .filter(|_| tokens.match_func_offset(0, |t| t.is_keyword(tokenizer::KeywordType::ArrayOpen)));
+    tokens.pop_state();
+    found
+}
+
+/// Picks the version of a native function that takes the arguments,
+/// preferring the one that doesn't need any conversions. Numbers that nothing decided yet are ints
+fn find_overload<'a>(overloads: &'a [NativeSignature], arg_terms: &[Term], types: &Inference) -> Option<&'a NativeSignature> {
+    let fits = |signature: &&NativeSignature| signature.args.len() == arg_terms.len()
+        && signature.args.iter().zip(arg_terms.iter()).all(|(arg, term)| types.can_coerce(term, arg));
+    let exact = |signature: &&NativeSignature| signature.args.iter().zip(arg_terms.iter())
+        .all(|(arg, term)| types.get_type(term).as_ref() == Some(arg));
+
+    overloads.iter().filter(fits).find(exact)
+        .or_else(|| overloads.iter().find(fits))
+}
+
+fn parse_call(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool)
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    use tokenizer::KeywordType;
+
+    // Function name
+    let next = match tokens.read() {
+        Some(token) => token,
+        None => return Err(Box::new(SimpleError::ExpectedIdentifier(meta.get_end(), 0)))
+    };
+
+    let start = next.start;
+    let function = match &next.token_type {
+        tokenizer::TokenType::Identifier(name) if scopes.get_native(&name[..]).is_some() => name.clone(),
+        _ => return Err(Box::new(SimpleError::ExpectedIdentifier(next.start, 0)))
+    };
+    let argument_error = |cause| -> Box<dyn ParserError> { Box::new(CallError {
+        start: start,
+        strength: 3,
+        function: function.clone(),
+        problem: CallProblem::Argument(cause)
+    }) };
+
+    // Arguments
+    let mut args = Vec::new();
+    if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayOpen)) {
+        tokens.next();
+        if tokens.match_func_offset(0, |t| t.is_keyword(KeywordType::ArrayClose)) {
+            tokens.next();
+        }else{
+            loop {
+                args.push(parse_value(tokens, meta, scope, scopes).map_err(&argument_error)?);
+
+                match tokens.read() {
+                    Some(token) if token.is_keyword(KeywordType::ArraySeparator) => {},
+                    Some(token) if token.is_keyword(KeywordType::ArrayClose) => break,
+                    Some(token) => return Err(argument_error(Box::new(ExpectedArrayClose(token.start)))),
+                    None => return Err(argument_error(Box::new(ExpectedArrayClose(meta.get_end()))))
+                }
+            }
+        }
+    }else{
+        // A single argument doesn't need brackets, if it's a literal or a block.
+        // Fields after it are the argument's, ``print (p).x`` prints the field
+        tokens.push_state();
+        match parse_block(tokens, meta, scope, scopes) {
+            Ok(arg) => {
+                tokens.pop_state_no_revert();
+                args.push(parse_fields(tokens, meta, arg, scopes).map_err(&argument_error)?);
+            },
+            Err(block_error) => {
+                tokens.pop_state();
+                if block_error.get_strength() > 0 {
+                    return Err(argument_error(block_error));
+                }
+
+                tokens.push_state();
+                match parse_literal(tokens, meta, scopes) {
+                    Ok(arg) => {
+                        tokens.pop_state_no_revert();
+                        args.push(parse_fields(tokens, meta, arg, scopes).map_err(&argument_error)?);
+                    },
+                    Err(_) => {
+                        tokens.pop_state();
+                        let loc = tokens.peek().map(|token| token.start).unwrap_or(meta.get_end());
+                        return Err(Box::new(SimpleError::ExpectedArguments(loc, 1)));
+                    }
+                }
             }
         }
     }
-    tokens.pop_state();
-    
-    tokens.push_state();
-    let result = parse_variable(tokens, meta, scope, scopes);
-    match result {
-        Ok(value) => {
-            tokens.pop_state_no_revert();
-            return Ok(value);
+
+    let arg_terms: Vec<Term> = args.iter().map(|arg| arg.get_term()).collect();
+    let overloads = scopes.get_native(&function[..]).unwrap().to_vec();
+    let signature = find_overload(&overloads[..], &arg_terms[..], &scopes.types).cloned();
+    match signature {
+        Some(signature) => {
+            // The arguments are the types the function takes from now on
+            for (term, arg) in arg_terms.iter().zip(signature.args.iter()) {
+                scopes.types.coerce(term, &Term::from(arg.clone()))
+                    .expect("An overload was picked for arguments it can't take");
+            }
+            Ok(Box::new(CallNode {
+                start: start,
+                signature: signature,
+                function: function,
+                args: args
+            }))
         },
-        Err(error) => {
-            if error.cmp_strength(&current_error) {
-                current_error_end = tokens.get_index();
-                current_error = Some(error);
+        None => {
+            let found = arg_terms.iter().map(|term| scopes.types.describe(term)).collect();
+            Err(Box::new(CallError {
+                start: start,
+                strength: 3,
+                problem: CallProblem::NoOverload { found: found, overloads: overloads },
+                function: function
+            }))
+        }
+    }
+}
+
+fn parse_literal(tokens: &mut Needle<Token>, meta: &TextMetaData, scopes: &mut ScopePool) -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    if let Some(token) = tokens.read() {
+        if let Some(literal) = token.as_literal() {
+            let term = match literal {
+                tokenizer::LiteralType::_String(_) => Term::Known(Type::Str),
+                tokenizer::LiteralType::Integer(_) => scopes.types.fresh(Kind::Number),
+                tokenizer::LiteralType::Float(_) => Term::Known(Type::Float)
+            };
+            Ok(Box::new(LiteralNode { start: token.start, literal: literal, term: term }))
+        }else {
+            Err(Box::new(LiteralError::new(token.start)))
+        }
+    }else{
+        Err(Box::new(LiteralError::new(meta.get_end())))
+    }
+}
+
+/// Errors at least this strong mean the code could only have been meant as what gave them,
+/// so the other kinds of values aren't tried. A failed assignment isn't a variable
+const COMMITTED_STRENGTH: u8 = 3;
+
+pub fn parse_value(tokens: &mut Needle<Token>, meta: &TextMetaData, scope: ScopeHandle, scopes: &mut ScopePool) 
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
+    let mut current_error: Option<Box<dyn ParserError>> = None;
+    let mut current_error_end = 0;
+
+    for alternative in 0..8 {
+        tokens.push_state();
+        let result = match alternative {
+            0 => parse_import(tokens, meta, scope, scopes),
+            1 => parse_def(tokens, meta, scope, scopes),
+            2 => parse_block(tokens, meta, scope, scopes),
+            3 => parse_assignment(tokens, meta, scope, scopes),
+            4 => parse_construction(tokens, meta, scope, scopes),
+            5 => parse_call(tokens, meta, scope, scopes),
+            6 => parse_literal(tokens, meta, scopes),
+            _ => parse_variable(tokens, meta, scope, scopes)
+        };
+        match result {
+            Ok(value) => {
+                tokens.pop_state_no_revert();
+                return parse_fields(tokens, meta, value, scopes);
+            },
+            Err(error) => {
+                if error.cmp_strength(&current_error) {
+                    current_error_end = tokens.get_index();
+                    current_error = Some(error);
+                }
             }
         }
+        tokens.pop_state();
+
+        if current_error.as_ref().is_some_and(|error| error.get_strength() >= COMMITTED_STRENGTH) {
+            break;
+        }
     }
-    tokens.pop_state();
 
     if let Some(error) = current_error {
         tokens.index = current_error_end;
-        return Err(error);
+        Err(error)
     }else {
         panic!("No error was given, I have no idea why");
     }
 } 
 
 pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope: ScopeHandle, scopes: &mut ScopePool) 
-        -> ParseResult<Box<SyntaxTreeNode>> {
+        -> ParseResult<Box<dyn SyntaxTreeNode>> {
     use tokenizer::KeywordType;
 
     let start = match tokens.peek() {
@@ -292,9 +1123,9 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
     let next = tokens.peek();
     if next.is_none() { 
         return Err(Box::new(BlockError {
-            start: meta.get_end(),
-            strength: 1, 
-            causes: vec![Box::new(ExpectedBlockOpen(start))],
+            start: start,
+            strength: 2, 
+            causes: vec![Box::new(ExpectedBlockClose(meta.get_end()))],
             recover: None
         }));
     }
@@ -303,7 +1134,7 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
         return Ok(Box::new(NilNode { start: start }));
     }
 
-    let mut contents: Vec<Box<SyntaxTreeNode>> = Vec::new();
+    let mut contents: Vec<Box<dyn SyntaxTreeNode>> = Vec::new();
     let mut _return = None;
     let mut errors = Vec::new();
     let scope = parent_scope.create_subscope(scopes);
@@ -314,7 +1145,7 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
             Err(err) => {
                 let start = err.get_start();
                 errors.push(err);
-                Box::new(ErrorNode { start: start })
+                Box::new(ErrorNode { start: start, term: Term::Known(Type::Nil) })
             }
         };
 
@@ -361,7 +1192,7 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
         }
     }
 
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         Err(
             Box::new(BlockError {
                 start: start,
@@ -378,29 +1209,7 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
             })
         )
     }else{
-        // If you return something, it cannot be of type undef
-        if let Some(r) = &_return {
-            if r.get_possible_returns(scope, scopes).is_undef() {
-                errors.push(Box::new(SimpleError::ExpectedExpression(r.get_start(), 4)));
-                contents.push(_return.unwrap());
-                return Err(
-                    Box::new(BlockError {
-                        start: start,
-                        strength: 4,
-                        causes: errors,
-                        recover: Some(
-                            Box::new(BlockNode {
-                                start: start,
-                                scope: scope,
-                                contents: contents,
-                                _return: None
-                            })
-                        )
-                    })
-                );
-            }
-        }
-
+        // A block results in its last value, or nil if that's followed by a ';'
         Ok(Box::new(BlockNode {
             start: start,
             scope: scope,
@@ -408,4 +1217,6 @@ pub fn parse_block(tokens: &mut Needle<Token>, meta: &TextMetaData, parent_scope
             _return: _return
         }))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,180 @@
+use std::fmt::Write;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+use super::{ SyntaxTreeNode, TreeDump, CodeLocation, Loc, ScopeHandle, ScopePool, Term };
+use super::super::compiler::Compiler;
+use super::super::vm::Instruction;
+
+/// A file of ``def``s that code can import, it's parsed once however many times it's imported
+#[derive(Clone)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    /// The scope the ``def``s are in, it has no parent so the module can't see the code importing it
+    pub scope: ScopeHandle,
+    /// The ``def``s and ``import``s of the module, in order. Shared, so the loader can be cloned
+    pub body: Rc<Vec<Box<dyn SyntaxTreeNode>>>
+}
+
+/// Finds and keeps the modules that code imports, ``import name`` looks for ``name.tlang``
+/// next to the file that imports it, and then in the search paths
+#[derive(Clone)]
+pub struct ModuleLoader {
+    modules: Vec<Module>,
+    search_paths: Vec<PathBuf>,
+    /// Every module file that was read, also the ones with errors in them
+    files: Vec<PathBuf>,
+    /// The files that are being parsed with their names, the source file comes first if there is one.
+    /// The modules that import each other are found in here
+    loading: Vec<(PathBuf, String)>
+}
+
+impl ModuleLoader {
+    pub fn new() -> ModuleLoader {
+        ModuleLoader {
+            modules: Vec::new(),
+            search_paths: Vec::new(),
+            files: Vec::new(),
+            loading: Vec::new()
+        }
+    }
+
+    /// The file the code comes from. Without one, modules are looked for in the current directory
+    pub fn set_source(&mut self, path: &Path) {
+        let name = path.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.loading = vec![(path, name)];
+    }
+
+    /// The directory that the code that's being parsed imports modules from
+    pub fn get_directory(&self) -> PathBuf {
+        match self.loading.last().and_then(|(path, _)| path.parent()) {
+            Some(directory) => directory.to_path_buf(),
+            None => PathBuf::from(".")
+        }
+    }
+
+    pub fn add_search_path(&mut self, path: &Path) {
+        self.search_paths.push(path.to_path_buf());
+    }
+
+    /// The file ``import name`` refers to. If there's no such file, it's the one next to the importing file
+    pub fn resolve(&self, name: &str) -> PathBuf {
+        let file_name = format!("{}.tlang", name);
+        let next_to = self.get_directory().join(&file_name);
+        if next_to.is_file() {
+            return next_to;
+        }
+
+        self.search_paths.iter()
+            .map(|directory| directory.join(&file_name))
+            .find(|path| path.is_file())
+            .unwrap_or(next_to)
+    }
+
+    pub fn find(&self, path: &Path) -> Option<usize> {
+        self.modules.iter().position(|module| module.path == path)
+    }
+
+    /// If ``path`` is being parsed already, the names of the modules that led to importing it again
+    pub fn find_cycle(&self, path: &Path, name: &str) -> Option<Vec<String>> {
+        let index = self.loading.iter().position(|(loading, _)| loading == path)?;
+        let mut names: Vec<String> = self.loading[index..].iter().map(|(_, name)| name.clone()).collect();
+        names.push(String::from(name));
+        Some(names)
+    }
+
+    pub fn start_loading(&mut self, path: PathBuf, name: &str) {
+        if !self.files.contains(&path) {
+            self.files.push(path.clone());
+        }
+        self.loading.push((path, String::from(name)));
+    }
+
+    pub fn finish_loading(&mut self) {
+        self.loading.pop();
+    }
+
+    pub fn add(&mut self, module: Module) -> usize {
+        self.modules.push(module);
+        self.modules.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> &Module {
+        &self.modules[index]
+    }
+
+    /// The files of every module that was imported, even if it had errors
+    pub fn get_paths(&self) -> Vec<PathBuf> {
+        self.files.clone()
+    }
+}
+
+/// Makes the ``def``s of a module available in the scope as ``name.def``
+pub struct ImportNode {
+    pub start: Loc,
+    pub name: String,
+    /// The index of the module in the ``ModuleLoader``
+    pub module: usize
+}
+
+impl CodeLocation for ImportNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for ImportNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Import of '{}'", indent_style.repeat(indent), self.start, self.name).unwrap();
+    }
+}
+
+impl SyntaxTreeNode for ImportNode {
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        let module = scopes.modules.get(self.module);
+        // The defs are evaluated where the module is first imported, later imports reuse them
+        if !compiler.add_module(module.scope) {
+            return;
+        }
+
+        compiler.enter_scope(module.scope);
+        for node in module.body.iter() {
+            node.compile(module.scope, scopes, compiler);
+        }
+        compiler.enter_scope(scope);
+    }
+}
+
+/// A ``def`` of an imported module, ``module.name``
+pub struct MemberNode {
+    pub start: Loc,
+    pub module_name: String,
+    pub module: usize,
+    pub name: String,
+    /// The ``def``'s scheme, instantiated for this use of it
+    pub term: Term
+}
+
+impl CodeLocation for MemberNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for MemberNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Member '{}' of module '{}'",
+            indent_style.repeat(indent), self.start, self.name, self.module_name).unwrap();
+    }
+}
+
+impl SyntaxTreeNode for MemberNode {
+    fn get_term(&self) -> Term {
+        self.term.clone()
+    }
+
+    fn compile(&self, _scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        if !self.has_value(scopes) {
+            return;
+        }
+        let slot = compiler.get_local(scopes.modules.get(self.module).scope, scopes, &self.name[..]);
+        compiler.emit_with(Instruction::LoadLocal, slot, self.start);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+use super::{ SyntaxTreeNode, TreeDump, CodeLocation, Loc, ScopeHandle, ScopePool, Term, Type };
+use super::super::compiler::Compiler;
+use super::super::vm::Instruction;
+
+/// ``def name = struct [field: Type, ...]``, the struct is in the scope from when it's parsed,
+/// so there's nothing to run
+pub struct StructNode {
+    pub start: Loc,
+    pub struct_type: Type
+}
+
+impl CodeLocation for StructNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for StructNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Struct '{}'", indent_style.repeat(indent), self.start, self.struct_type).unwrap();
+        if let Type::Struct { fields, .. } = &self.struct_type {
+            for (name, field_type) in fields {
+                writeln!(text, "{}{}: {}", indent_style.repeat(indent + 1), name, field_type).unwrap();
+            }
+        }
+    }
+}
+
+impl SyntaxTreeNode for StructNode {
+    fn compile(&self, _scope: ScopeHandle, _scopes: &ScopePool, _compiler: &mut Compiler) {}
+}
+
+/// ``Name[field: value, ...]``, a new value of a struct
+pub struct ConstructionNode {
+    pub start: Loc,
+    pub struct_type: Type,
+    /// The values of the fields in the order the struct declares them, which is the order they're run in
+    pub values: Vec<Box<dyn SyntaxTreeNode>>
+}
+
+impl CodeLocation for ConstructionNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for ConstructionNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Construction of '{}'", indent_style.repeat(indent), self.start, self.struct_type).unwrap();
+        if let Type::Struct { fields, .. } = &self.struct_type {
+            for ((name, _), value) in fields.iter().zip(self.values.iter()) {
+                writeln!(text, "{}{}:", indent_style.repeat(indent + 1), name).unwrap();
+                value.dump_with_indent(text, indent + 2, indent_style);
+            }
+        }
+    }
+}
+
+impl SyntaxTreeNode for ConstructionNode {
+    fn get_term(&self) -> Term {
+        Term::Known(self.struct_type.clone())
+    }
+
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        if let Type::Struct { fields, .. } = &self.struct_type {
+            for ((_, field_type), value) in fields.iter().zip(self.values.iter()) {
+                value.compile(scope, scopes, compiler);
+                compiler.emit_conversion(value.get_type(scopes), Some(field_type.clone()), value.get_start());
+            }
+        }
+        compiler.emit_with(Instruction::NewArray, self.values.len() as u32, self.start);
+    }
+}
+
+/// ``value.field``
+pub struct FieldNode {
+    pub start: Loc,
+    pub value: Box<dyn SyntaxTreeNode>,
+    pub field: String,
+    /// Where the field is in the struct
+    pub index: usize,
+    pub field_type: Type
+}
+
+impl CodeLocation for FieldNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for FieldNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Field '{}' of", indent_style.repeat(indent), self.start, self.field).unwrap();
+        self.value.dump_with_indent(text, indent + 1, indent_style);
+    }
+}
+
+impl SyntaxTreeNode for FieldNode {
+    fn get_term(&self) -> Term {
+        Term::from(self.field_type.clone())
+    }
+
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        self.value.compile(scope, scopes, compiler);
+        compiler.emit_with(Instruction::PushInt, self.index as i64, self.start);
+        compiler.emit(Instruction::Index, self.start);
+    }
+}
+
+/// ``value.field = data``, the struct is changed for every variable that has it
+pub struct FieldAssignmentNode {
+    pub start: Loc,
+    /// The struct that has the field
+    pub target: Box<dyn SyntaxTreeNode>,
+    pub field: String,
+    pub index: usize,
+    pub field_type: Type,
+    pub data: Box<dyn SyntaxTreeNode>
+}
+
+impl CodeLocation for FieldAssignmentNode {
+    fn get_start(&self) -> Loc { self.start }
+}
+
+impl TreeDump for FieldAssignmentNode {
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}({}): Assignment of the field '{}' of", indent_style.repeat(indent), self.start, self.field).unwrap();
+        self.target.dump_with_indent(text, indent + 1, indent_style);
+        writeln!(text, "{}to", indent_style.repeat(indent)).unwrap();
+        self.data.dump_with_indent(text, indent + 1, indent_style);
+    }
+}
+
+impl SyntaxTreeNode for FieldAssignmentNode {
+    fn compile(&self, scope: ScopeHandle, scopes: &ScopePool, compiler: &mut Compiler) {
+        self.target.compile(scope, scopes, compiler);
+        compiler.emit_with(Instruction::PushInt, self.index as i64, self.start);
+        self.data.compile(scope, scopes, compiler);
+        compiler.emit_conversion(self.data.get_type(scopes), Some(self.field_type.clone()), self.start);
+        compiler.emit(Instruction::SetIndex, self.start);
+    }
+}
@@ -0,0 +1,90 @@
+use super::{ Inference, Kind, Term, Type, Scheme };
+
+fn function(args: Vec<Term>, returns: Term) -> Term {
+    Term::Function { args: args, returns: Some(Box::new(returns)) }
+}
+
+#[test]
+fn unifies_variables() {
+    let mut types = Inference::new();
+    let a = types.fresh(Kind::Any);
+    let b = types.fresh(Kind::Any);
+    assert!(types.unify(&a, &b).is_ok());
+    assert!(types.unify(&b, &Term::Known(Type::Str)).is_ok());
+    assert_eq!(types.resolve(&a), Term::Known(Type::Str));
+    assert_eq!(types.get_type(&a), Some(Type::Str));
+
+    let mismatch = types.unify(&a, &Term::Known(Type::Int)).unwrap_err();
+    assert_eq!(mismatch.expected, Term::Known(Type::Str));
+    assert_eq!(mismatch.found, Term::Known(Type::Int));
+}
+
+#[test]
+fn numbers_are_ints_or_floats() {
+    let mut types = Inference::new();
+    let number = types.fresh(Kind::Number);
+    assert_eq!(types.describe(&number), "number");
+    assert_eq!(types.get_type(&number), Some(Type::Int));
+    assert!(types.unify(&number, &Term::Known(Type::Str)).is_err());
+
+    // A variable that's unified with a number becomes one too
+    let any = types.fresh(Kind::Any);
+    assert!(types.unify(&any, &number).is_ok());
+    assert!(types.unify(&any, &Term::Known(Type::Str)).is_err());
+    assert!(types.unify(&any, &Term::Known(Type::Float)).is_ok());
+    assert_eq!(types.get_type(&number), Some(Type::Float));
+}
+
+#[test]
+fn converts_ints_to_floats() {
+    let mut types = Inference::new();
+    assert!(types.coerce(&Term::Known(Type::Int), &Term::Known(Type::Float)).is_ok());
+    assert!(types.coerce(&Term::Known(Type::Float), &Term::Known(Type::Int)).is_err());
+    assert!(types.can_coerce(&Term::Known(Type::Int), &Type::Float));
+    assert!(!types.can_coerce(&Term::Known(Type::Str), &Type::Float));
+
+    // A number that isn't decided yet becomes the type, instead of being converted
+    let number = types.fresh(Kind::Number);
+    assert!(types.coerce(&number, &Term::Known(Type::Float)).is_ok());
+    assert_eq!(types.resolve(&number), Term::Known(Type::Float));
+}
+
+#[test]
+fn unifies_functions() {
+    let mut types = Inference::new();
+    let a = types.fresh(Kind::Any);
+    let b = types.fresh(Kind::Any);
+    let expected = function(vec![a.clone()], a.clone());
+    assert!(types.unify(&expected, &function(vec![Term::Known(Type::Int)], b.clone())).is_ok());
+    assert_eq!(types.resolve(&b), Term::Known(Type::Int));
+    assert_eq!(types.describe(&expected), "func[int] -> int");
+
+    assert!(types.unify(&expected, &function(vec![], Term::Known(Type::Int))).is_err());
+    // A type can't contain itself
+    let c = types.fresh(Kind::Any);
+    assert!(types.unify(&c, &function(vec![c.clone()], Term::Known(Type::Str))).is_err());
+}
+
+#[test]
+fn generalizes_definitions() {
+    let mut types = Inference::new();
+    let a = types.fresh(Kind::Any);
+    let identity = types.generalize(&function(vec![a.clone()], a.clone()), &[]);
+    assert_eq!(identity.vars.len(), 1);
+
+    // Every use gets its own variables
+    let first = types.instantiate(&identity);
+    let second = types.instantiate(&identity);
+    assert!(types.unify(&first, &function(vec![Term::Known(Type::Int)], Term::Known(Type::Int))).is_ok());
+    assert!(types.unify(&second, &function(vec![Term::Known(Type::Str)], Term::Known(Type::Str))).is_ok());
+    assert_eq!(types.resolve(&a), a);
+
+    // Variables that the environment uses stay the same
+    let b = types.fresh(Kind::Any);
+    let scheme = types.generalize(&function(vec![b.clone()], Term::Known(Type::Int)), &types.free_vars(&b));
+    assert!(scheme.vars.is_empty());
+
+    // Numbers are defaulted before generalizing, a value has one type
+    let number = types.fresh(Kind::Number);
+    assert_eq!(types.generalize(&number, &[]), Scheme::mono(Term::Known(Type::Int)));
+}
@@ -1,117 +1,124 @@
-use std::collections::HashMap;
+use std::fmt::Write;
+use std::collections::{ HashMap, HashSet };
 use super::TreeDump;
+use super::super::vm::natives::NativeSignature;
+use super::module_node::ModuleLoader;
+use super::inference::{ Inference, Scheme, TypeVar };
+use super::super::needle::Loc;
 
-#[derive(Clone)]
-pub struct TypeCollection {
-    types: Vec<Type>
+#[derive(Clone, PartialEq, Debug)]
+pub enum Type {
+    Int,
+    Float,
+    Str,
+    /// What ``()``, blocks that end in ``;`` and assignments result in. Nil values take no space,
+    /// the code that makes one leaves nothing on the stack
+    Nil,
+    /// There are no array or function values yet, only annotations have these types
+    Array(Box<Type>),
+    /// Without ``returns`` the function doesn't give back a value
+    Function { args: Vec<Type>, returns: Option<Box<Type>> },
+    /// A ``def name = struct [...]``, the fields are in the order they're declared in.
+    /// Its values are the heap addresses of arrays with the fields in that order
+    Struct { name: String, fields: Vec<(String, Type)> }
 }
 
-impl TypeCollection {
-    pub fn from(types: Vec<Type>) -> TypeCollection {
-        TypeCollection {
-            types: types
-        }
-    }
-
-    pub fn undef() -> TypeCollection {
-        TypeCollection {
-            types: Vec::new()
-        }
-    }
-
-    pub fn is_undef(&self) -> bool {
-        self.types.len() == 0
-    }
-
-    pub fn constrain(&mut self, other: &TypeCollection) {
-        if self.types.len() == 0 {
-            // We have an undefined type, so just grab the types that get constrained
-            for t in other.types.iter() {
-                self.types.push(t.clone());
-            }
-            return;
-        }
-
-        let mut new_types = Vec::with_capacity(self.types.len());
-        for t in other.types.iter() {
-            if self.types.contains(t) {
-                new_types.push(t.clone());
-            }
-        }
-
-        self.types.clear();
-        self.types.append(&mut new_types);
-    }
-    
-    pub fn collapse(&self) -> Option<Type> {
-        if self.types.contains(&Type::Int) {
-            Some(Type::Int)
-        }else if self.types.contains(&Type::Float) {
-            Some(Type::Float)
-        }else if self.types.contains(&Type::Str) {
-            Some(Type::Str)
-        }else{
-            None
+impl Type {
+    /// Whether values of the type can be passed to and from the host as a ``Value``
+    pub fn is_value(&self) -> bool {
+        match self {
+            Type::Int | Type::Float | Type::Str => true,
+            Type::Array(element) => element.is_value(),
+            _ => false
         }
     }
-}
 
-impl std::fmt::Display for TypeCollection {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.types.len() == 0 {
-            write!(f, "undef")
-        }else {
-            for (i, elem) in self.types.iter().enumerate() {
-                if i == 0 {
-                    write!(f, "{}", elem)?;
-                }else if i < self.types.len() - 1 {
-                    write!(f, ", {}", elem)?;
-                }else{
-                    write!(f, " or {}", elem)?;
-                }
-            }
-
-            Ok(())
+    /// The index and the type of a field of a struct
+    pub fn get_field(&self, name: &str) -> Option<(usize, &Type)> {
+        match self {
+            Type::Struct { fields, .. } => fields.iter().enumerate()
+                .find(|(_, (field, _))| field == name)
+                .map(|(index, (_, field_type))| (index, field_type)),
+            _ => None
         }
     }
 }
 
-#[derive(Clone, PartialEq)]
-pub enum Type {
-    Int,
-    Float,
-    Str
-}
-
 impl std::fmt::Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Type::Int => write!(f, "int"),
             Type::Float => write!(f, "float"),
-            Type::Str => write!(f, "string")
+            Type::Str => write!(f, "string"),
+            Type::Nil => write!(f, "nil"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Function { args, returns } => {
+                write!(f, "func[")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, "]")?;
+                match returns {
+                    Some(returns) => write!(f, " -> {}", returns),
+                    None => Ok(())
+                }
+            },
+            Type::Struct { name, .. } => write!(f, "{}", name)
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Scope {
-    members: HashMap<String, TypeCollection>,
+    members: HashMap<String, Scheme>,
+    /// Where the members were first assigned, the ones the host declared aren't in here
+    declarations: HashMap<String, Loc>,
+    /// The modules imported in the scope, by the index the ``ModuleLoader`` gave them
+    modules: HashMap<String, usize>,
+    /// The structs declared in the scope, they have names of their own apart from the members
+    structs: HashMap<String, Type>,
+    /// The modules the scope failed to import, the import's error is the only one they get
+    failed_imports: HashSet<String>,
+    /// Where the defs of the scope are, members and structs. They can't be assigned again
+    definitions: HashMap<String, Loc>,
     parent_scope: Option<u32>,
     id: u32
 }
 
+#[derive(Clone)]
 pub struct ScopePool {
     scopes: HashMap<u32, Scope>,
-    n_scopes: u32
+    n_scopes: u32,
+    /// The native functions code can call, every overload of a name
+    natives: HashMap<String, Vec<NativeSignature>>,
+    pub modules: ModuleLoader,
+    /// What the type variables of the members and the nodes are bound to
+    pub types: Inference
 }
 
 impl ScopePool {
     pub fn new() -> ScopePool {
         ScopePool {
             scopes: HashMap::new(),
-            n_scopes: 0
+            n_scopes: 0,
+            natives: HashMap::new(),
+            modules: ModuleLoader::new(),
+            types: Inference::new()
         }
     }
 
+    pub fn declare_native(&mut self, name: &str, signature: NativeSignature) {
+        let overloads = self.natives.entry(String::from(name)).or_default();
+        if !overloads.contains(&signature) {
+            overloads.push(signature);
+        }
+    }
+
+    pub fn get_native(&self, name: &str) -> Option<&[NativeSignature]> {
+        self.natives.get(name).map(|overloads| &overloads[..])
+    }
+
     fn get_member_loc(&self, scope_id: u32, var_name: &str) -> Option<u32> {
         let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_member_loc");
         
@@ -123,7 +130,7 @@ impl ScopePool {
         }
     }
 
-    fn get_member_mut(&mut self, scope_id: u32, var_name: &str) -> Option<&mut TypeCollection> {
+    fn get_member_mut(&mut self, scope_id: u32, var_name: &str) -> Option<&mut Scheme> {
         let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_member_mut");
         
         if scope.members.contains_key(var_name) {
@@ -135,7 +142,41 @@ impl ScopePool {
         }
     }
 
-    fn get_member(&self, scope_id: u32, var_name: &str) -> Option<&TypeCollection> {
+    fn get_module(&self, scope_id: u32, name: &str) -> Option<usize> {
+        let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_module");
+
+        match scope.modules.get(name) {
+            Some(module) => Some(*module),
+            None => self.get_module(scope.parent_scope?, name)
+        }
+    }
+
+    fn get_struct(&self, scope_id: u32, name: &str) -> Option<&Type> {
+        let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_struct");
+
+        match scope.structs.get(name) {
+            Some(struct_type) => Some(struct_type),
+            None => self.get_struct(scope.parent_scope?, name)
+        }
+    }
+
+    fn has_failed_import(&self, scope_id: u32, name: &str) -> bool {
+        let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in has_failed_import");
+
+        scope.failed_imports.contains(name)
+            || scope.parent_scope.is_some_and(|parent| self.has_failed_import(parent, name))
+    }
+
+    fn get_definition(&self, scope_id: u32, name: &str) -> Option<Loc> {
+        let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_definition");
+
+        match scope.definitions.get(name) {
+            Some(loc) => Some(*loc),
+            None => self.get_definition(scope.parent_scope?, name)
+        }
+    }
+
+    fn get_member(&self, scope_id: u32, var_name: &str) -> Option<&Scheme> {
         let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_member");
         
         if scope.members.contains_key(var_name) {
@@ -146,11 +187,30 @@ impl ScopePool {
         }
     }
 
+    /// The type variables that the members of the scope and its parents use, other than ``except``.
+    /// A ``def`` of ``except`` can't generalize them
+    fn get_environment(&self, scope_id: u32, except: &str) -> Vec<TypeVar> {
+        let scope = self.scopes.get(&scope_id).expect("Expected a valid scope id in get_environment");
+        let mut vars: Vec<TypeVar> = scope.members.iter()
+            .filter(|(name, _)| *name != except)
+            .flat_map(|(_, scheme)| self.types.free_vars(&scheme.term).into_iter().filter(move |var| !scheme.vars.contains(var)))
+            .collect();
+        if let Some(parent_id) = scope.parent_scope {
+            vars.extend(self.get_environment(parent_id, except));
+        }
+        vars
+    }
+
     pub fn create_scope(&mut self) -> ScopeHandle {
         self.scopes.insert(
             self.n_scopes,
             Scope {
                 members: HashMap::new(),
+                declarations: HashMap::new(),
+                modules: HashMap::new(),
+                structs: HashMap::new(),
+                failed_imports: HashSet::new(),
+                definitions: HashMap::new(),
                 parent_scope: None,
                 id: self.n_scopes
             }
@@ -166,6 +226,11 @@ impl ScopePool {
             self.n_scopes,
             Scope {
                 members: HashMap::new(),
+                declarations: HashMap::new(),
+                modules: HashMap::new(),
+                structs: HashMap::new(),
+                failed_imports: HashSet::new(),
+                definitions: HashMap::new(),
                 parent_scope: Some(parent_scope),
                 id: self.n_scopes
             }
@@ -178,16 +243,16 @@ impl ScopePool {
 }
 
 impl ScopePool {
-    fn print_scope_with_indent(&self, scope: &Scope, indent: usize, indent_style: &str) {
-        println!("{}Scope[{}]:", indent_style.repeat(indent), scope.id);
+    fn dump_scope_with_indent(&self, text: &mut String, scope: &Scope, indent: usize, indent_style: &str) {
+        writeln!(text, "{}Scope[{}]:", indent_style.repeat(indent), scope.id).unwrap();
         for member in scope.members.iter() {
-            println!("{}{}: {}", indent_style.repeat(indent + 1), member.0, member.1);
+            writeln!(text, "{}{}: {}", indent_style.repeat(indent + 1), member.0, self.types.describe(&member.1.term)).unwrap();
         }
 
         for sub_scope in self.scopes.values() {
             if let Some(parent) = sub_scope.parent_scope {
                 if parent == scope.id {
-                    self.print_scope_with_indent(sub_scope, indent + 1, indent_style);
+                    self.dump_scope_with_indent(text, sub_scope, indent + 1, indent_style);
                 }
             }
         }
@@ -195,17 +260,17 @@ impl ScopePool {
 }
 
 impl TreeDump for ScopePool {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        println!("{}ScopePool:", indent_style.repeat(indent));
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        writeln!(text, "{}ScopePool:", indent_style.repeat(indent)).unwrap();
         for scope in self.scopes.values() {
             if scope.parent_scope.is_none() {
-                self.print_scope_with_indent(scope, indent + 1, indent_style);
+                self.dump_scope_with_indent(text, scope, indent + 1, indent_style);
             }
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ScopeHandle {
     id: u32
 }
@@ -215,15 +280,97 @@ impl ScopeHandle {
         scope_pool.create_scope_with_parent(self.id)
     }
 
-    pub fn get_mut<'a>(&self, scope_pool: &'a mut ScopePool, var_name: &str) -> Option<&'a mut TypeCollection> {
+    pub fn get_mut<'a>(&self, scope_pool: &'a mut ScopePool, var_name: &str) -> Option<&'a mut Scheme> {
         scope_pool.get_member_mut(self.id, var_name)
     }
 
-    pub fn get<'a>(&self, scope_pool: &'a ScopePool, var_name: &str) -> Option<&'a TypeCollection> {
+    pub fn get<'a>(&self, scope_pool: &'a ScopePool, var_name: &str) -> Option<&'a Scheme> {
         scope_pool.get_member(self.id, var_name)
     }
 
-    pub fn insert(&self, scope_pool: &mut ScopePool, var_name: &str, var_type: TypeCollection) -> Option<TypeCollection> {
+    /// Finds the scope that the variable was declared in, either this one or a parent
+    pub fn get_declaring_scope(&self, scope_pool: &ScopePool, var_name: &str) -> Option<ScopeHandle> {
+        scope_pool.get_member_loc(self.id, var_name).map(|id| ScopeHandle { id: id })
+    }
+
+    pub fn get_environment(&self, scope_pool: &ScopePool, except: &str) -> Vec<TypeVar> {
+        scope_pool.get_environment(self.id, except)
+    }
+
+    /// The index of the module imported as ``name`` in this scope or a parent
+    pub fn get_module(&self, scope_pool: &ScopePool, name: &str) -> Option<usize> {
+        scope_pool.get_module(self.id, name)
+    }
+
+    pub fn insert_failed_import(&self, scope_pool: &mut ScopePool, name: &str) {
+        let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
+        scope.failed_imports.insert(String::from(name));
+    }
+
+    /// Whether importing ``name`` failed in this scope or a parent
+    pub fn has_failed_import(&self, scope_pool: &ScopePool, name: &str) -> bool {
+        scope_pool.has_failed_import(self.id, name)
+    }
+
+    /// The names of the modules imported in this scope itself, sorted
+    pub fn get_module_names(&self, scope_pool: &ScopePool) -> Vec<String> {
+        let mut names: Vec<String> = scope_pool.scopes[&self.id].modules.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn insert_module(&self, scope_pool: &mut ScopePool, name: &str, module: usize) {
+        let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
+        scope.modules.insert(String::from(name), module);
+    }
+
+    /// The struct declared as ``name`` in this scope or a parent
+    pub fn get_struct<'a>(&self, scope_pool: &'a ScopePool, name: &str) -> Option<&'a Type> {
+        scope_pool.get_struct(self.id, name)
+    }
+
+    /// Whether this scope itself declares a struct called ``name``, a subscope can declare it again
+    pub fn declares_struct(&self, scope_pool: &ScopePool, name: &str) -> bool {
+        scope_pool.scopes[&self.id].structs.contains_key(name)
+    }
+
+    /// The structs this scope itself declares, sorted by name
+    pub fn get_structs(&self, scope_pool: &ScopePool) -> Vec<Type> {
+        let mut structs: Vec<(&String, &Type)> = scope_pool.scopes[&self.id].structs.iter().collect();
+        structs.sort_by_key(|(name, _)| *name);
+        structs.into_iter().map(|(_, struct_type)| struct_type.clone()).collect()
+    }
+
+    /// Marks the member or struct ``name`` as a def, so it can't be assigned again
+    pub fn define(&self, scope_pool: &mut ScopePool, name: &str, loc: Loc) {
+        let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
+        scope.definitions.insert(String::from(name), loc);
+    }
+
+    /// Where the def called ``name`` is, in this scope or a parent. None if it isn't a def
+    pub fn get_definition(&self, scope_pool: &ScopePool, name: &str) -> Option<Loc> {
+        scope_pool.get_definition(self.id, name)
+    }
+
+    pub fn insert_struct(&self, scope_pool: &mut ScopePool, name: &str, struct_type: Type) {
+        let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
+        scope.structs.insert(String::from(name), struct_type);
+    }
+
+    /// Where the variable was first assigned, None if it is a global of the engine
+    pub fn get_declaration(&self, scope_pool: &ScopePool, var_name: &str) -> Option<Loc> {
+        let scope = self.get_declaring_scope(scope_pool, var_name)?;
+        scope_pool.scopes[&scope.id].declarations.get(var_name).copied()
+    }
+
+    /// Inserts a variable that's assigned for the first time at ``loc``
+    pub fn declare(&self, scope_pool: &mut ScopePool, var_name: &str, var_type: Scheme, loc: Loc) {
+        let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
+        scope.declarations.insert(String::from(var_name), loc);
+        self.insert(scope_pool, var_name, var_type);
+    }
+
+    pub fn insert(&self, scope_pool: &mut ScopePool, var_name: &str, var_type: Scheme) -> Option<Scheme> {
         let scope = scope_pool.scopes.get_mut(&self.id).expect("ScopeHandle has an invalid ScopeID. Maybe you passed the wrong ScopePool");
         scope.members.insert(String::from(var_name), var_type)
     }
@@ -0,0 +1,28 @@
+//! TrolledLang as a library.
+//!
+//! ``Engine`` is the way to embed the language, it compiles and runs code,
+//! and holds the host functions, globals and limits the code runs with.
+//! The other modules are the pieces it's made of, for tools that need to look inside.
+
+// Structs are built with every field named, and types get ``new`` rather than ``Default``
+#![allow(clippy::redundant_field_names, clippy::new_without_default)]
+
+pub mod tokenizer;
+pub mod lexer;
+pub mod tree_dump;
+pub mod needle;
+pub mod vm;
+pub mod compiler;
+pub mod debugger;
+pub mod dap;
+pub mod repl;
+pub mod watch;
+pub mod project;
+mod engine;
+
+pub use engine::{ Engine, Script, Parsed, Error, Session, Evaluation };
+pub use tree_dump::TreeDump;
+pub use vm::natives::{ Value, NativeResult };
+pub use vm::conversion::{ FromValue, IntoValue, Typed, IntoNative, IntoReturn, ConversionError };
+pub use vm::Limits;
+pub use lexer::Type;
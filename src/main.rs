@@ -1,101 +1,409 @@
-mod tokenizer;
-mod lexer;
-mod tree_dump;
-mod needle;
-mod vm;
-use needle::{ Needle };
-pub use tree_dump::TreeDump;
-
-fn run(code: &String) -> bool {
-    let (result, errors, meta) = tokenizer::tokenize(&code[..]);
-
-    if errors.len() > 0 {
-        println!("There were errors, woohoo!");
-        for error in errors.iter() {
-            println!("({}): {}", error.loc, error.msg);
+use language::{ Engine, Script, Error, TreeDump, Value, tokenizer, vm, debugger, dap, repl, watch, project };
+use std::io::Write;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: tlang <command> [arguments]
+       tlang <path> [<args>...]
+
+Commands:
+    run [--profile] <path> [<args>...]
+                              Run a source file or a .tlbc bytecode file, and print what it results in
+    eval <code> [<args>...]   Run the code given on the command line, and print what it results in
+    file <path> [<args>...]   Run a file as a script, an int it results in is the exit status.
+                              This is what 'tlang <path>' and a '#!/usr/bin/env tlang' line do
+    watch <path> [<args>...]  Run a source file again every time it changes
+    check <path>              Report the errors in a source file without running it
+    tokens <path>             Print the tokens of a source file
+    ast <path>                Print the syntax tree of a source file
+    scopes <path>             Print the scopes and variables of a source file
+    build <path> [<output>]   Compile a source file to bytecode, next to it by default
+    build [<project>]         Compile the project in the directory, or the one the current directory is in.
+                              Only the files that changed since the last build are compiled
+    disasm <path>             Print the bytecode of a source or .tlbc file
+    repl                      Start the interactive shell
+    debug <path>              Step through a source file in the debugger
+    dap                       Serve the debug adapter protocol over stdin and stdout
+    help                      Print this
+
+Options:
+    --profile    Print where the time went after running, and write folded stacks next to the file
+    -h, --help   Print this
+
+The options go before the path, the <args> after it are for the program, 'arg_count[]' is how many there are
+and 'arg[i]' is one of them. 'exit[code]' stops the program with the exit code.
+
+Exit codes:
+    0  Success
+    1  A file couldn't be read or written, or the bytecode is invalid
+    2  The command line is invalid
+    3  The code has characters that aren't part of the language
+    4  The code doesn't parse
+    5  The code parses, but values have the wrong types
+    6  The program failed while running";
+
+/// The commands that take the path of a file
+const FILE_COMMANDS: [&str; 9] = ["run", "file", "watch", "check", "tokens", "ast", "scopes", "disasm", "debug"];
+
+/// A file couldn't be read or written, or the bytecode is invalid
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_LEX: i32 = 3;
+const EXIT_PARSE: i32 = 4;
+const EXIT_TYPE: i32 = 5;
+const EXIT_RUNTIME: i32 = 6;
+
+/// The commands where the arguments after the path are for the program
+const SCRIPT_COMMANDS: [&str; 4] = ["run", "file", "watch", "eval"];
+
+/// How often ``watch`` looks at the file
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+fn is_command(arg: &str) -> bool {
+    FILE_COMMANDS.contains(&arg) || ["eval", "build", "repl", "dap", "help"].contains(&arg)
+}
+
+/// The diagnostics have been printed by the time a command fails, this is the exit code
+type CommandResult = Result<(), i32>;
+
+/// Prints the error to stderr, and gives back the exit code for it
+fn report(error: Error) -> i32 {
+    match error {
+        Error::Tokenize(_) => {
+            eprintln!("{}", error);
+            EXIT_LEX
+        },
+        Error::Parse(error) => {
+            eprint!("{}", error.dump());
+            EXIT_PARSE
+        },
+        Error::Type(error) => {
+            eprint!("{}", error.dump());
+            EXIT_TYPE
+        },
+        Error::Link(_) => {
+            eprintln!("{}", error);
+            EXIT_TYPE
+        },
+        Error::Runtime(_) | Error::Conversion(_) => {
+            eprintln!("{}", error);
+            EXIT_RUNTIME
         }
-        println!("\n");
     }
+}
 
-    // for token in result.iter() {
-    //     token.print();
-    // }
-    
-    let mut scopes = lexer::ScopePool::new();
-    let tree = lexer::parse_value(
-        &mut Needle::new(result, 0), 
-        &meta, 
-        scopes.create_scope(), 
-        &mut scopes
-        );
-    
-    scopes.print();
+fn read_source(path: &str) -> Result<String, i32> {
+    std::fs::read_to_string(path).map_err(|err| {
+        eprintln!("Error opening file! {}", err);
+        EXIT_FAILURE
+    })
+}
 
-    if let Ok(value) = tree {
-        value.print();
-    }else if let Err(error) = tree {
-        println!("Got an error!");
-        error.print();
+/// Compiles a source file, or loads and verifies a bytecode file
+fn load_script(engine: &Engine, path: &str) -> Result<Script, i32> {
+    if path.ends_with(".tlbc") {
+        let program = vm::bytecode_file::load(path).map_err(|err| {
+            eprintln!("Error loading bytecode! {}", err);
+            EXIT_FAILURE
+        })?;
+        vm::verifier::verify(&program).map_err(|err| {
+            eprintln!("Invalid bytecode! {}", err);
+            EXIT_FAILURE
+        })?;
+        // The file doesn't know the type of the result, so it isn't printed
+        return Ok(Script::from_program(program));
     }
 
-    errors.len() == 0
+    engine.compile(&read_source(path)?[..]).map_err(report)
 }
 
-fn main() {
-    vm::test_vm();
-    return;
-
-    let args: Vec<String> = std::env::args().collect();
-    if let Some(data) = args.get(1) {
-        match &data[..] {
-            "run" => {
-                if let Some(data) = args.get(2) {
-                    run(data);
-                }else {
-                    // Open a shell for the to write into
-                    println!("\n-- TROLLEDLANG SHELL --\n'quit' or 'exit' to exit the shell\n");
-                    loop {
-                        match read_line("") {
-                            Ok(result) => {
-                                if result == "exit" || result == "quit" { break; }
-                                print!(">");
-                                let result = run(&result);
-                                if !result { println!("An error occured :("); }
-                            },
-                            _ => {
-                                println!("Invalid input!");
-                            }
-                        }
-                    }
-                }
-            },
-            "file" => {
-                if let Some(path) = args.get(2) {
-                    match std::fs::read_to_string(path) {
-                        Ok(code) => {
-                            let result = run(&code);
-                            if !result { println!("An error occured :("); }
-                        },
-                        Err(err) => {
-                            println!("Error opening file! {}", err);
-                        }
-                    }
-                }else{
-                    println!("Expected an argument for the path");
-                }
+/// The source file and the modules it imports, the ones that could be found
+fn watched_files(engine: &Engine, path: &str) -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(path)];
+    if let Ok(code) = std::fs::read_to_string(path) {
+        files.extend(engine.find_imports(&code[..]));
+    }
+    files
+}
+
+/// The folded stacks of a profiled file go next to it
+fn folded_path(path: &str) -> String {
+    std::path::Path::new(path).with_extension("folded").to_string_lossy().into_owned()
+}
+
+/// Exit statuses only have 8 bits, the rest are dropped like the shell does
+fn exit_code(status: i64) -> i32 {
+    (status & 0xff) as i32
+}
+
+/// Runs the file, in a script the int it results in is the exit code instead of being printed
+fn run(engine: &Engine, path: &str, profile: bool, script_mode: bool) -> CommandResult {
+    let script = load_script(engine, path)?;
+    run_script(engine, &script, if profile { Some(path) } else { None }, script_mode)
+}
+
+/// ``profile_path`` is the file the script came from, when it's profiled
+fn run_script(engine: &Engine, script: &Script, profile_path: Option<&str>, script_mode: bool) -> CommandResult {
+    let mut machine = engine.create_machine(script).map_err(report)?;
+    if profile_path.is_some() {
+        machine.enable_profiling();
+    }
+
+    let result = machine.run();
+    if let (Some(profiler), Some(path)) = (machine.take_profile(), profile_path) {
+        // The report shows the lines next to the counts, if there's source for them
+        let source = if path.ends_with(".tlbc") { None } else { std::fs::read_to_string(path).ok() };
+        println!("{}", profiler.report(source.as_deref()));
+        let folded_path = folded_path(path);
+        match std::fs::write(&folded_path, profiler.folded_stacks()) {
+            Ok(()) => println!("Wrote folded stacks to {}", folded_path),
+            Err(err) => eprintln!("Error writing folded stacks! {}", err)
+        }
+    }
+    if let Err(error) = result {
+        return match error.get_exit_status() {
+            Some(0) => Ok(()),
+            Some(status) => Err(exit_code(status)),
+            None => Err(report(Error::Runtime(error)))
+        };
+    }
+
+    match script.get_result(&mut machine) {
+        Some(Value::Int(status)) if script_mode && status != 0 => Err(exit_code(status)),
+        _ if script_mode => Ok(()),
+        Some(Value::Str(string)) => {
+            println!("= \"{}\"", string);
+            Ok(())
+        },
+        Some(value) => {
+            println!("= {}", value);
+            Ok(())
+        },
+        None => Ok(())
+    }
+}
+
+/// Runs the file, and runs it again whenever it changes, until the user stops it
+fn watch(engine: &Engine, path: &str) -> CommandResult {
+    let mut watcher = watch::Watcher::new(watched_files(engine, path));
+    loop {
+        // Clears the screen, so only the diagnostics of the latest version are shown
+        print!("\x1b[2J\x1b[H");
+        println!("Watching {}, press Ctrl-C to stop\n", path);
+        match run(engine, path, false, false) {
+            Ok(()) => println!("\nRan without errors"),
+            Err(code) => println!("\nStopped with exit code {}", code)
+        }
+        std::io::stdout().flush().ok();
+        watcher.wait(WATCH_INTERVAL);
+        // The changes could import other modules
+        watcher.set_files(watched_files(engine, path));
+    }
+}
+
+fn print_tokens(path: &str) -> CommandResult {
+    let code = read_source(path)?;
+    let (tokens, errors, meta) = tokenizer::tokenize(&code[..]);
+    for token in tokens.iter() {
+        token.print();
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for error in errors.iter() {
+        eprintln!("({}): {}", meta.index_to_loc(error.loc), error.msg);
+    }
+    Err(EXIT_LEX)
+}
+
+/// Builds the project of the manifest, ``path`` is the manifest or the directory it's in.
+/// Without one, it's looked for from the current directory up
+fn build_project(engine: &Engine, path: &str) -> CommandResult {
+    let manifest_path = if path.is_empty() {
+        let directory = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        match project::Manifest::find(&directory) {
+            Some(path) => path,
+            None => {
+                eprintln!("There's no {} here, 'tlang build <path>' builds a single file", project::MANIFEST_NAME);
+                return Err(EXIT_USAGE);
             }
-            _ => {
-                println!("Invalid commandline argument");
+        }
+    }else if std::path::Path::new(path).is_dir() {
+        std::path::Path::new(path).join(project::MANIFEST_NAME)
+    }else{
+        PathBuf::from(path)
+    };
+
+    let manifest = project::Manifest::load(&manifest_path).map_err(|error| {
+        eprintln!("{}", error);
+        EXIT_FAILURE
+    })?;
+    match project::build(engine, &manifest) {
+        Ok(report) => {
+            for output in report.built.iter() {
+                println!("Built {}", output.display());
             }
+            println!("{} built, {} up to date", report.built.len(), report.up_to_date.len());
+            Ok(())
+        },
+        Err(project::ProjectError::Compile { path, error }) => {
+            eprintln!("In {}:", path.display());
+            Err(report(*error))
+        },
+        Err(error) => {
+            eprintln!("{}", error);
+            Err(EXIT_FAILURE)
+        }
+    }
+}
+
+fn build(engine: &Engine, path: &str, out_path: Option<&str>) -> CommandResult {
+    let script = engine.compile(&read_source(path)?[..]).map_err(report)?;
+    let out_path = match out_path {
+        Some(out_path) => String::from(out_path),
+        None => std::path::Path::new(path).with_extension("tlbc").to_string_lossy().into_owned()
+    };
+
+    match vm::bytecode_file::save(&script.program, &out_path[..]) {
+        Ok(()) => {
+            println!("Wrote bytecode to {}", out_path);
+            Ok(())
+        },
+        Err(err) => {
+            eprintln!("Error writing bytecode! {}", err);
+            Err(EXIT_FAILURE)
+        }
+    }
+}
+
+fn debug(engine: &Engine, path: &str) -> CommandResult {
+    let code = read_source(path)?;
+    // Parsed by the engine first, so the errors are reported and exit like they do for 'run'
+    engine.parse(&code[..]).map_err(report)?;
+    match debugger::Debugger::new(&code, engine.get_natives()) {
+        Ok(debugger) => {
+            debugger::Prompt::new(debugger).run_prompt();
+            Ok(())
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            Err(EXIT_FAILURE)
         }
-    }else {
-        println!("Expected a commandline argument");
     }
 }
 
-fn read_line<'a>(prompt: &str) -> std::io::Result<String> {
-    println!("{}", prompt);
-    let buffer = &mut String::new();
-    std::io::stdin().read_line(buffer)?; // <- API requires buffer param as of Rust 1.0; returns `Result` of bytes read
-    Ok(String::from(buffer.trim_end()))
-}
\ No newline at end of file
+fn cli(args: &[String]) -> CommandResult {
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        return Err(EXIT_USAGE);
+    }
+
+    let mut command = None;
+    let mut help = false;
+    let mut profile = false;
+    let mut positional = Vec::new();
+    let mut program_args = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        // The options come before the path, everything after it is for the program
+        if command.is_some_and(|command| SCRIPT_COMMANDS.contains(&command)) && !positional.is_empty() {
+            program_args = args[i..].to_vec();
+            break;
+        }
+
+        match &arg[..] {
+            "--help" | "-h" => help = true,
+            "--profile" => profile = true,
+            option if option.starts_with('-') => {
+                eprintln!("Unknown option '{}', 'tlang help' lists the options", option);
+                return Err(EXIT_USAGE);
+            },
+            // 'tlang <path>' runs the file as a script, that's how a shebang line calls it
+            path if command.is_none() && !is_command(path) && std::path::Path::new(path).is_file() => {
+                command = Some("file");
+                positional.push(path);
+            },
+            arg if command.is_none() => command = Some(arg),
+            arg => positional.push(arg)
+        }
+    }
+
+    let command = if help { "help" } else { command.unwrap_or("help") };
+    let path = match positional.first() {
+        Some(path) => *path,
+        None if FILE_COMMANDS.contains(&command) => {
+            eprintln!("Expected the path of a file, 'tlang {} <path>'", command);
+            return Err(EXIT_USAGE);
+        },
+        None if command == "eval" => {
+            eprintln!("Expected the code to run, 'tlang eval <code>'");
+            return Err(EXIT_USAGE);
+        },
+        None => ""
+    };
+    if profile && command == "eval" {
+        eprintln!("'--profile' writes next to a file, use 'tlang run --profile <path>'");
+        return Err(EXIT_USAGE);
+    }
+
+    let mut engine = Engine::new();
+    engine.set_args(program_args);
+    if command != "eval" {
+        engine.set_source_path(path);
+    }
+    match command {
+        "run" => run(&engine, path, profile, false),
+        // The path is the code
+        "eval" => run_script(&engine, &engine.compile(path).map_err(report)?, None, false),
+        "file" => run(&engine, path, profile, true),
+        "watch" => watch(&engine, path),
+        "check" => engine.compile(&read_source(path)?[..]).map(|_| ()).map_err(report),
+        "tokens" => print_tokens(path),
+        "ast" => {
+            let parsed = engine.parse(&read_source(path)?[..]).map_err(report)?;
+            parsed.tree.print();
+            Ok(())
+        },
+        "scopes" => {
+            let parsed = engine.parse(&read_source(path)?[..]).map_err(report)?;
+            parsed.scopes.print();
+            Ok(())
+        },
+        // A source file is built on its own, anything else is a project
+        "build" if path.ends_with(".tlang") => build(&engine, path, positional.get(1).copied()),
+        "build" => build_project(&engine, path),
+        "disasm" => {
+            let script = load_script(&engine, path)?;
+            print!("{}", vm::disassembler::disassemble(&script.program));
+            Ok(())
+        },
+        "repl" => {
+            repl::Repl::new(engine).run_prompt();
+            Ok(())
+        },
+        "debug" => debug(&engine, path),
+        "dap" => {
+            let stdin = std::io::stdin();
+            dap::Server::new(stdin.lock(), std::io::stdout()).run().map_err(|err| {
+                eprintln!("Debug adapter error! {}", err);
+                EXIT_FAILURE
+            })
+        },
+        "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => {
+            eprintln!("Unknown command '{}', 'tlang help' lists the commands", command);
+            Err(EXIT_USAGE)
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(match cli(&args[..]) {
+        Ok(()) => 0,
+        Err(code) => code
+    });
+}
@@ -4,7 +4,7 @@ pub struct Needle<T> {
     index_stack: Vec<usize>
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Loc {
     pub line: usize, 
     pub character: usize
@@ -63,7 +63,7 @@ impl Needle<char> {
         }
     }
 
-    pub fn get_meta_data<'a>(&self) -> TextMetaData {
+    pub fn get_meta_data(&self) -> TextMetaData {
         let mut lines = Vec::new();
         for (i, c) in self.reading.iter().enumerate() {
             if *c == '\n' {
@@ -119,12 +119,12 @@ impl<T> Needle<T> {
     }
 
     pub fn pop_state(&mut self) {
-        assert!(self.index_stack.len() > 0, "Cannot pop_state when index stack length is 0");
+        assert!(!self.index_stack.is_empty(), "Cannot pop_state when index stack length is 0");
         self.index = self.index_stack.pop().unwrap();
     }
 
     pub fn pop_state_no_revert(&mut self) {
-        assert!(self.index_stack.len() > 0, "Cannot pop_state when index stack length is 0");
+        assert!(!self.index_stack.is_empty(), "Cannot pop_state when index stack length is 0");
         self.index_stack.pop();
     }
 
@@ -157,11 +157,12 @@ impl<T> Needle<T> {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> bool {
         if self.index >= self.reading.len() { return false; }
 
         self.index += 1;
-        return true;
+        true
     }
 
     pub fn skip(&mut self, n_indices: usize) -> bool {
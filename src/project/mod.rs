@@ -0,0 +1,314 @@
+//! Projects of more than one file, described by a ``tlang.toml`` manifest.
+//!
+//! ``build`` compiles the entry file and every module in the source directories to bytecode.
+//! What each output was built from is remembered in a state file in the output directory,
+//! so a file is only compiled again when its source or a module it imports changed.
+//!
+//! ```toml
+//! [project]
+//! name = "game"
+//! entry = "src/main.tlang"
+//! sources = ["src"]
+//! module_paths = ["lib"]
+//! output = "build"
+//! ```
+
+use super::engine::{ Engine, Error };
+use super::vm::bytecode_file;
+use std::path::{ Path, PathBuf };
+
+pub mod toml;
+use toml::Toml;
+
+/// The name of the manifest file, in the root directory of a project
+pub const MANIFEST_NAME: &str = "tlang.toml";
+
+/// The name of the file in the output directory that remembers what was built
+const STATE_NAME: &str = ".tlang-build";
+
+pub enum ProjectError {
+    Io { path: PathBuf, message: String },
+    /// The manifest isn't valid, or doesn't have what a project needs
+    Manifest { path: PathBuf, message: String },
+    /// A file of the project has errors in it
+    Compile { path: PathBuf, error: Box<Error> }
+}
+
+impl ProjectError {
+    fn io(path: &Path, error: std::io::Error) -> ProjectError {
+        ProjectError::Io {
+            path: path.to_path_buf(),
+            message: format!("{}", error)
+        }
+    }
+}
+
+impl std::fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectError::Io { path, message } => write!(f, "{}: {}", path.display(), message),
+            ProjectError::Manifest { path, message } => write!(f, "{}: {}", path.display(), message),
+            ProjectError::Compile { path, error } => write!(f, "{}: {}", path.display(), error)
+        }
+    }
+}
+
+/// The paths are relative to the directory of the manifest in the file, and joined with it here
+#[derive(Debug)]
+pub struct Manifest {
+    pub name: String,
+    /// The file with the program, it's built to ``name.tlbc``
+    pub entry: PathBuf,
+    /// The directories with the modules of the project, every ``.tlang`` file in them is built
+    pub sources: Vec<PathBuf>,
+    /// Where imports look for modules that aren't next to the importing file
+    pub module_paths: Vec<PathBuf>,
+    pub output: PathBuf
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, ProjectError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ProjectError::io(path, error))?;
+        let root = path.parent().unwrap_or_else(|| Path::new("."));
+        Manifest::parse(&text[..], root)
+            .map_err(|message| ProjectError::Manifest { path: path.to_path_buf(), message: message })
+    }
+
+    /// Reads the ``[project]`` table. Only ``entry`` is required, the name defaults to the name
+    /// of the entry file, the sources to its directory and the output to ``build``
+    pub fn parse(text: &str, root: &Path) -> Result<Manifest, String> {
+        let document = Toml::parse(text).map_err(|error| format!("{}", error))?;
+        let project = document.get("project").ok_or_else(|| String::from("Expected a [project] table"))?;
+
+        let string = |key: &str| match project.get(key) {
+            Some(value) => value.as_str()
+                .map(|string| Some(String::from(string)))
+                .ok_or_else(|| format!("'{}' should be a string", key)),
+            None => Ok(None)
+        };
+        let paths = |key: &str| match project.get(key) {
+            Some(value) => value.as_array()
+                .and_then(|values| values.iter().map(|value| value.as_str().map(|path| root.join(path))).collect())
+                .map(Some)
+                .ok_or_else(|| format!("'{}' should be an array of strings", key)),
+            None => Ok(None)
+        };
+
+        let entry = root.join(string("entry")?.ok_or_else(|| String::from("Expected the 'entry' file in [project]"))?);
+        let name = match string("name")? {
+            Some(name) => name,
+            None => entry.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+        };
+        let sources = match paths("sources")? {
+            Some(sources) => sources,
+            None => vec![entry.parent().map(Path::to_path_buf).unwrap_or_else(|| root.to_path_buf())]
+        };
+
+        Ok(Manifest {
+            name: name,
+            entry: entry,
+            sources: sources,
+            module_paths: paths("module_paths")?.unwrap_or_default(),
+            output: root.join(string("output")?.unwrap_or_else(|| String::from("build")))
+        })
+    }
+
+    /// Looks for the manifest in the directory and the ones above it
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        directory.ancestors()
+            .map(|directory| directory.join(MANIFEST_NAME))
+            .find(|path| path.is_file())
+    }
+}
+
+/// A cheap hash of the contents of a file, FNV-1a. None if the file can't be read
+fn hash_file(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Some(hash)
+}
+
+/// Escapes the characters that separate the parts of the state file, so paths can have them
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// The path that ``escape_path`` escaped, None if an escape isn't one it writes
+fn unescape_path(text: &str) -> Option<PathBuf> {
+    let mut path = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            path.push(c);
+            continue;
+        }
+        path.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None
+        });
+    }
+    Some(PathBuf::from(path))
+}
+
+/// The source files each output was built from, with their hashes at the time.
+/// It's written as lines of ``output``, ``source`` and ``hash``, separated by tabs, with the paths escaped
+struct BuildState {
+    entries: Vec<(PathBuf, PathBuf, u64)>
+}
+
+impl BuildState {
+    /// A missing or broken state file means everything gets built
+    fn load(path: &Path) -> BuildState {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let entries = text.lines().filter_map(|line| {
+            let mut parts = line.split('\t');
+            let output = unescape_path(parts.next()?)?;
+            let source = unescape_path(parts.next()?)?;
+            let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+            Some((output, source, hash))
+        }).collect();
+        BuildState { entries: entries }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut text = String::new();
+        for (output, source, hash) in self.entries.iter() {
+            text.push_str(&format!("{}\t{}\t{:016x}\n", escape_path(output), escape_path(source), hash));
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Whether the output exists and none of the files it was built from changed
+    fn is_up_to_date(&self, output: &Path) -> bool {
+        let mut sources = self.entries.iter().filter(|(built, _, _)| built == output).peekable();
+        output.is_file() && sources.peek().is_some()
+            && sources.all(|(_, source, hash)| hash_file(source) == Some(*hash))
+    }
+
+    fn set_sources(&mut self, output: &Path, sources: Vec<PathBuf>) {
+        self.entries.retain(|(built, _, _)| built != output);
+        for source in sources {
+            // A module is one of the modules it was built from
+            if self.entries.iter().any(|(built, known, _)| built == output && *known == source) {
+                continue;
+            }
+            if let Some(hash) = hash_file(&source) {
+                self.entries.push((output.to_path_buf(), source, hash));
+            }
+        }
+    }
+}
+
+/// What ``build`` did with every output
+pub struct BuildReport {
+    pub built: Vec<PathBuf>,
+    pub up_to_date: Vec<PathBuf>
+}
+
+/// The ``.tlang`` files in the directory and the ones in it, sorted so builds go in the same order
+fn find_sources(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), ProjectError> {
+    let entries = std::fs::read_dir(directory).map_err(|error| ProjectError::io(directory, error))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_sources(&path, files)?;
+        }else if path.extension().is_some_and(|extension| extension == "tlang") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compiles the entry file and the modules of the project to bytecode in the output directory,
+/// leaving the ones that are up to date. Modules go where they are in their source directory.
+/// The engine's natives are the ones the code can call, the project's module paths are only added for the build
+pub fn build(engine: &Engine, manifest: &Manifest) -> Result<BuildReport, ProjectError> {
+    let mut engine = engine.clone();
+    for path in manifest.module_paths.iter() {
+        engine.add_module_path(path);
+    }
+    engine.set_source_path(&manifest.entry);
+
+    let mut targets = vec![(manifest.entry.clone(), manifest.output.join(format!("{}.tlbc", manifest.name)))];
+    for directory in manifest.sources.iter() {
+        let mut files = Vec::new();
+        find_sources(directory, &mut files)?;
+        for file in files {
+            if file == manifest.entry {
+                continue;
+            }
+            let relative = file.strip_prefix(directory).unwrap_or(&file);
+            targets.push((file.clone(), manifest.output.join(relative).with_extension("tlbc")));
+        }
+    }
+
+    let state_path = manifest.output.join(STATE_NAME);
+    let mut state = BuildState::load(&state_path);
+    let mut report = BuildReport { built: Vec::new(), up_to_date: Vec::new() };
+    let mut result = Ok(());
+    for (source, output) in targets {
+        if state.is_up_to_date(&output) {
+            report.up_to_date.push(output);
+            continue;
+        }
+
+        let parsed = if source == manifest.entry {
+            std::fs::read_to_string(&source)
+                .map_err(|error| ProjectError::io(&source, error))
+                .and_then(|code| engine.parse(&code[..]).map_err(|error| ProjectError::Compile { path: source.clone(), error: Box::new(error) }))
+        }else{
+            engine.parse_module(&source).map_err(|error| ProjectError::Compile { path: source.clone(), error: Box::new(error) })
+        };
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                result = Err(error);
+                break;
+            }
+        };
+
+        let script = engine.compile_parsed(&parsed);
+        if let Some(directory) = output.parent() {
+            if let Err(error) = std::fs::create_dir_all(directory) {
+                result = Err(ProjectError::io(directory, error));
+                break;
+            }
+        }
+        if let Err(error) = bytecode_file::save(&script.program, &output.to_string_lossy()) {
+            result = Err(ProjectError::Io { path: output, message: format!("{}", error) });
+            break;
+        }
+
+        let mut sources = vec![source];
+        sources.extend(parsed.get_module_paths());
+        state.set_sources(&output, sources);
+        report.built.push(output);
+    }
+
+    // What was built before an error doesn't have to be built again
+    if std::fs::create_dir_all(&manifest.output).is_ok() {
+        state.save(&state_path).map_err(|error| ProjectError::io(&state_path, error))?;
+    }
+    result.map(|_| report)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,128 @@
+use super::{ Manifest, BuildState, build };
+use super::toml::Toml;
+use super::super::engine::Engine;
+use std::path::{ Path, PathBuf };
+
+#[test]
+fn parses_toml() {
+    let text = "# Comment\ntop = 1\n[project]\nname = \"game\" # After a value\nflags = [true, false,]\n\
+        paths = [\n    'src',\n    \"l\\tib\"\n]\nsize = -1_000\n";
+    let document = Toml::parse(text).unwrap();
+    assert_eq!(document.get("top").and_then(Toml::as_i64), Some(1));
+    let project = document.get("project").unwrap();
+    assert_eq!(project.get("name").and_then(Toml::as_str), Some("game"));
+    assert_eq!(project.get("flags").and_then(Toml::as_array).map(|flags| flags.len()), Some(2));
+    assert_eq!(project.get("paths"), Some(&Toml::Array(vec![Toml::Str(String::from("src")), Toml::Str(String::from("l\tib"))])));
+    assert_eq!(project.get("size").and_then(Toml::as_i64), Some(-1000));
+
+    let error = Toml::parse("[project]\nname = game\n").unwrap_err();
+    assert_eq!(error.line, 2);
+    assert!(Toml::parse("a = 1\na = 2").is_err());
+    assert!(Toml::parse("a = \"unterminated\n").is_err());
+    assert!(Toml::parse("a = 1 2").is_err());
+}
+
+#[test]
+fn reads_manifests() {
+    let root = Path::new("/project");
+    let manifest = Manifest::parse("[project]\nentry = \"src/main.tlang\"\nmodule_paths = [\"lib\"]", root).unwrap();
+    assert_eq!(manifest.name, "main");
+    assert_eq!(manifest.entry, root.join("src/main.tlang"));
+    assert_eq!(manifest.sources, vec![root.join("src")]);
+    assert_eq!(manifest.module_paths, vec![root.join("lib")]);
+    assert_eq!(manifest.output, root.join("build"));
+
+    assert!(Manifest::parse("[project]\nname = \"game\"", root).unwrap_err().contains("'entry'"));
+    assert!(Manifest::parse("[project]\nentry = 1", root).unwrap_err().contains("should be a string"));
+    assert!(Manifest::parse("entry = \"main.tlang\"", root).is_err());
+}
+
+/// A copy of ``tests/project``, so builds can change it
+fn copy_project(name: &str) -> PathBuf {
+    fn copy(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy(&path, &target);
+            }else{
+                std::fs::copy(&path, &target).unwrap();
+            }
+        }
+    }
+
+    let directory = std::env::temp_dir().join(format!("tlang_project_{}_{}", name, std::process::id()));
+    std::fs::remove_dir_all(&directory).ok();
+    copy(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/project"), &directory);
+    directory
+}
+
+fn names(paths: &[PathBuf], root: &Path) -> Vec<String> {
+    paths.iter().map(|path| path.strip_prefix(root).unwrap().to_string_lossy().into_owned()).collect()
+}
+
+#[test]
+fn builds_what_changed() {
+    let root = copy_project("changes");
+    let manifest = Manifest::load(&root.join("tlang.toml")).ok().unwrap();
+    let output = &manifest.output;
+
+    let report = build(&Engine::new(), &manifest).ok().unwrap();
+    assert_eq!(names(&report.built, output), vec!["game.tlbc", "shapes/square.tlbc", "words.tlbc"]);
+    assert!(report.up_to_date.is_empty());
+
+    let report = build(&Engine::new(), &manifest).ok().unwrap();
+    assert!(report.built.is_empty());
+    assert_eq!(report.up_to_date.len(), 3);
+
+    // The entry and the square import the units from the module path
+    std::fs::write(root.join("lib/units.tlang"), "def meter = 2.0;").unwrap();
+    let report = build(&Engine::new(), &manifest).ok().unwrap();
+    assert_eq!(names(&report.built, output), vec!["game.tlbc", "shapes/square.tlbc"]);
+
+    // The project's module paths are only used for the build
+    let engine = Engine::new();
+    std::fs::remove_file(output.join("words.tlbc")).unwrap();
+    let report = build(&engine, &manifest).ok().unwrap();
+    assert_eq!(names(&report.built, output), vec!["words.tlbc"]);
+    assert!(engine.parse("(import units; units.meter)").is_err());
+
+    let program = super::bytecode_file::load(&output.join("game.tlbc").to_string_lossy()).ok().unwrap();
+    let script = super::super::engine::Script::from_program(program);
+    assert!(engine.run(&script).is_ok());
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn stops_at_errors() {
+    let root = copy_project("errors");
+    let manifest = Manifest::load(&root.join("tlang.toml")).ok().unwrap();
+    std::fs::write(root.join("src/words.tlang"), "def greeting = ;").unwrap();
+
+    match build(&Engine::new(), &manifest) {
+        Err(super::ProjectError::Compile { path, .. }) => assert_eq!(path, root.join("src/main.tlang")),
+        _ => panic!("The build should have failed")
+    }
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn saves_paths_with_separators_in_them() {
+    let directory = std::env::temp_dir().join(format!("tlang_state_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("state");
+    let entries = vec![
+        (PathBuf::from("out/a\tb.tlbc"), PathBuf::from("src/a\tb.tlang"), 1),
+        (PathBuf::from("out/c\nd\\t.tlbc"), PathBuf::from("src/c\nd\\t.tlang"), 0xffff_0000_ffff_0000)
+    ];
+
+    BuildState { entries: entries.clone() }.save(&path).unwrap();
+    assert_eq!(BuildState::load(&path).entries, entries);
+
+    // A line with an escape that isn't written is left out
+    std::fs::write(&path, "out/a\\x.tlbc\tsrc/a.tlang\t0000000000000001\n").unwrap();
+    assert!(BuildState::load(&path).entries.is_empty());
+    std::fs::remove_dir_all(&directory).ok();
+}
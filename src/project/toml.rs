@@ -0,0 +1,285 @@
+//! Just enough TOML for project manifests: tables, strings, integers, booleans and arrays
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Toml {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Toml>),
+    /// Keeps the order of the keys, the way they're written
+    Table(Vec<(String, Toml)>)
+}
+
+/// Where the text isn't valid, lines start at 1
+#[derive(Clone, PartialEq, Debug)]
+pub struct TomlError {
+    pub line: usize,
+    pub message: String
+}
+
+impl std::fmt::Display for TomlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(line {}): {}", self.line, self.message)
+    }
+}
+
+impl Toml {
+    pub fn get(&self, key: &str) -> Option<&Toml> {
+        match self {
+            Toml::Table(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Toml::Str(string) => Some(&string[..]),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Toml::Int(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Toml::Bool(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Toml]> {
+        match self {
+            Toml::Array(values) => Some(&values[..]),
+            _ => None
+        }
+    }
+
+    /// Parses a document into a table, ``[name]`` headers start tables inside of it
+    pub fn parse(text: &str) -> Result<Toml, TomlError> {
+        let mut parser = Parser { chars: text.chars().collect(), index: 0, line: 1 };
+        let mut root: Vec<(String, Toml)> = Vec::new();
+        // The table the keys go in, None for the root
+        let mut table: Option<String> = None;
+
+        loop {
+            parser.skip_blank();
+            match parser.peek() {
+                None => break,
+                Some('[') => {
+                    parser.index += 1;
+                    parser.skip_spaces();
+                    let name = parser.parse_key()?;
+                    parser.skip_spaces();
+                    parser.expect(']')?;
+                    if root.iter().any(|(key, _)| *key == name) {
+                        return Err(parser.error(format!("'{}' is defined more than once", name)));
+                    }
+                    root.push((name.clone(), Toml::Table(Vec::new())));
+                    table = Some(name);
+                },
+                Some(_) => {
+                    let key = parser.parse_key()?;
+                    parser.skip_spaces();
+                    parser.expect('=')?;
+                    parser.skip_spaces();
+                    let value = parser.parse_value()?;
+
+                    let members = match &table {
+                        Some(name) => match root.iter_mut().find(|(key, _)| key == name) {
+                            Some((_, Toml::Table(members))) => members,
+                            _ => unreachable!()
+                        },
+                        None => &mut root
+                    };
+                    if members.iter().any(|(name, _)| *name == key) {
+                        return Err(parser.error(format!("'{}' is defined more than once", key)));
+                    }
+                    members.push((key, value));
+                }
+            }
+            parser.end_line()?;
+        }
+
+        Ok(Toml::Table(root))
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+    line: usize
+}
+
+impl Parser {
+    fn error(&self, message: String) -> TomlError {
+        TomlError {
+            line: self.line,
+            message: message
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.index += 1;
+            }
+        }
+    }
+
+    /// Skips whitespace, newlines and comments
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => self.line += 1,
+                Some('\r') => {},
+                _ => return
+            }
+            self.index += 1;
+        }
+    }
+
+    /// Only a comment may follow a value or a header on its line
+    fn end_line(&mut self) -> Result<(), TomlError> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(c) => Err(self.error(format!("Expected the end of the line, found '{}'", c)))
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), TomlError> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.index += 1;
+                Ok(())
+            },
+            Some('\n') | None => Err(self.error(format!("Expected '{}', found the end of the line", c))),
+            Some(found) => Err(self.error(format!("Expected '{}', found '{}'", c, found)))
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, TomlError> {
+        if self.peek() == Some('"') {
+            return self.parse_string();
+        }
+
+        let start = self.index;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                self.index += 1;
+            }else{
+                break;
+            }
+        }
+        if start == self.index {
+            return Err(self.error(String::from("Expected a key")));
+        }
+        Ok(self.chars[start..self.index].iter().collect())
+    }
+
+    fn parse_value(&mut self) -> Result<Toml, TomlError> {
+        match self.peek() {
+            Some('"') => Ok(Toml::Str(self.parse_string()?)),
+            Some('\'') => {
+                // Literal strings don't have escapes
+                self.index += 1;
+                let start = self.index;
+                while !matches!(self.peek(), None | Some('\'') | Some('\n')) {
+                    self.index += 1;
+                }
+                let string = self.chars[start..self.index].iter().collect();
+                self.expect('\'')?;
+                Ok(Toml::Str(string))
+            },
+            Some('[') => {
+                self.index += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.index += 1;
+                        return Ok(Toml::Array(values));
+                    }
+                    values.push(self.parse_value()?);
+                    self.skip_blank();
+                    // A comma after the last value is allowed
+                    if self.peek() == Some(',') {
+                        self.index += 1;
+                    }else{
+                        self.expect(']')?;
+                        return Ok(Toml::Array(values));
+                    }
+                }
+            },
+            Some(c) if c.is_ascii_alphabetic() => {
+                let word = self.parse_key()?;
+                match &word[..] {
+                    "true" => Ok(Toml::Bool(true)),
+                    "false" => Ok(Toml::Bool(false)),
+                    _ => Err(self.error(format!("Unexpected '{}', strings need quotes", word)))
+                }
+            },
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() => {
+                let start = self.index;
+                self.index += 1;
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_digit() || c == '_' {
+                        self.index += 1;
+                    }else{
+                        break;
+                    }
+                }
+                let text: String = self.chars[start..self.index].iter().filter(|c| **c != '_').collect();
+                text.parse::<i64>().map(Toml::Int).map_err(|_| self.error(format!("Invalid integer '{}'", text)))
+            },
+            Some('\n') | None => Err(self.error(String::from("Expected a value, found the end of the line"))),
+            Some(c) => Err(self.error(format!("Unexpected '{}'", c)))
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, TomlError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let c = match self.peek() {
+                Some('\n') | None => return Err(self.error(String::from("Unterminated string"))),
+                Some(c) => c
+            };
+            self.index += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error(String::from("Unterminated string")))?;
+                    self.index += 1;
+                    string.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        other => return Err(self.error(format!("Invalid escape '\\{}'", other)))
+                    });
+                },
+                c => string.push(c)
+            }
+        }
+    }
+}
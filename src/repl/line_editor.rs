@@ -0,0 +1,533 @@
+//! Reading lines from a terminal with editing and history, without any crates.
+//!
+//! On Linux the terminal is put in raw mode through termios, so every key press can be handled.
+//! Anywhere else, or when stdin isn't a terminal, lines are read as they are with no editing.
+//! The editing itself is done by ``EditState``, which doesn't touch the terminal.
+
+use std::io::{ Read, Write };
+use std::path::PathBuf;
+
+/// The most lines the history keeps, the oldest ones are dropped first
+const MAX_HISTORY: usize = 1000;
+const HISTORY_FILE: &str = ".tlang_history";
+
+#[cfg(target_os = "linux")]
+mod termios {
+    //! The parts of termios that raw mode needs, the layout is the one glibc and musl use
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Termios {
+        pub c_iflag: u32,
+        pub c_oflag: u32,
+        pub c_cflag: u32,
+        pub c_lflag: u32,
+        pub c_line: u8,
+        pub c_cc: [u8; 32],
+        pub c_ispeed: u32,
+        pub c_ospeed: u32
+    }
+
+    pub const BRKINT: u32 = 0o2;
+    pub const ICRNL: u32 = 0o400;
+    pub const INPCK: u32 = 0o20;
+    pub const ISTRIP: u32 = 0o40;
+    pub const IXON: u32 = 0o2000;
+    pub const OPOST: u32 = 0o1;
+    pub const CS8: u32 = 0o60;
+    pub const ECHO: u32 = 0o10;
+    pub const ICANON: u32 = 0o2;
+    pub const IEXTEN: u32 = 0o100000;
+    pub const ISIG: u32 = 0o1;
+    pub const VTIME: usize = 5;
+    pub const VMIN: usize = 6;
+    pub const TCSAFLUSH: i32 = 2;
+
+    extern "C" {
+        pub fn isatty(fd: i32) -> i32;
+        pub fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+        pub fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32;
+    }
+}
+
+/// Raw mode for as long as it's alive, the terminal is restored when it's dropped
+#[cfg(target_os = "linux")]
+struct RawMode {
+    original: termios::Termios
+}
+
+#[cfg(target_os = "linux")]
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        use termios::*;
+        unsafe {
+            let mut original = std::mem::zeroed::<Termios>();
+            if tcgetattr(0, &mut original) != 0 {
+                return None;
+            }
+
+            let mut raw = original;
+            raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+            raw.c_oflag &= !OPOST;
+            raw.c_cflag |= CS8;
+            raw.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+            raw.c_cc[VMIN] = 1;
+            raw.c_cc[VTIME] = 0;
+            if tcsetattr(0, TCSAFLUSH, &raw) != 0 {
+                return None;
+            }
+            Some(RawMode { original: original })
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            termios::tcsetattr(0, termios::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn stdin_is_tty() -> bool {
+    unsafe { termios::isatty(0) == 1 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn stdin_is_tty() -> bool {
+    false
+}
+
+/// The lines that were entered before, oldest first
+pub struct History {
+    entries: Vec<String>,
+    /// Where the history is saved, if anywhere
+    path: Option<PathBuf>
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            entries: Vec::new(),
+            path: None
+        }
+    }
+
+    /// The history in the home directory, it's empty if there isn't one yet
+    pub fn load_default() -> History {
+        let path = match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(HISTORY_FILE),
+            None => return History::new()
+        };
+        let entries = std::fs::read_to_string(&path)
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        History {
+            entries: entries,
+            path: Some(path)
+        }
+    }
+
+    pub fn get_entries(&self) -> &[String] {
+        &self.entries[..]
+    }
+
+    /// Adds a line and saves the history, blank lines and repeats of the last line are skipped
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.last().map(|last| last == line).unwrap_or(false) {
+            return;
+        }
+        self.entries.push(String::from(line));
+        if self.entries.len() > MAX_HISTORY {
+            let excess = self.entries.len() - MAX_HISTORY;
+            self.entries.drain(..excess);
+        }
+
+        if let Some(path) = &self.path {
+            let mut text = self.entries.join("\n");
+            text.push('\n');
+            // Losing the history isn't worth interrupting the shell for
+            std::fs::write(path, text).ok();
+        }
+    }
+
+    /// The index of the newest line before ``before`` that contains ``query``
+    fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())].iter().rposition(|entry| entry.contains(query))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// Ctrl-C
+    Interrupt,
+    /// Ctrl-D
+    EndOfFile,
+    /// Ctrl-R
+    Search,
+    /// Ctrl-K
+    KillToEnd,
+    /// Ctrl-U
+    KillToStart,
+    Escape,
+    Unknown
+}
+
+/// Reads a key press from the bytes of a terminal, ``None`` if they end
+pub fn read_key(bytes: &mut impl Iterator<Item = u8>) -> Option<Key> {
+    let byte = bytes.next()?;
+    Some(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        127 | 8 => Key::Backspace,
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Interrupt,
+        4 => Key::EndOfFile,
+        5 => Key::End,
+        6 => Key::Right,
+        11 => Key::KillToEnd,
+        14 => Key::Down,
+        16 => Key::Up,
+        18 => Key::Search,
+        21 => Key::KillToStart,
+        27 => read_escape(bytes),
+        byte if byte < 32 => Key::Unknown,
+        byte => read_char(byte, bytes)
+    })
+}
+
+/// Reads the rest of an escape sequence, after the escape byte
+fn read_escape(bytes: &mut impl Iterator<Item = u8>) -> Key {
+    match bytes.next() {
+        Some(b'[') | Some(b'O') => {},
+        _ => return Key::Escape
+    }
+
+    match bytes.next() {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') => {
+            // Sequences like ``ESC [ 3 ~``, anything after the first digit is skipped up to the ``~``
+            let mut last = digit;
+            while last != b'~' {
+                match bytes.next() {
+                    Some(byte) if byte.is_ascii_digit() || byte == b';' || byte == b'~' => last = byte,
+                    _ => return Key::Unknown
+                }
+            }
+            match digit {
+                b'1' | b'7' => Key::Home,
+                b'3' => Key::Delete,
+                b'4' | b'8' => Key::End,
+                _ => Key::Unknown
+            }
+        },
+        _ => Key::Unknown
+    }
+}
+
+/// Reads a UTF-8 character that starts with ``first``
+fn read_char(first: u8, bytes: &mut impl Iterator<Item = u8>) -> Key {
+    let length = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Key::Unknown
+    };
+
+    let mut encoded = vec![first];
+    for _ in 1..length {
+        match bytes.next() {
+            Some(byte) => encoded.push(byte),
+            None => return Key::Unknown
+        }
+    }
+    match std::str::from_utf8(&encoded).ok().and_then(|text| text.chars().next()) {
+        Some(c) => Key::Char(c),
+        None => Key::Unknown
+    }
+}
+
+/// What a key press did to the line
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    Continue,
+    Submit(String),
+    Interrupt,
+    EndOfFile
+}
+
+/// A Ctrl-R search through the history
+struct Search {
+    query: String,
+    /// The history entry that matches, if any does
+    found: Option<usize>
+}
+
+/// The line being edited
+pub struct EditState {
+    line: Vec<char>,
+    /// Where the cursor is, in characters
+    cursor: usize,
+    /// The history entry that's shown, ``None`` for the line that's being written
+    history_index: Option<usize>,
+    /// The line that was being written before moving through the history
+    draft: Vec<char>,
+    search: Option<Search>
+}
+
+impl EditState {
+    pub fn new() -> EditState {
+        EditState {
+            line: Vec::new(),
+            cursor: 0,
+            history_index: None,
+            draft: Vec::new(),
+            search: None
+        }
+    }
+
+    pub fn get_line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn set_line(&mut self, line: &str) {
+        self.line = line.chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    fn show_history(&mut self, index: Option<usize>, history: &History) {
+        if self.history_index.is_none() {
+            self.draft = self.line.clone();
+        }
+        self.history_index = index;
+        match index {
+            Some(index) => self.set_line(&history.entries[index][..]),
+            None => {
+                self.line = std::mem::take(&mut self.draft);
+                self.cursor = self.line.len();
+            }
+        }
+    }
+
+    /// Handles a key during a search, gives back true if it ended the search
+    fn handle_search(&mut self, key: Key, history: &History) -> bool {
+        let search = self.search.as_mut().unwrap();
+        match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                let before = search.found.map(|found| found + 1).unwrap_or(history.entries.len());
+                search.found = history.search(&search.query[..], before);
+            },
+            Key::Backspace => {
+                search.query.pop();
+                search.found = history.search(&search.query[..], history.entries.len());
+            },
+            Key::Search => {
+                let before = search.found.unwrap_or(history.entries.len());
+                if let Some(found) = history.search(&search.query[..], before) {
+                    search.found = Some(found);
+                }
+            },
+            Key::Interrupt | Key::Escape => {
+                self.search = None;
+                return true;
+            },
+            _ => {
+                // Any other key takes the match and is then handled as usual
+                if let Some(found) = search.found {
+                    self.set_line(&history.entries[found][..]);
+                }
+                self.search = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn handle(&mut self, key: Key, history: &History) -> Action {
+        if self.search.is_some() {
+            let ended = self.handle_search(key, history);
+            if !ended || key == Key::Interrupt || key == Key::Escape {
+                return Action::Continue;
+            }
+        }
+
+        match key {
+            Key::Char(c) => {
+                self.line.insert(self.cursor, c);
+                self.cursor += 1;
+            },
+            Key::Enter => return Action::Submit(self.get_line()),
+            Key::Backspace => if self.cursor > 0 {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            },
+            Key::Delete => if self.cursor < self.line.len() {
+                self.line.remove(self.cursor);
+            },
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.line.len(),
+            Key::Up => {
+                let index = match self.history_index {
+                    Some(index) => index.checked_sub(1),
+                    None => history.entries.len().checked_sub(1)
+                };
+                if index.is_some() {
+                    self.show_history(index, history);
+                }
+            },
+            Key::Down => if let Some(index) = self.history_index {
+                let next = if index + 1 < history.entries.len() { Some(index + 1) } else { None };
+                self.show_history(next, history);
+            },
+            Key::KillToEnd => self.line.truncate(self.cursor),
+            Key::KillToStart => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            },
+            Key::Search => self.search = Some(Search { query: String::new(), found: None }),
+            Key::Interrupt => return Action::Interrupt,
+            Key::EndOfFile => if self.line.is_empty() {
+                return Action::EndOfFile;
+            }else if self.cursor < self.line.len() {
+                self.line.remove(self.cursor);
+            },
+            Key::Escape | Key::Unknown => {}
+        }
+        Action::Continue
+    }
+
+    /// What to write to the terminal to show the line after the prompt, with the cursor in place
+    pub fn render(&self, prompt: &str, history: &History) -> String {
+        let (prompt, line, cursor) = match &self.search {
+            Some(search) => {
+                let found = search.found.map(|found| &history.entries[found][..]).unwrap_or("");
+                (format!("(reverse-i-search)'{}': ", search.query), String::from(found), found.chars().count())
+            },
+            None => (String::from(prompt), self.get_line(), self.cursor)
+        };
+
+        let column = prompt.chars().count() + cursor;
+        let mut text = format!("\r{}{}\x1b[K\r", prompt, line);
+        if column > 0 {
+            text.push_str(&format!("\x1b[{}C", column));
+        }
+        text
+    }
+}
+
+/// What reading a line resulted in
+#[derive(PartialEq, Debug)]
+pub enum ReadResult {
+    Line(String),
+    /// Ctrl-C, the line was thrown away
+    Interrupted,
+    EndOfFile
+}
+
+pub struct LineEditor {
+    history: History,
+    /// Whether the terminal supports editing, otherwise lines are read as they are
+    raw: bool
+}
+
+impl LineEditor {
+    /// Edits lines if stdin is a terminal, the history is kept in the home directory
+    pub fn new() -> LineEditor {
+        let raw = stdin_is_tty();
+        LineEditor {
+            history: if raw { History::load_default() } else { History::new() },
+            raw: raw
+        }
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> std::io::Result<ReadResult> {
+        if self.raw {
+            if let Some(result) = self.read_raw(prompt) {
+                return result;
+            }
+        }
+
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(ReadResult::EndOfFile);
+        }
+        Ok(ReadResult::Line(String::from(line.trim_end_matches(['\n', '\r']))))
+    }
+
+    /// ``None`` if the terminal couldn't be put in raw mode
+    #[cfg(target_os = "linux")]
+    fn read_raw(&mut self, prompt: &str) -> Option<std::io::Result<ReadResult>> {
+        let raw_mode = RawMode::enable()?;
+        let result = self.edit(prompt);
+        drop(raw_mode);
+
+        if let Ok(ReadResult::Line(line)) = &result {
+            self.history.add(&line[..]);
+        }
+        Some(result)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_raw(&mut self, _prompt: &str) -> Option<std::io::Result<ReadResult>> {
+        None
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn edit(&mut self, prompt: &str) -> std::io::Result<ReadResult> {
+        let stdin = std::io::stdin();
+        let mut bytes = stdin.lock().bytes().map_while(Result::ok);
+        let mut stdout = std::io::stdout();
+        let mut state = EditState::new();
+
+        loop {
+            write!(stdout, "{}", state.render(prompt, &self.history))?;
+            stdout.flush()?;
+
+            let key = match read_key(&mut bytes) {
+                Some(key) => key,
+                None => return Ok(ReadResult::EndOfFile)
+            };
+            let result = match state.handle(key, &self.history) {
+                Action::Continue => continue,
+                Action::Submit(line) => ReadResult::Line(line),
+                Action::Interrupt => ReadResult::Interrupted,
+                Action::EndOfFile => ReadResult::EndOfFile
+            };
+
+            // The output isn't translated in raw mode, so the line has to be ended by hand
+            if result == ReadResult::Interrupted {
+                write!(stdout, "^C")?;
+            }
+            write!(stdout, "\r\n")?;
+            stdout.flush()?;
+            return Ok(result);
+        }
+    }
+}
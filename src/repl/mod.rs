@@ -0,0 +1,165 @@
+//! The interactive shell, ``tlang run`` without a file.
+//! Code is run in a ``Session``, so variables are kept from one input to the next,
+//! and lines that start with ``:`` are commands for looking at code instead of running it.
+
+use super::engine::{ Engine, Session, Error };
+use super::tokenizer;
+use super::vm::natives::Value;
+use super::TreeDump;
+
+mod line_editor;
+
+pub use line_editor::{ LineEditor, History, EditState, Key, Action, ReadResult, read_key };
+
+const HELP: &str = "\
+Code is run when it's complete, a block that isn't closed continues on the next line.
+Variables are kept from one input to the next.
+
+:type <code>      Print the type of the code without running it
+:ast <code>       Print the syntax tree of the code
+:tokens <code>    Print the tokens of the code
+:scopes           Print the scopes, with the variables that are set
+:load <path>      Run the code in a file
+:reset            Forget the variables, and any unfinished input
+:help             Print this
+:quit, exit       Exit the shell";
+
+pub struct Repl {
+    session: Session,
+    /// The lines of an input that isn't complete yet
+    pending: String
+}
+
+impl Repl {
+    pub fn new(engine: Engine) -> Repl {
+        Repl {
+            session: Session::new(engine),
+            pending: String::new()
+        }
+    }
+
+    /// Whether the last line left a block open, so the next one continues it
+    pub fn is_continuing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn print_error(error: Error) {
+        match error {
+            Error::Parse(error) | Error::Type(error) => {
+                println!("Got an error!");
+                error.print();
+            },
+            error => println!("{}", error)
+        }
+    }
+
+    fn eval(&mut self, code: &str) {
+        let evaluation = match self.session.eval(code) {
+            Ok(evaluation) => evaluation,
+            Err(error) => return Repl::print_error(error)
+        };
+        match &evaluation.value {
+            Some(Value::Str(string)) => println!("= \"{}\" : string", string),
+            Some(value) => println!("= {} : {}", value, value.get_type()),
+            None => {}
+        }
+        for note in evaluation.forgotten.iter() {
+            println!("Note: {}", note);
+        }
+    }
+
+    /// Runs a command, the line without the ``:``. Returns false if the shell should exit
+    fn command(&mut self, input: &str) -> bool {
+        let (command, argument) = match input.find(' ') {
+            Some(index) => (&input[..index], input[index..].trim()),
+            None => (input, "")
+        };
+
+        let engine = self.session.get_engine();
+        match command {
+            "type" | "t" => match engine.parse(argument) {
+                Ok(parsed) => match parsed.get_result_type() {
+                    Some(value_type) => println!("{}", value_type),
+                    None => println!("undef")
+                },
+                Err(error) => Repl::print_error(error)
+            },
+            "ast" => match engine.parse(argument) {
+                Ok(parsed) => parsed.tree.print(),
+                Err(error) => Repl::print_error(error)
+            },
+            "tokens" => {
+                let (tokens, errors, meta) = tokenizer::tokenize(argument);
+                for token in tokens.iter() {
+                    token.print();
+                }
+                for error in errors.iter() {
+                    println!("({}): {}", meta.index_to_loc(error.loc), error.msg);
+                }
+            },
+            "scopes" => engine.create_scopes().0.print(),
+            "load" | "l" => match std::fs::read_to_string(argument) {
+                Ok(code) => self.eval(&code[..]),
+                Err(err) => println!("Error opening file! {}", err)
+            },
+            "reset" => {
+                self.session.reset();
+                self.pending.clear();
+            },
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return false,
+            _ => println!("Unknown command ':{}', ':help' lists the commands", command)
+        }
+
+        true
+    }
+
+    /// Throws away the input that isn't complete yet
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Handles a line of input, returns false if the shell should exit
+    pub fn input(&mut self, line: &str) -> bool {
+        let trimmed = line.trim();
+        if !self.is_continuing() {
+            if trimmed == "exit" || trimmed == "quit" {
+                return false;
+            }
+            if trimmed.is_empty() {
+                return true;
+            }
+        }
+        if let Some(command) = trimmed.strip_prefix(':') {
+            return self.command(command);
+        }
+
+        self.pending.push_str(line.trim_end_matches('\n'));
+        self.pending.push('\n');
+        if self.session.get_engine().is_incomplete(&self.pending[..]) {
+            return true;
+        }
+
+        let code = std::mem::take(&mut self.pending);
+        self.eval(&code[..]);
+        true
+    }
+
+    /// Reads lines until exit or the end of the input
+    pub fn run_prompt(&mut self) {
+        println!("\n-- TROLLEDLANG SHELL --\n'exit' to exit the shell, ':help' for a list of commands\n");
+        let mut editor = LineEditor::new();
+        loop {
+            let prompt = if self.is_continuing() { "... " } else { "> " };
+            match editor.read_line(prompt) {
+                Ok(ReadResult::Line(line)) => if !self.input(&line[..]) { break; },
+                Ok(ReadResult::Interrupted) => self.cancel(),
+                Ok(ReadResult::EndOfFile) => break,
+                Err(_) => println!("Invalid input!")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use super::{ Repl, Key, Action, History, EditState, read_key };
+use super::super::engine::Engine;
+
+#[test]
+fn continues_unclosed_blocks() {
+    let mut repl = Repl::new(Engine::new());
+    assert!(repl.input("(\n"));
+    assert!(repl.is_continuing());
+    assert!(repl.input("  x = 1;\n"));
+    assert!(repl.is_continuing());
+    assert!(repl.input("  x)\n"));
+    assert!(!repl.is_continuing());
+}
+
+#[test]
+fn runs_commands() {
+    let mut repl = Repl::new(Engine::new());
+    assert!(repl.input("(x = 1;\n"));
+    assert!(repl.input(":reset\n"));
+    assert!(!repl.is_continuing());
+    assert!(repl.input(":help\n"));
+    assert!(!repl.input(":quit\n"));
+    assert!(!repl.input("exit\n"));
+}
+
+fn keys(bytes: &[u8]) -> Vec<Key> {
+    let mut bytes = bytes.iter().copied();
+    std::iter::from_fn(|| read_key(&mut bytes)).collect()
+}
+
+/// Types the bytes into a new line, and gives back what the last key did
+fn type_keys(bytes: &[u8], history: &History) -> (EditState, Action) {
+    let mut state = EditState::new();
+    let mut action = Action::Continue;
+    for key in keys(bytes) {
+        action = state.handle(key, history);
+    }
+    (state, action)
+}
+
+fn history(entries: &[&str]) -> History {
+    let mut history = History::new();
+    for entry in entries {
+        history.add(entry);
+    }
+    history
+}
+
+#[test]
+fn reads_keys() {
+    assert_eq!(keys(b"a\x1b[A\x1b[D\x1b[3~\x1bOH\x1b[4~\x7f\r\x12\x03"), vec![
+        Key::Char('a'), Key::Up, Key::Left, Key::Delete, Key::Home, Key::End,
+        Key::Backspace, Key::Enter, Key::Search, Key::Interrupt
+    ]);
+    assert_eq!(keys("é".as_bytes()), vec![Key::Char('é')]);
+}
+
+#[test]
+fn edits_lines() {
+    let history = History::new();
+    let (state, action) = type_keys(b"x = 2\x1b[D\x1b[D\x7f1\r", &history);
+    assert_eq!(action, Action::Submit(String::from("x 1 2")));
+    assert_eq!(state.get_cursor(), 3);
+
+    let (state, _) = type_keys(b"abc\x01\x1b[Cd\x0b", &history);
+    assert_eq!(state.get_line(), "ad");
+    assert_eq!(type_keys(b"\x04", &history).1, Action::EndOfFile);
+    assert_eq!(type_keys(b"abc\x03", &history).1, Action::Interrupt);
+}
+
+#[test]
+fn moves_through_history() {
+    let history = history(&["x = 1", "y = 2", "y = 2", "  "]);
+    assert_eq!(history.get_entries(), &[String::from("x = 1"), String::from("y = 2")]);
+
+    assert_eq!(type_keys(b"z\x1b[A\x1b[A", &history).0.get_line(), "x = 1");
+    assert_eq!(type_keys(b"z\x1b[A\x1b[A\x1b[A\x1b[B", &history).0.get_line(), "y = 2");
+    assert_eq!(type_keys(b"z\x1b[A\x1b[B", &history).0.get_line(), "z");
+}
+
+#[test]
+fn searches_history() {
+    let history = history(&["x = 1", "greeting = \"hi\"", "x = 2", "print x"]);
+    assert_eq!(type_keys(b"\x12x =\r", &history).1, Action::Submit(String::from("x = 2")));
+    assert_eq!(type_keys(b"\x12x =\x12\r", &history).1, Action::Submit(String::from("x = 1")));
+    assert_eq!(type_keys(b"\x12gr\x1b[C", &history).0.get_line(), "greeting = \"hi\"");
+    assert_eq!(type_keys(b"a\x12x\x03", &history).0.get_line(), "a");
+}
@@ -1,4 +1,5 @@
-use super::Needle;
+use std::fmt::Write;
+use super::needle::Needle;
 use std::vec::Vec;
 use super::TreeDump;
 use super::needle::{ Loc, TextMetaData };
@@ -101,15 +102,15 @@ impl Token {
 }
 
 impl TreeDump for Token {
-    fn print_with_indent(&self, indent: usize, indent_style: &str) {
-        print!("{}({}): ", indent_style.repeat(indent), self.start);
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str) {
+        write!(text, "{}({}): ", indent_style.repeat(indent), self.start).unwrap();
         use TokenType::*;
         match &self.token_type {
-            Keyword(keyword) => println!("keyword '{}'", keyword),
-            Operator(operator) => println!("operator '{}'", operator),
-            Identifier(string) => println!("identifier '{}'", string),
-            Literal(literal) => println!("literal {}", literal),
-            Invalid => println!("invalid")
+            Keyword(keyword) => writeln!(text, "keyword '{}'", keyword).unwrap(),
+            Operator(operator) => writeln!(text, "operator '{}'", operator).unwrap(),
+            Identifier(string) => writeln!(text, "identifier '{}'", string).unwrap(),
+            Literal(literal) => writeln!(text, "literal {}", literal).unwrap(),
+            Invalid => writeln!(text, "invalid").unwrap()
         }
     }
 }
@@ -178,7 +179,7 @@ pub const OPERATOR_TOKENS: [(&str, OperatorType); 6] = [
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum KeywordType {    
-    If, While, Loop, As, Run, Assign,
+    If, While, Loop, As, Run, Assign, Import, Def, Dot, Colon, Arrow,
     BlockOpen, BlockClose, BlockSeparator, 
     ArrayOpen, ArrayClose, ArraySeparator
 }
@@ -193,6 +194,11 @@ impl std::fmt::Display for KeywordType {
             As => "as",
             Run => "run",
             Assign => "assign",
+            Import => "import",
+            Def => "def",
+            Dot => "dot",
+            Colon => "colon",
+            Arrow => "arrow",
             BlockOpen => "block open",
             BlockClose => "block close",
             BlockSeparator => "block separator",
@@ -205,12 +211,14 @@ impl std::fmt::Display for KeywordType {
 
 /// [substr that produces keyword, The keyword enum member, 
 /// isAlphabetic(can't be surrounded by other alphabetic things)]
-pub const KEYWORD_TOKENS: [(&str, KeywordType, bool); 13] = [
+pub const KEYWORD_TOKENS: [(&str, KeywordType, bool); 18] = [
     ("if",      KeywordType::If,                true ),
     ("while",   KeywordType::While,             true ),
     ("loop",    KeywordType::Loop,  	        true ),
     ("as",      KeywordType::As,                true ),
     ("run",     KeywordType::Run,               true ),
+    ("import",  KeywordType::Import,            true ),
+    ("def",     KeywordType::Def,               true ),
     ("=",       KeywordType::Assign,            false),
     ("#(",      KeywordType::BlockOpen,         false),
 
@@ -220,17 +228,20 @@ pub const KEYWORD_TOKENS: [(&str, KeywordType, bool); 13] = [
 
     (",",       KeywordType::ArraySeparator,    false),
     ("[",       KeywordType::ArrayOpen,         false),
-    ("]",       KeywordType::ArrayClose,        false)
+    ("]",       KeywordType::ArrayClose,        false),
+    (".",       KeywordType::Dot,               false),
+    (":",       KeywordType::Colon,             false),
+    ("->",      KeywordType::Arrow,             false)
 ];
 
 /// *IMPORTANT: The needle will change, so buffering the change 
 /// with push_state and pop_state around this function is vital*
-pub fn try_tokenize_word<'a>(needle: &mut Needle<char>, meta: &TextMetaData) -> Result<Token, Error> {
+pub fn try_tokenize_word(needle: &mut Needle<char>, meta: &TextMetaData) -> Result<Token, Error> {
     let start = needle.get_index();
     
     let mut is_first = true;
     while let Some(&c) = needle.peek() {
-        if !(c.is_alphabetic() || c == '_' || (!is_first && c.is_digit(10))) {
+        if !(c.is_alphabetic() || c == '_' || (!is_first && c.is_ascii_digit())) {
             break;
         }else{
             is_first = false;
@@ -340,7 +351,7 @@ pub fn try_tokenize_number(needle: &mut Needle<char>, meta: &TextMetaData) -> Re
 
     if start == needle.get_index() {
         return Err(Error::at_needle(needle, 1, 
-            "Expected something after '.' to make a float or get a member of a structure"));
+            "Expected a digit after '.' to make a float"));
     }
 
     Ok(
@@ -374,7 +385,15 @@ pub fn tokenize(chars: &str) -> (Vec<Token>, Vec<Error>, TextMetaData) {
     let mut needle = Needle::from_str(chars, 0usize);
     let meta = needle.get_meta_data();
     let mut tokens = Vec::new();
-    let mut errors = Vec::new();
+    let errors = Vec::new();
+
+    // A shebang line lets scripts be run directly, it's for the shell and not part of the code
+    if needle.matches_slice("#!") {
+        while let Some(c) = needle.read() {
+            if *c == '\n' { break; }
+        }
+    }
+
     'outer: loop {
         // Skip the shitespace in the beginning
         loop {
@@ -395,6 +414,10 @@ pub fn tokenize(chars: &str) -> (Vec<Token>, Vec<Error>, TextMetaData) {
         // This one doesn't change the index so we don't have to push/pop
         {
             for op in &OPERATOR_TOKENS {
+                // Keywords that start like an operator, like '->', go first
+                if KEYWORD_TOKENS.iter().any(|keyword| keyword.0.len() > op.0.len() && needle.matches_slice(keyword.0)) {
+                    continue;
+                }
                 if needle.matches_slice(op.0) {
                     tokens.push(Token::operator(meta.index_to_loc(needle.get_index()), op.1));
                     needle.skip(op.0.len());
@@ -404,14 +427,14 @@ pub fn tokenize(chars: &str) -> (Vec<Token>, Vec<Error>, TextMetaData) {
 
             for keyword in &KEYWORD_TOKENS {
                 if needle.matches_slice(keyword.0) {
-                    if keyword.2 {
-                        if needle.match_func_offset(-1, |c| c.is_alphabetic())
-                            || needle.match_func_offset(
-                                    keyword.0.len() as isize, 
-                                    |c| c.is_alphabetic()
-                                ) {
-                            continue;
-                        }
+                    // Keywords made of letters can't be part of a longer word
+                    if keyword.2 && (needle.match_func_offset(-1, |c| c.is_alphabetic())
+                            || needle.match_func_offset(keyword.0.len() as isize, |c| c.is_alphabetic())) {
+                        continue;
+                    }
+                    // A dot before a digit starts a float, like '.5'
+                    if keyword.1 == KeywordType::Dot && needle.match_func_offset(1, |c| c.is_ascii_digit()) {
+                        continue;
                     }
                     tokens.push(Token::keyword(meta.index_to_loc(needle.get_index()), keyword.1));
                     needle.skip(keyword.0.len());
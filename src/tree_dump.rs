@@ -1,6 +1,14 @@
 pub trait TreeDump {
     fn print(&self) {
-        self.print_with_indent(0, " : ");
+        print!("{}", self.dump());
     }
-    fn print_with_indent(&self, indent: usize, indent_style: &str);
-}
\ No newline at end of file
+
+    /// The text ``print`` writes, for writing it somewhere other than stdout
+    fn dump(&self) -> String {
+        let mut text = String::new();
+        self.dump_with_indent(&mut text, 0, " : ");
+        text
+    }
+
+    fn dump_with_indent(&self, text: &mut String, indent: usize, indent_style: &str);
+}
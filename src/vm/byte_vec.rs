@@ -13,7 +13,8 @@ impl<'a> Iterator for ByteVecIterator<'a> {
     type Item = u8;
 
     fn next(&mut self) -> Option<u8> {
-        if self.batch_index <= self.byte_vec.batch_index && self.local_index <= self.byte_vec.local_index {
+        let index = (self.batch_index << BATCH_SIZE_BYTES) + self.local_index;
+        if index < self.byte_vec.get_index() {
             let result = unsafe {
                 let batch = self.byte_vec.batches.get_unchecked(self.batch_index);
                 batch.get_unchecked(self.local_index)
@@ -32,6 +33,7 @@ impl<'a> Iterator for ByteVecIterator<'a> {
     }
 }
 
+#[derive(Clone)]
 pub struct ByteVec {
     batches: Vec<[u8; 1 << BATCH_SIZE_BYTES]>,
     local_index: usize,
@@ -51,7 +53,7 @@ impl ByteVec {
         (self.batch_index << BATCH_SIZE_BYTES) + self.local_index
     }
 
-    pub fn iter(&self) -> ByteVecIterator {
+    pub fn iter(&self) -> ByteVecIterator<'_> {
         ByteVecIterator {
             byte_vec: self,
             batch_index: 0,
@@ -61,61 +63,74 @@ impl ByteVec {
 
     unsafe fn get_byte_unchecked(&self, index: usize) -> u8 {
         let batch_index = index >> BATCH_SIZE_BYTES;
-        let batch = self.batches[batch_index];
-        batch[index - (batch_index << BATCH_SIZE_BYTES)]
+        let batch = self.batches.get_unchecked(batch_index);
+        *batch.get_unchecked(index - (batch_index << BATCH_SIZE_BYTES))
     }
 
-    unsafe fn get_value_slow_unchecked<T: Sized + Clone + std::fmt::Display>(&self, index: usize) -> T {
-        let temp_buf = &mut [0u8; 32];
-        for i in 0..size_of::<T>() {
-            temp_buf[i] = self.get_byte_unchecked(index + i);
+    unsafe fn set_byte_unchecked(&mut self, index: usize, byte: u8) {
+        let batch_index = index >> BATCH_SIZE_BYTES;
+        let batch = self.batches.get_unchecked_mut(batch_index);
+        *batch.get_unchecked_mut(index - (batch_index << BATCH_SIZE_BYTES)) = byte;
+    }
+
+    /// Reads a value at any index, returns None if it doesn't fit within the pushed bytes
+    pub fn read<T: Sized + Copy>(&self, index: usize) -> Option<T> {
+        if index + size_of::<T>() > self.get_index() {
+            return None;
         }
 
-        let value = temp_buf.as_ptr();
-        let ptr = value as *const T;
-        let cl = (*ptr).clone();
-        cl
+        // Safe because of the bounds check above
+        unsafe {
+            Some(self.read_unchecked(index))
+        }
+    }
+
+    /// Like ``read``, but doesn't check the bounds.
+    ///
+    /// # Safety
+    /// ``index`` and the bytes of the T after it have to be in bounds,
+    /// and the memory may not be a valid T either, so be careful
+    pub unsafe fn read_unchecked<T: Sized + Copy>(&self, index: usize) -> T {
+        let mut value = std::mem::MaybeUninit::<T>::uninit();
+        let ptr = value.as_mut_ptr() as *mut u8;
+        for i in 0..size_of::<T>() {
+            *ptr.add(i) = self.get_byte_unchecked(index + i);
+        }
+        value.assume_init()
     }
 
-    pub fn print(&self) {
-        print!("bytes:");
-        for i in 0..self.get_index() {
-            unsafe {
-                print!(" {:x}", self.get_byte_unchecked(i));
+    /// Overwrites an already pushed value, returns false if it doesn't fit
+    pub fn write<T: Sized>(&mut self, index: usize, value: T) -> bool {
+        if index + size_of::<T>() > self.get_index() {
+            return false;
+        }
+
+        unsafe {
+            let ptr = (&value as *const T) as *const u8;
+            for i in 0..size_of::<T>() {
+                self.set_byte_unchecked(index + i, *ptr.add(i));
             }
         }
-        println!("");
+        true
     }
 
-    pub fn peek_from_top<T: Sized + Clone + std::fmt::Display>(&self, back_offset: usize) -> Option<T> {
-        let index = self.get_index();
-        if back_offset > index || back_offset < size_of::<T>() {
-            println!("Backoffset: {}, index: {}, size: {}", back_offset, index, size_of::<T>());
+    /// Removes a value from the top and returns it
+    pub fn pop<T: Sized + Copy>(&mut self) -> Option<T> {
+        let length = self.get_index();
+        if length < size_of::<T>() {
             return None;
         }
 
-        let index = index - back_offset;
-        let batch_index = index >> BATCH_SIZE_BYTES;
-        let local_index = index - (batch_index << BATCH_SIZE_BYTES);
+        let value = self.read(length - size_of::<T>());
+        self.truncate(length - size_of::<T>());
+        value
+    }
 
-        // Check if we're outside the current batch
-        if local_index + size_of::<T>() >= BATCH_SIZE {
-            unsafe {
-                Some(self.get_value_slow_unchecked::<T>(index))
-            }
-        }else{
-            unsafe {
-                // get_unchecked is safe because we checked the bounds above
-                let batch = self.batches.get_unchecked(batch_index);
-                let value = batch.as_ptr().offset(local_index as isize);
-                // This is safe because we clone it, we don't use another reference.
-                // However, the type could still be improperly formatted in memory,
-                // so naturally it's not quite safe.
-                // But machine code is not considered safe anyway,
-                // so I don't really care xD
-                let other = value as *const T;
-                Some((*other).clone())
-            }
+    /// Shortens the vector, keeps the allocated batches around for later pushes
+    pub fn truncate(&mut self, length: usize) {
+        if length < self.get_index() {
+            self.batch_index = length >> BATCH_SIZE_BYTES;
+            self.local_index = length - (self.batch_index << BATCH_SIZE_BYTES);
         }
     }
 
@@ -126,7 +141,9 @@ impl ByteVec {
         while self.local_index >= BATCH_SIZE {
             self.local_index -= BATCH_SIZE;
             self.batch_index += 1;
-            self.batches.push([0; BATCH_SIZE]);
+            if self.batch_index >= self.batches.len() {
+                self.batches.push([0; BATCH_SIZE]);
+            }
         }
     }
 
@@ -142,7 +159,7 @@ impl ByteVec {
         unsafe {
             let ptr = (&value as *const T) as *const u8;
             for i in 0..t_size {
-                self.push_byte(*ptr.offset(i as isize));
+                self.push_byte(*ptr.add(i));
             }
         }
     }
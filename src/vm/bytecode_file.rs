@@ -0,0 +1,354 @@
+//! The ``.tlbc`` format, for compiling once and running the result later.
+//!
+//! All numbers are little endian. The layout is
+//!
+//! ```text
+//! magic      "TLBC"
+//! version    u16
+//! checksum   u32, FNV-1a of everything after the header
+//! constants  u32 count, then per constant a u8 tag (0 string, 1 float)
+//!            followed by a u32 length and utf8 bytes, or the f64 bits
+//! functions  u32 count, then per function a u32 name length, the name,
+//!            u64 address, u32 n_args, u32 n_locals and a u8 returns flag
+//! natives    u32 count, then per imported native function a u32 name length, the name,
+//!            a u8 argument count, a u8 type per argument and a u8 return type
+//!            (types are 1 int, 2 float, 3 string, and 0 for no return value)
+//! lines      u32 count, then per entry a u64 address, u32 line and u32 character
+//! code       u64 length, then the instructions
+//! ```
+
+use super::{ ByteVec, Program, Function, Constant, LineTable };
+use super::natives::{ NativeImport, NativeSignature };
+use super::super::lexer::Type;
+use super::super::needle::Loc;
+
+pub const MAGIC: [u8; 4] = *b"TLBC";
+pub const VERSION: u16 = 3;
+const HEADER_SIZE: usize = 10;
+
+const CONSTANT_STRING: u8 = 0;
+const CONSTANT_FLOAT: u8 = 1;
+
+/// Only the types of values are stored, a native function can't be saved if it takes or gives back anything else
+fn type_tag(native: &NativeImport, value_type: &Option<Type>) -> Result<u8, SaveError> {
+    match value_type {
+        None => Ok(0),
+        Some(Type::Int) => Ok(1),
+        Some(Type::Float) => Ok(2),
+        Some(Type::Str) => Ok(3),
+        Some(other) => Err(SaveError::UnstorableType { function: native.name.clone(), value_type: other.clone() })
+    }
+}
+
+fn tag_type(tag: u8) -> Result<Option<Type>, LoadError> {
+    match tag {
+        0 => Ok(None),
+        1 => Ok(Some(Type::Int)),
+        2 => Ok(Some(Type::Float)),
+        3 => Ok(Some(Type::Str)),
+        _ => Err(LoadError::Corrupt("a native function has an unknown type"))
+    }
+}
+
+pub enum LoadError {
+    Io(std::io::Error),
+    NotBytecode,
+    WrongVersion(u16),
+    ChecksumMismatch,
+    Truncated(&'static str),
+    Corrupt(&'static str),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use LoadError::*;
+        match self {
+            Io(error) => write!(f, "Could not read the file: {}", error),
+            NotBytecode => write!(f, "Not a bytecode file, it doesn't start with 'TLBC'"),
+            WrongVersion(version) =>
+                write!(f, "The file is bytecode version {}, but only version {} is supported", version, VERSION),
+            ChecksumMismatch => write!(f, "The file is corrupt, the checksum doesn't match"),
+            Truncated(section) => write!(f, "The file is truncated, it ends in the {}", section),
+            Corrupt(reason) => write!(f, "The file is corrupt, {}", reason)
+        }
+    }
+}
+
+pub enum SaveError {
+    Io(std::io::Error),
+    /// A native function the program imports has a type that the format can't store
+    UnstorableType { function: String, value_type: Type }
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "Could not write the file: {}", error),
+            SaveError::UnstorableType { function, value_type } =>
+                write!(f, "The native function '{}' uses the type {}, only ints, floats and strings can be saved", function, value_type)
+        }
+    }
+}
+
+fn checksum<I: Iterator<Item = u8>>(bytes: I) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    for byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn push_bytes(bytes: &mut ByteVec, data: &[u8]) {
+    for byte in data {
+        bytes.push_byte(*byte);
+    }
+}
+
+fn push_string(bytes: &mut ByteVec, string: &str) {
+    push_bytes(bytes, &(string.len() as u32).to_le_bytes());
+    push_bytes(bytes, string.as_bytes());
+}
+
+pub fn write_program(program: &Program) -> Result<ByteVec, SaveError> {
+    let mut body = ByteVec::new();
+
+    push_bytes(&mut body, &(program.constants.len() as u32).to_le_bytes());
+    for constant in program.constants.iter() {
+        match constant {
+            Constant::Str(string) => {
+                body.push_byte(CONSTANT_STRING);
+                push_string(&mut body, &string[..]);
+            },
+            Constant::Float(value) => {
+                body.push_byte(CONSTANT_FLOAT);
+                push_bytes(&mut body, &value.to_bits().to_le_bytes());
+            }
+        }
+    }
+
+    push_bytes(&mut body, &(program.functions.len() as u32).to_le_bytes());
+    for function in program.functions.iter() {
+        push_string(&mut body, &function.name[..]);
+        push_bytes(&mut body, &(function.address as u64).to_le_bytes());
+        push_bytes(&mut body, &(function.n_args as u32).to_le_bytes());
+        push_bytes(&mut body, &(function.n_locals as u32).to_le_bytes());
+        body.push_byte(function.returns as u8);
+    }
+
+    push_bytes(&mut body, &(program.natives.len() as u32).to_le_bytes());
+    for native in program.natives.iter() {
+        push_string(&mut body, &native.name[..]);
+        body.push_byte(native.signature.args.len() as u8);
+        for arg in native.signature.args.iter() {
+            body.push_byte(type_tag(native, &Some(arg.clone()))?);
+        }
+        body.push_byte(type_tag(native, &native.signature.returns)?);
+    }
+
+    let lines = program.line_table.entries();
+    push_bytes(&mut body, &(lines.len() as u32).to_le_bytes());
+    for (address, loc) in lines.iter() {
+        push_bytes(&mut body, &(*address as u64).to_le_bytes());
+        push_bytes(&mut body, &(loc.line as u32).to_le_bytes());
+        push_bytes(&mut body, &(loc.character as u32).to_le_bytes());
+    }
+
+    push_bytes(&mut body, &(program.code.get_index() as u64).to_le_bytes());
+    body.push_byte_vec(&program.code);
+
+    let mut bytes = ByteVec::new();
+    push_bytes(&mut bytes, &MAGIC);
+    push_bytes(&mut bytes, &VERSION.to_le_bytes());
+    push_bytes(&mut bytes, &checksum(body.iter()).to_le_bytes());
+    bytes.push_byte_vec(&body);
+    Ok(bytes)
+}
+
+/// Reads the file front to back, naming the section it's in for the errors
+struct Reader<'a> {
+    bytes: &'a ByteVec,
+    index: usize,
+    section: &'static str
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let mut data = [0u8; N];
+        for byte in data.iter_mut() {
+            *byte = self.bytes.read::<u8>(self.index).ok_or(LoadError::Truncated(self.section))?;
+            self.index += 1;
+        }
+        Ok(data)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    /// Reads a length and checks that there are enough bytes left for it,
+    /// so a corrupt length can't make us allocate a huge amount of memory
+    fn read_length(&mut self, element_size: usize) -> Result<usize, LoadError> {
+        let length = self.read_u32()? as usize;
+        if length.saturating_mul(element_size) > self.bytes.get_index() - self.index {
+            return Err(LoadError::Truncated(self.section));
+        }
+        Ok(length)
+    }
+
+    fn read_string(&mut self) -> Result<String, LoadError> {
+        let length = self.read_length(1)?;
+        let mut data = Vec::with_capacity(length);
+        for _ in 0..length {
+            data.push(self.read_u8()?);
+        }
+        String::from_utf8(data).map_err(|_| LoadError::Corrupt("a string is not valid utf8"))
+    }
+}
+
+pub fn read_program(bytes: &ByteVec) -> Result<Program, LoadError> {
+    let mut reader = Reader { bytes: bytes, index: 0, section: "header" };
+
+    if bytes.get_index() < MAGIC.len() || reader.read_bytes::<4>()? != MAGIC {
+        return Err(LoadError::NotBytecode);
+    }
+    let version = reader.read_u16()?;
+    if version != VERSION {
+        return Err(LoadError::WrongVersion(version));
+    }
+    let expected_checksum = reader.read_u32()?;
+    let result = read_sections(&mut reader);
+
+    // A truncated file is more helpful to know about than a wrong checksum,
+    // but any other error could be caused by the corruption
+    match result {
+        Err(LoadError::Truncated(section)) => Err(LoadError::Truncated(section)),
+        _ if checksum(bytes.iter().skip(HEADER_SIZE)) != expected_checksum => Err(LoadError::ChecksumMismatch),
+        result => result
+    }
+}
+
+fn read_sections(reader: &mut Reader) -> Result<Program, LoadError> {
+    let bytes = reader.bytes;
+
+    reader.section = "constant pool";
+    let n_constants = reader.read_length(5)?;
+    let mut constants = Vec::with_capacity(n_constants);
+    for _ in 0..n_constants {
+        constants.push(match reader.read_u8()? {
+            CONSTANT_STRING => Constant::Str(reader.read_string()?),
+            CONSTANT_FLOAT => Constant::Float(f64::from_bits(reader.read_u64()?)),
+            _ => return Err(LoadError::Corrupt("a constant has an unknown type"))
+        });
+    }
+
+    reader.section = "function table";
+    let n_functions = reader.read_length(21)?;
+    let mut functions = Vec::with_capacity(n_functions);
+    for _ in 0..n_functions {
+        let name = reader.read_string()?;
+        let address = reader.read_u64()? as usize;
+        let n_args = reader.read_u32()? as usize;
+        let n_locals = reader.read_u32()? as usize;
+        let returns = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(LoadError::Corrupt("a function has an invalid return flag"))
+        };
+        functions.push(Function {
+            name: name,
+            address: address,
+            n_args: n_args,
+            n_locals: n_locals,
+            returns: returns
+        });
+    }
+
+    reader.section = "native imports";
+    let n_natives = reader.read_length(6)?;
+    let mut natives = Vec::with_capacity(n_natives);
+    for _ in 0..n_natives {
+        let name = reader.read_string()?;
+        let n_args = reader.read_u8()?;
+        let mut args = Vec::with_capacity(n_args as usize);
+        for _ in 0..n_args {
+            args.push(tag_type(reader.read_u8()?)?
+                .ok_or(LoadError::Corrupt("a native function takes an argument without a type"))?);
+        }
+        let returns = tag_type(reader.read_u8()?)?;
+        natives.push(NativeImport {
+            name: name,
+            signature: NativeSignature { args: args, returns: returns }
+        });
+    }
+
+    reader.section = "line table";
+    let n_lines = reader.read_length(16)?;
+    let mut line_table = LineTable::new();
+    let mut previous_address = None;
+    for _ in 0..n_lines {
+        let address = reader.read_u64()? as usize;
+        let line = reader.read_u32()? as usize;
+        let character = reader.read_u32()? as usize;
+        if previous_address.is_some_and(|previous| previous >= address) {
+            return Err(LoadError::Corrupt("the line table is not sorted"));
+        }
+        previous_address = Some(address);
+        line_table.add(address, Loc::new(line, character));
+    }
+
+    reader.section = "code";
+    let code_length = reader.read_u64()? as usize;
+    if code_length != bytes.get_index() - reader.index {
+        return Err(if code_length > bytes.get_index() - reader.index {
+            LoadError::Truncated(reader.section)
+        }else{
+            LoadError::Corrupt("there are bytes after the code")
+        });
+    }
+    let mut code = ByteVec::new();
+    for _ in 0..code_length {
+        code.push_byte(reader.read_u8()?);
+    }
+
+    if functions.iter().any(|function| function.address >= code_length) {
+        return Err(LoadError::Corrupt("a function starts outside of the code"));
+    }
+    if previous_address.is_some_and(|address| address >= code_length) {
+        return Err(LoadError::Corrupt("the line table points outside of the code"));
+    }
+
+    Ok(Program {
+        code: code,
+        functions: functions,
+        constants: constants,
+        natives: natives,
+        line_table: line_table
+    })
+}
+
+pub fn save(program: &Program, path: &str) -> Result<(), SaveError> {
+    let bytes = write_program(program)?;
+    std::fs::write(path, bytes.iter().collect::<Vec<u8>>()).map_err(SaveError::Io)
+}
+
+pub fn load(path: &str) -> Result<Program, LoadError> {
+    let data = std::fs::read(path).map_err(LoadError::Io)?;
+    let mut bytes = ByteVec::new();
+    for byte in data {
+        bytes.push_byte(byte);
+    }
+    read_program(&bytes)
+}
@@ -0,0 +1,271 @@
+//! Turning Rust values into ``Value``s and back, so hosts can work with ordinary Rust types.
+//!
+//! A ``bool`` is an int that's 0 or 1, and a ``Vec`` is an array of its element type.
+//! A Rust function with typed arguments is registered through ``IntoNative``,
+//! where the tuple of its argument types becomes the signature.
+
+use super::super::lexer::Type;
+use super::natives::{ Value, NativeSignature, NativeResult, NativeFn };
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConversionError {
+    WrongType { expected: Type, found: Type },
+    /// An int that doesn't fit in the Rust integer type
+    OutOfRange { value: i64, target: &'static str },
+    /// The code didn't result in a value
+    NoValue
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::WrongType { expected, found } => write!(f, "Expected a {}, found a {}", expected, found),
+            ConversionError::OutOfRange { value, target } => write!(f, "The int {} doesn't fit in a {}", value, target),
+            ConversionError::NoValue => write!(f, "Expected a value, found nothing")
+        }
+    }
+}
+
+/// A Rust type that's always the same type in the language
+pub trait Typed {
+    fn value_type() -> Type;
+}
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ConversionError>;
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Value, ConversionError> {
+        Ok(value)
+    }
+}
+
+fn expect_int(value: Value) -> Result<i64, ConversionError> {
+    match value {
+        Value::Int(value) => Ok(value),
+        value => Err(ConversionError::WrongType { expected: Type::Int, found: value.get_type() })
+    }
+}
+
+/// Integers that always fit in an int
+macro_rules! small_int {
+    ($($int:ty),*) => {$(
+        impl Typed for $int {
+            fn value_type() -> Type {
+                Type::Int
+            }
+        }
+
+        impl IntoValue for $int {
+            fn into_value(self) -> Value {
+                Value::Int(self as i64)
+            }
+        }
+    )*}
+}
+
+/// Integers that can be read from an int, if it's in range
+macro_rules! int_from_value {
+    ($($int:ty),*) => {$(
+        impl FromValue for $int {
+            fn from_value(value: Value) -> Result<$int, ConversionError> {
+                let value = expect_int(value)?;
+                <$int as std::convert::TryFrom<i64>>::try_from(value)
+                    .map_err(|_| ConversionError::OutOfRange { value: value, target: stringify!($int) })
+            }
+        }
+    )*}
+}
+
+small_int!(i8, i16, i32, i64, isize, u8, u16, u32);
+// u64 and usize can be too big for an int, so they can only be read
+int_from_value!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl Typed for bool {
+    fn value_type() -> Type {
+        Type::Int
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Int(self as i64)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<bool, ConversionError> {
+        match expect_int(value)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(ConversionError::OutOfRange { value: value, target: "bool" })
+        }
+    }
+}
+
+macro_rules! float {
+    ($($float:ty),*) => {$(
+        impl Typed for $float {
+            fn value_type() -> Type {
+                Type::Float
+            }
+        }
+
+        impl IntoValue for $float {
+            fn into_value(self) -> Value {
+                Value::Float(self as f64)
+            }
+        }
+
+        impl FromValue for $float {
+            /// Ints are converted, like they are when an int is stored in a float variable
+            fn from_value(value: Value) -> Result<$float, ConversionError> {
+                match value {
+                    Value::Float(value) => Ok(value as $float),
+                    Value::Int(value) => Ok(value as $float),
+                    value => Err(ConversionError::WrongType { expected: Type::Float, found: value.get_type() })
+                }
+            }
+        }
+    )*}
+}
+
+float!(f32, f64);
+
+impl Typed for String {
+    fn value_type() -> Type {
+        Type::Str
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::Str(String::from(self))
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<String, ConversionError> {
+        match value {
+            Value::Str(string) => Ok(string),
+            value => Err(ConversionError::WrongType { expected: Type::Str, found: value.get_type() })
+        }
+    }
+}
+
+impl<T: Typed> Typed for Vec<T> {
+    fn value_type() -> Type {
+        Type::Array(Box::new(T::value_type()))
+    }
+}
+
+impl<T: Typed + IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Array {
+            element: T::value_type(),
+            values: self.into_iter().map(IntoValue::into_value).collect()
+        }
+    }
+}
+
+impl<T: Typed + FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Vec<T>, ConversionError> {
+        match value {
+            Value::Array { values, .. } => values.into_iter().map(T::from_value).collect(),
+            value => Err(ConversionError::WrongType { expected: Vec::<T>::value_type(), found: value.get_type() })
+        }
+    }
+}
+
+/// What a native function written as a Rust function can give back
+pub trait IntoReturn {
+    fn returns() -> Option<Type>;
+    fn into_result(self) -> NativeResult;
+}
+
+impl IntoReturn for () {
+    fn returns() -> Option<Type> {
+        None
+    }
+
+    fn into_result(self) -> NativeResult {
+        Ok(None)
+    }
+}
+
+impl<T: Typed + IntoValue> IntoReturn for T {
+    fn returns() -> Option<Type> {
+        Some(T::value_type())
+    }
+
+    fn into_result(self) -> NativeResult {
+        Ok(Some(self.into_value()))
+    }
+}
+
+/// The error stops the program, like the error of any native function
+impl<T: IntoReturn> IntoReturn for Result<T, String> {
+    fn returns() -> Option<Type> {
+        T::returns()
+    }
+
+    fn into_result(self) -> NativeResult {
+        self?.into_result()
+    }
+}
+
+/// A Rust function that can be registered as a native function,
+/// ``Args`` is the tuple of its argument types
+pub trait IntoNative<Args> {
+    fn get_signature() -> NativeSignature;
+    fn into_native(self) -> NativeFn;
+}
+
+macro_rules! into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+                where F: Fn($($arg),*) -> R + 'static, R: IntoReturn, $($arg: Typed + FromValue),* {
+            fn get_signature() -> NativeSignature {
+                NativeSignature {
+                    args: vec![$($arg::value_type()),*],
+                    returns: R::returns()
+                }
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self) -> NativeFn {
+                std::rc::Rc::new(move |args| {
+                    let mut args = args.iter().cloned();
+                    $(let $arg = $arg::from_value(args.next().unwrap())
+                        .map_err(|error| format!("{}", error))?;)*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+    }
+}
+
+into_native!();
+into_native!(A);
+into_native!(A, B);
+into_native!(A, B, C);
+into_native!(A, B, C, D);
+into_native!(A, B, C, D, E);
+into_native!(A, B, C, D, E, G);
@@ -0,0 +1,138 @@
+use super::{ ByteVec, Instruction, Program, Constant, LineTable, Function };
+use super::natives::NativeImport;
+use std::fmt::Write;
+
+impl std::fmt::Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Str(string) => write!(f, "string {:?}", string),
+            Constant::Float(value) => write!(f, "float {:?}", value)
+        }
+    }
+}
+
+/// Turns the instructions into text, one instruction per line.
+/// Without the rest of the program, constants and functions are just shown as indices
+pub fn disassemble_code(code: &ByteVec) -> String {
+    disassemble_with(code, &[], &[], &[], &LineTable::new())
+}
+
+/// Like ``disassemble_code``, but also lists the constants and functions,
+/// and annotates the instructions with them and with where in the source they came from
+pub fn disassemble(program: &Program) -> String {
+    let mut text = String::new();
+
+    if !program.constants.is_empty() {
+        writeln!(text, "constants:").unwrap();
+        for (i, constant) in program.constants.iter().enumerate() {
+            writeln!(text, "    {}: {}", i, constant).unwrap();
+        }
+    }
+
+    if !program.functions.is_empty() {
+        writeln!(text, "functions:").unwrap();
+        for (i, function) in program.functions.iter().enumerate() {
+            writeln!(text, "    {}: {} at {:04x}, {} args, {} locals{}",
+                i, function.name, function.address, function.n_args, function.n_locals,
+                if function.returns { ", returns" } else { "" }).unwrap();
+        }
+    }
+
+    if !program.natives.is_empty() {
+        writeln!(text, "natives:").unwrap();
+        for (i, native) in program.natives.iter().enumerate() {
+            writeln!(text, "    {}: {}", i, native).unwrap();
+        }
+    }
+
+    if !text.is_empty() {
+        text.push('\n');
+    }
+
+    text.push_str(&disassemble_with(&program.code, &program.constants[..],
+        &program.functions[..], &program.natives[..], &program.line_table));
+    text
+}
+
+fn disassemble_with(code: &ByteVec, constants: &[Constant], functions: &[Function],
+        natives: &[NativeImport], line_table: &LineTable) -> String {
+    let mut text = String::new();
+    let mut lines = line_table.entries().iter().peekable();
+    let mut address = 0;
+
+    while address < code.get_index() {
+        if let Some(function) = functions.iter().find(|function| function.address == address) {
+            writeln!(text, "{}:", function.name).unwrap();
+        }
+
+        let byte = code.read::<u8>(address).unwrap();
+        let instruction = match Instruction::from_byte(byte) {
+            Some(instruction) => instruction,
+            None => {
+                writeln!(text, "{:04x}  ??? ({:02x})", address, byte).unwrap();
+                address += 1;
+                continue;
+            }
+        };
+
+        let operand = match decode_operand(code, address + 1, instruction, constants, functions, natives) {
+            Some(operand) => operand,
+            None => {
+                writeln!(text, "{:04x}  {:?} <truncated>", address, instruction).unwrap();
+                break;
+            }
+        };
+
+        let mut line = format!("{:04x}  {:<14}{}", address, format!("{:?}", instruction), operand);
+
+        // Only the first instruction of every line table entry gets the location
+        let mut loc = None;
+        while let Some((entry_address, entry_loc)) = lines.peek() {
+            if *entry_address > address {
+                break;
+            }
+            loc = Some(*entry_loc);
+            lines.next();
+        }
+        if let Some(loc) = loc {
+            line = format!("{:<40}; {}", line, loc);
+        }
+
+        writeln!(text, "{}", line.trim_end()).unwrap();
+        address += 1 + instruction.operand_size();
+    }
+
+    text
+}
+
+fn decode_operand(code: &ByteVec, address: usize, instruction: Instruction,
+        constants: &[Constant], functions: &[Function], natives: &[NativeImport]) -> Option<String> {
+    use Instruction::*;
+    Some(match instruction {
+        PushInt => format!("{}", code.read::<i64>(address)?),
+        PushConstant => {
+            let index = code.read::<u32>(address)?;
+            match constants.get(index as usize) {
+                Some(constant) => format!("{} ({})", index, constant),
+                None => format!("{}", index)
+            }
+        },
+        LoadLocal | StoreLocal | NewArray => format!("{}", code.read::<u32>(address)?),
+        Jump | JumpIfZero => format!("{:04x}", code.read::<u64>(address)?),
+        Call => {
+            let index = code.read::<u32>(address)?;
+            match functions.get(index as usize) {
+                Some(function) => format!("{} ({})", index, function.name),
+                None => format!("{}", index)
+            }
+        },
+        CallNative => {
+            let index = code.read::<u32>(address)?;
+            match natives.get(index as usize) {
+                Some(native) => format!("{} ({})", index, native),
+                None => format!("{}", index)
+            }
+        },
+        _ => String::new()
+    })
+}
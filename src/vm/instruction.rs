@@ -0,0 +1,109 @@
+/// Every instruction is a single byte, followed by its operands.
+/// All values on the stack take up 8 bytes, so ints, floats and
+/// bools (0 or 1 as an int) can be moved around the same way.
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Instruction {
+    /// Stops the virtual machine
+    Halt,
+
+    /// Operand: i64
+    PushInt,
+    /// Operand: u32 constant index. Floats are pushed as they are,
+    /// strings as the heap address they were loaded into
+    PushConstant,
+    Pop,
+    Dup,
+
+    /// Operand: u32 slot index, relative to the frame pointer
+    LoadLocal,
+    /// Operand: u32 slot index, relative to the frame pointer
+    StoreLocal,
+
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
+    ModInt,
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+
+    EqualsInt,
+    EqualsFloat,
+    LessInt,
+    LessFloat,
+
+    IntToFloat,
+    /// Fails if the float doesn't fit in an int
+    FloatToInt,
+
+    /// Operand: u32 number of elements. Pops the elements and pushes the heap address of the array
+    NewArray,
+    /// Pops an int index and an array address, pushes the element
+    Index,
+    /// Pops a value, an int index and an array address, and stores the value as the element
+    SetIndex,
+
+    /// Operand: u64 address
+    Jump,
+    /// Operand: u64 address. Pops an int and jumps if it is zero
+    JumpIfZero,
+
+    /// Operand: u32 function index
+    Call,
+    /// Operand: u32 index into the program's native imports.
+    /// Pops the arguments and pushes the result, if the function has one
+    CallNative,
+    Return,
+}
+
+const INSTRUCTIONS: [Instruction; 30] = [
+    Instruction::Halt,
+    Instruction::PushInt,
+    Instruction::PushConstant,
+    Instruction::Pop,
+    Instruction::Dup,
+    Instruction::LoadLocal,
+    Instruction::StoreLocal,
+    Instruction::AddInt,
+    Instruction::SubInt,
+    Instruction::MulInt,
+    Instruction::DivInt,
+    Instruction::ModInt,
+    Instruction::AddFloat,
+    Instruction::SubFloat,
+    Instruction::MulFloat,
+    Instruction::DivFloat,
+    Instruction::EqualsInt,
+    Instruction::EqualsFloat,
+    Instruction::LessInt,
+    Instruction::LessFloat,
+    Instruction::IntToFloat,
+    Instruction::FloatToInt,
+    Instruction::NewArray,
+    Instruction::Index,
+    Instruction::SetIndex,
+    Instruction::Jump,
+    Instruction::JumpIfZero,
+    Instruction::Call,
+    Instruction::CallNative,
+    Instruction::Return,
+];
+
+impl Instruction {
+    pub fn from_byte(byte: u8) -> Option<Instruction> {
+        INSTRUCTIONS.get(byte as usize).copied()
+    }
+
+    /// The number of bytes of operands following the instruction
+    pub fn operand_size(&self) -> usize {
+        use Instruction::*;
+        match self {
+            PushInt | Jump | JumpIfZero => 8,
+            PushConstant | LoadLocal | StoreLocal | NewArray | Call | CallNative => 4,
+            _ => 0
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::super::needle::Loc;
+
+/// Maps instruction addresses back to the location in the source code
+/// they were compiled from. Entries are sorted by address, and an entry
+/// is valid until the next one starts.
+#[derive(Clone)]
+pub struct LineTable {
+    entries: Vec<(usize, Loc)>
+}
+
+impl LineTable {
+    pub fn new() -> LineTable {
+        LineTable {
+            entries: Vec::new()
+        }
+    }
+
+    /// Addresses have to be added in increasing order
+    pub fn add(&mut self, address: usize, loc: Loc) {
+        if let Some(last) = self.entries.last_mut() {
+            if last.0 == address {
+                last.1 = loc;
+                return;
+            }
+
+            let previous = last.1;
+            if previous.line == loc.line && previous.character == loc.character {
+                return;
+            }
+        }
+
+        self.entries.push((address, loc));
+    }
+
+    pub fn get(&self, address: usize) -> Option<Loc> {
+        match self.entries.binary_search_by_key(&address, |entry| entry.0) {
+            Ok(index) => Some(self.entries[index].1),
+            Err(0) => None,
+            Err(index) => Some(self.entries[index - 1].1)
+        }
+    }
+
+    pub fn entries(&self) -> &[(usize, Loc)] {
+        &self.entries[..]
+    }
+}
@@ -1,26 +1,774 @@
 mod byte_vec;
-use byte_vec::ByteVec;
+mod instruction;
+mod line_table;
+mod program;
+pub mod bytecode_file;
+pub mod disassembler;
+pub mod verifier;
+pub mod profiler;
+pub mod natives;
+pub mod conversion;
+pub use byte_vec::ByteVec;
+pub use instruction::Instruction;
+pub use line_table::LineTable;
+pub use program::{ Program, Function, Constant };
+use super::needle::Loc;
+use super::lexer::Type;
+use natives::{ NativeImport, NativeRegistry, Value };
+use std::time::Instant;
+use std::rc::Rc;
+
+/// The amount of nested calls allowed when ``Limits`` doesn't set one
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+/// How many instructions run between checks of the deadline, reading the clock is slow
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// All values on the stack are this many bytes
+pub const SLOT_SIZE: usize = 8;
+
+/// The size of a call instruction including the operand
+const CALL_SIZE: usize = 5;
+
+pub struct CallFrame {
+    pub function: usize,
+    pub return_address: usize,
+    /// Index into the stack where the first argument is
+    pub frame_pointer: usize
+}
+
+/// Bounds on what a program may use, for running code that isn't trusted.
+/// Everything is unlimited by default, except for the call depth which is ``DEFAULT_MAX_CALL_DEPTH``
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    /// In bytes
+    pub max_stack_size: Option<usize>,
+    /// In bytes, including the code and the constants
+    pub max_heap_size: Option<usize>,
+    /// ``None`` means ``DEFAULT_MAX_CALL_DEPTH``, the frames have to stop somewhere
+    pub max_call_depth: Option<usize>,
+    pub deadline: Option<Instant>
+}
+
+/// The limit that stopped a program, with the value it was set to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Instructions(u64),
+    StackSize(usize),
+    HeapSize(usize),
+    CallDepth(usize),
+    Deadline
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Instructions(max) => write!(f, "more than {} instructions were executed", max),
+            Limit::StackSize(max) => write!(f, "the stack grew past {} bytes", max),
+            Limit::HeapSize(max) => write!(f, "the heap grew past {} bytes", max),
+            Limit::CallDepth(max) => write!(f, "calls were nested more than {} deep", max),
+            Limit::Deadline => write!(f, "the deadline passed")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    /// Calls were nested ``DEFAULT_MAX_CALL_DEPTH`` deep without ``Limits`` setting the depth,
+    /// a limit that was set gives ``LimitExceeded`` instead
+    StackOverflow,
+    StackUnderflow,
+    InvalidInstruction(u8),
+    InvalidAddress,
+    InvalidFunction(u32),
+    InvalidLocal(u32),
+    InvalidConstant(u32),
+    DivisionByZero,
+    IntegerOverflow,
+    IndexOutOfBounds { index: i64, length: u64 },
+    InvalidCast(f64),
+    LimitExceeded(Limit),
+    InvalidNative(u32),
+    /// The program calls a native function that the machine wasn't linked with
+    UnlinkedNative(String),
+    NativeError { function: String, message: String },
+    /// The program called ``exit``, this stops it without anything having gone wrong
+    Exit(i64)
+}
+
+impl std::fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RuntimeErrorKind::*;
+        match self {
+            StackOverflow => write!(f, "Stack overflow, calls were nested more than {} deep", DEFAULT_MAX_CALL_DEPTH),
+            StackUnderflow => write!(f, "Stack underflow"),
+            InvalidInstruction(byte) => write!(f, "Invalid instruction '{:x}'", byte),
+            InvalidAddress => write!(f, "Invalid address"),
+            InvalidFunction(index) => write!(f, "Invalid function index '{}'", index),
+            InvalidLocal(index) => write!(f, "Invalid local slot '{}'", index),
+            InvalidConstant(index) => write!(f, "Invalid constant index '{}'", index),
+            DivisionByZero => write!(f, "Division by zero"),
+            IntegerOverflow => write!(f, "Integer overflow"),
+            IndexOutOfBounds { index, length } =>
+                write!(f, "Index {} is out of bounds for an array of length {}", index, length),
+            InvalidCast(value) => write!(f, "Cannot cast '{}' to an int", value),
+            LimitExceeded(limit) => write!(f, "Limit exceeded, {}", limit),
+            InvalidNative(index) => write!(f, "Invalid native function index '{}'", index),
+            UnlinkedNative(function) => write!(f, "The native function '{}' is not available", function),
+            NativeError { function, message } => write!(f, "Error in '{}': {}", function, message),
+            Exit(status) => write!(f, "The program exited with status {}", status)
+        }
+    }
+}
+
+/// One function in the chain of calls that led to an error,
+/// the innermost call comes first.
+pub struct StackTraceEntry {
+    pub function: String,
+    /// Where in the function the error happened, or where the next function was called
+    pub loc: Option<Loc>
+}
+
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// The address of the instruction that caused the error
+    pub address: usize,
+    pub loc: Option<Loc>,
+    pub trace: Vec<StackTraceEntry>
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.loc {
+            Some(loc) => write!(f, "({}): {}", loc, self.kind)?,
+            None => write!(f, "({:x}): {}", self.address, self.kind)?
+        }
+
+        for entry in self.trace.iter() {
+            match entry.loc {
+                Some(loc) => write!(f, "\n    in '{}' at ({})", entry.function, loc)?,
+                None => write!(f, "\n    in '{}'", entry.function)?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RuntimeError {
+    /// Which limit stopped the program, if it was stopped by one rather than a bug in it
+    pub fn get_limit(&self) -> Option<Limit> {
+        match self.kind {
+            RuntimeErrorKind::LimitExceeded(limit) => Some(limit),
+            _ => None
+        }
+    }
+
+    /// The status the program exited with, if it was stopped by calling ``exit``
+    pub fn get_exit_status(&self) -> Option<i64> {
+        match self.kind {
+            RuntimeErrorKind::Exit(status) => Some(status),
+            _ => None
+        }
+    }
+}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
 
 pub struct VirtualMachine {
     stack: ByteVec,
-    heap: ByteVec
+    heap: ByteVec,
+    functions: Vec<Function>,
+    line_table: LineTable,
+    /// The values the constants get pushed as, strings are stored in the heap
+    constants: Vec<u64>,
+    natives: Vec<NativeImport>,
+    registry: Rc<NativeRegistry>,
+    /// The index in the registry of every native import, once it's linked
+    links: Vec<Option<usize>>,
+    frames: Vec<CallFrame>,
+    instruction_pointer: usize,
+    profiler: Option<profiler::Profiler>,
+    n_instructions: u64,
+    pub limits: Limits
 }
 
 impl VirtualMachine {
-    pub fn new(instructions: &ByteVec) -> VirtualMachine {
+    pub fn new(program: Program) -> VirtualMachine {
         let mut heap = ByteVec::new();
-        heap.push_byte_vec(instructions);
-        
+        heap.push_byte_vec(&program.code);
+
+        let constants = load_constants(&mut heap, &program.constants[..]);
+
         VirtualMachine {
             stack: ByteVec::new(),
-            heap: heap
+            heap: heap,
+            functions: program.functions,
+            line_table: program.line_table,
+            constants: constants,
+            links: vec![None; program.natives.len()],
+            natives: program.natives,
+            registry: Rc::new(NativeRegistry::new()),
+            frames: Vec::new(),
+            instruction_pointer: 0,
+            profiler: None,
+            n_instructions: 0,
+            limits: Limits::default()
+        }
+    }
+
+    /// Checks the program before making a machine for it,
+    /// use this for bytecode that didn't come straight from the compiler
+    pub fn verified(program: Program) -> Result<VirtualMachine, verifier::VerifyError> {
+        verifier::verify(&program)?;
+        Ok(VirtualMachine::new(program))
+    }
+
+    /// Gives the machine the native functions the program calls,
+    /// fails with the first one that the registry doesn't have
+    pub fn link_natives(&mut self, registry: Rc<NativeRegistry>) -> Result<(), NativeImport> {
+        self.links = self.natives.iter().map(|import| registry.find(import)).collect();
+        self.registry = registry;
+
+        match self.links.iter().position(|link| link.is_none()) {
+            Some(index) => Err(self.natives[index].clone()),
+            None => Ok(())
+        }
+    }
+
+    pub fn get_stack(&self) -> &ByteVec {
+        &self.stack
+    }
+
+    pub fn get_frames(&self) -> &[CallFrame] {
+        &self.frames[..]
+    }
+
+    /// How many instructions have run so far
+    pub fn get_instruction_count(&self) -> u64 {
+        self.n_instructions
+    }
+
+    pub fn get_instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn get_line_table(&self) -> &LineTable {
+        &self.line_table
+    }
+
+    pub fn get_loc(&self, address: usize) -> Option<Loc> {
+        self.line_table.get(address)
+    }
+
+    /// Reads a local slot of the innermost call frame
+    pub fn get_local(&self, slot: u32) -> Option<u64> {
+        let index = self.get_frame_pointer() + slot as usize * SLOT_SIZE;
+        self.stack.read::<u64>(index)
+    }
+
+    /// Runs another program in the current call frame, without disturbing the running one.
+    /// The program is loaded at the end of the heap, so it has to be compiled for that address,
+    /// see ``get_heap_end``. Gives back the value it left on the stack, if any
+    pub fn evaluate(&mut self, program: &Program) -> RuntimeResult<Option<u64>> {
+        let instruction_pointer = self.instruction_pointer;
+        let n_frames = self.frames.len();
+        let stack_length = self.stack.get_index();
+
+        self.instruction_pointer = self.heap.get_index();
+        self.heap.push_byte_vec(&program.code);
+        let mut constants = load_constants(&mut self.heap, &program.constants[..]);
+        std::mem::swap(&mut self.constants, &mut constants);
+        let mut natives = program.natives.clone();
+        let mut links = natives.iter().map(|import| self.registry.find(import)).collect();
+        std::mem::swap(&mut self.natives, &mut natives);
+        std::mem::swap(&mut self.links, &mut links);
+
+        let mut result = self.run();
+        if result.is_ok() && self.frames.len() != n_frames {
+            result = Err(self.error(RuntimeErrorKind::StackUnderflow));
+        }
+
+        let value = if self.stack.get_index() > stack_length { self.stack.pop::<u64>() } else { None };
+        std::mem::swap(&mut self.constants, &mut constants);
+        std::mem::swap(&mut self.natives, &mut natives);
+        std::mem::swap(&mut self.links, &mut links);
+        self.instruction_pointer = instruction_pointer;
+        self.frames.truncate(n_frames);
+        self.stack.truncate(stack_length);
+
+        result.map(|_| value)
+    }
+
+    /// Starts counting the instructions that run, see ``take_profile``
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(profiler::Profiler::new(&self.functions[..]));
+    }
+
+    /// Stops profiling and gives back what was counted since ``enable_profiling``
+    pub fn take_profile(&mut self) -> Option<profiler::Profiler> {
+        let mut profiler = self.profiler.take()?;
+        profiler.finish();
+        Some(profiler)
+    }
+
+    /// The address a program given to ``evaluate`` gets loaded at
+    pub fn get_heap_end(&self) -> usize {
+        self.heap.get_index()
+    }
+
+    /// The top level code doesn't have a frame, so it gets 0 as the frame pointer
+    fn get_frame_pointer(&self) -> usize {
+        self.frames.last().map(|frame| frame.frame_pointer).unwrap_or(0)
+    }
+
+    /// Walks the call frames from the innermost one and finds where each of them currently is
+    pub fn stack_trace(&self) -> Vec<StackTraceEntry> {
+        let mut trace = Vec::with_capacity(self.frames.len());
+        let mut address = self.instruction_pointer;
+        for frame in self.frames.iter().rev() {
+            trace.push(StackTraceEntry {
+                function: self.functions[frame.function].name.clone(),
+                loc: self.line_table.get(address)
+            });
+            address = frame.return_address - CALL_SIZE;
+        }
+        trace
+    }
+
+    fn error(&self, kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError {
+            kind: kind,
+            address: self.instruction_pointer,
+            loc: self.line_table.get(self.instruction_pointer),
+            trace: self.stack_trace()
+        }
+    }
+
+    fn read_operand<T: Copy>(&self, offset: usize) -> RuntimeResult<T> {
+        self.heap.read::<T>(self.instruction_pointer + 1 + offset)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidAddress))
+    }
+
+    fn read_heap<T: Copy>(&self, address: usize) -> RuntimeResult<T> {
+        self.heap.read::<T>(address)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidAddress))
+    }
+
+    fn pop<T: Copy>(&mut self) -> RuntimeResult<T> {
+        if self.stack.get_index() < self.get_frame_pointer() + SLOT_SIZE {
+            return Err(self.error(RuntimeErrorKind::StackUnderflow));
         }
+        Ok(self.stack.pop::<T>().unwrap())
+    }
+
+    fn local_index(&self, slot: u32) -> RuntimeResult<usize> {
+        let index = self.get_frame_pointer() + slot as usize * SLOT_SIZE;
+        if index + SLOT_SIZE > self.stack.get_index() {
+            return Err(self.error(RuntimeErrorKind::InvalidLocal(slot)));
+        }
+        Ok(index)
+    }
+
+    fn call(&mut self, function_index: u32, return_address: usize) -> RuntimeResult<usize> {
+        let max = self.limits.max_call_depth.unwrap_or(DEFAULT_MAX_CALL_DEPTH);
+        if self.frames.len() >= max {
+            let kind = match self.limits.max_call_depth {
+                Some(max) => RuntimeErrorKind::LimitExceeded(Limit::CallDepth(max)),
+                None => RuntimeErrorKind::StackOverflow
+            };
+            return Err(self.error(kind));
+        }
+
+        let function = self.functions.get(function_index as usize)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidFunction(function_index)))?;
+
+        // The arguments are already on the stack, so they become the first locals
+        let args_size = function.n_args * SLOT_SIZE;
+        if self.stack.get_index() < self.get_frame_pointer() + args_size {
+            return Err(self.error(RuntimeErrorKind::StackUnderflow));
+        }
+        let frame_pointer = self.stack.get_index() - args_size;
+        let address = function.address;
+
+        for _ in 0..function.n_locals {
+            self.stack.push(0u64);
+        }
+
+        self.frames.push(CallFrame {
+            function: function_index as usize,
+            return_address: return_address,
+            frame_pointer: frame_pointer
+        });
+
+        Ok(address)
+    }
+
+    fn return_from_call(&mut self) -> RuntimeResult<usize> {
+        let returns = match self.frames.last() {
+            Some(frame) => self.functions[frame.function].returns,
+            None => return Err(self.error(RuntimeErrorKind::StackUnderflow))
+        };
+
+        let value = if returns { Some(self.pop::<u64>()?) } else { None };
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.frame_pointer);
+        if let Some(value) = value {
+            self.stack.push(value);
+        }
+
+        Ok(frame.return_address)
+    }
+
+    fn call_native(&mut self, index: u32) -> RuntimeResult<()> {
+        let link = *self.links.get(index as usize)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidNative(index)))?;
+        let native_index = link
+            .ok_or_else(|| self.error(RuntimeErrorKind::UnlinkedNative(format!("{}", self.natives[index as usize]))))?;
+        let signature = self.registry.get(native_index).signature.clone();
+
+        let mut slots = vec![0u64; signature.args.len()];
+        for slot in slots.iter_mut().rev() {
+            *slot = self.pop::<u64>()?;
+        }
+        let mut args = Vec::with_capacity(slots.len());
+        for (slot, arg_type) in slots.into_iter().zip(signature.args.iter()) {
+            args.push(self.get_value(slot, arg_type.clone())
+                .ok_or_else(|| self.error(RuntimeErrorKind::InvalidAddress))?);
+        }
+
+        let native = self.registry.get(native_index);
+        if native.is_exit() {
+            let status = match args[0] {
+                Value::Int(status) => status,
+                _ => return Err(self.error(RuntimeErrorKind::InvalidAddress))
+            };
+            return Err(self.error(RuntimeErrorKind::Exit(status)));
+        }
+        let native_error = |message: String| self.error(RuntimeErrorKind::NativeError {
+            function: native.name.clone(),
+            message: message
+        });
+        let result = native.call(&args[..]).map_err(native_error)?;
+        match (result, signature.returns) {
+            (Some(value), Some(returns)) if value.get_type() == returns => {
+                let slot = self.store_value(value);
+                self.stack.push(slot);
+            },
+            (None, None) => {},
+            (_, returns) => {
+                let expected = returns.map(|returns| format!("a {}", returns)).unwrap_or(String::from("nothing"));
+                return Err(native_error(format!("It should give back {}", expected)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a slot as a value of the type
+    pub fn get_value(&self, slot: u64, value_type: Type) -> Option<Value> {
+        match value_type {
+            Type::Int => Some(Value::Int(slot as i64)),
+            Type::Float => Some(Value::Float(f64::from_bits(slot))),
+            Type::Str => self.get_string(slot).map(Value::Str),
+            Type::Array(element) => {
+                let address = slot as usize;
+                let length = self.heap.read::<u64>(address)? as usize;
+                if length > (self.heap.get_index() - address - SLOT_SIZE) / SLOT_SIZE {
+                    return None;
+                }
+
+                let mut values = Vec::with_capacity(length);
+                for i in 0..length {
+                    let slot = self.heap.read::<u64>(address + (i + 1) * SLOT_SIZE)?;
+                    values.push(self.get_value(slot, (*element).clone())?);
+                }
+                Some(Value::Array { element: *element, values: values })
+            },
+            Type::Nil | Type::Function { .. } | Type::Struct { .. } => None
+        }
+    }
+
+    /// Turns a value into a slot, strings are stored in the heap
+    fn store_value(&mut self, value: Value) -> u64 {
+        match value {
+            Value::Int(value) => value as u64,
+            Value::Float(value) => value.to_bits(),
+            Value::Str(string) => store_string(&mut self.heap, &string[..]),
+            Value::Array { values, .. } => {
+                // Strings and arrays in the elements go in the heap before the array that points to them
+                let slots: Vec<u64> = values.into_iter().map(|value| self.store_value(value)).collect();
+                let address = self.heap.get_index() as u64;
+                self.heap.push(slots.len() as u64);
+                for slot in slots {
+                    self.heap.push(slot);
+                }
+                address
+            }
+        }
+    }
+
+    fn new_array(&mut self, length: u32) -> RuntimeResult<()> {
+        let size = length as usize * SLOT_SIZE;
+        if self.stack.get_index() < self.get_frame_pointer() + size {
+            return Err(self.error(RuntimeErrorKind::StackUnderflow));
+        }
+
+        let start = self.stack.get_index() - size;
+        let address = self.heap.get_index();
+        self.heap.push(length as u64);
+        for i in 0..length as usize {
+            let value = self.stack.read::<u64>(start + i * SLOT_SIZE).unwrap();
+            self.heap.push(value);
+        }
+
+        self.stack.truncate(start);
+        self.stack.push(address as u64);
+        Ok(())
+    }
+
+    fn index(&mut self) -> RuntimeResult<()> {
+        let index = self.pop::<i64>()?;
+        let address = self.pop::<u64>()? as usize;
+        let length = self.read_heap::<u64>(address)?;
+        if index < 0 || index as u64 >= length {
+            return Err(self.error(RuntimeErrorKind::IndexOutOfBounds { index: index, length: length }));
+        }
+
+        let value = self.read_heap::<u64>(address + SLOT_SIZE * (1 + index as usize))?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn set_index(&mut self) -> RuntimeResult<()> {
+        let value = self.pop::<u64>()?;
+        let index = self.pop::<i64>()?;
+        let address = self.pop::<u64>()? as usize;
+        let length = self.read_heap::<u64>(address)?;
+        if index < 0 || index as u64 >= length {
+            return Err(self.error(RuntimeErrorKind::IndexOutOfBounds { index: index, length: length }));
+        }
+
+        if !self.heap.write(address + SLOT_SIZE * (1 + index as usize), value) {
+            return Err(self.error(RuntimeErrorKind::InvalidAddress));
+        }
+        Ok(())
+    }
+
+    /// Checked before every instruction
+    fn check_time_limits(&self) -> RuntimeResult<()> {
+        if let Some(max) = self.limits.max_instructions {
+            if self.n_instructions >= max {
+                return Err(self.error(RuntimeErrorKind::LimitExceeded(Limit::Instructions(max))));
+            }
+        }
+
+        if let Some(deadline) = self.limits.deadline {
+            if self.n_instructions.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(self.error(RuntimeErrorKind::LimitExceeded(Limit::Deadline)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checked after every instruction, while the instruction pointer still points at it
+    fn check_memory_limits(&self) -> RuntimeResult<()> {
+        if let Some(max) = self.limits.max_stack_size {
+            if self.stack.get_index() > max {
+                return Err(self.error(RuntimeErrorKind::LimitExceeded(Limit::StackSize(max))));
+            }
+        }
+
+        if let Some(max) = self.limits.max_heap_size {
+            if self.heap.get_index() > max {
+                return Err(self.error(RuntimeErrorKind::LimitExceeded(Limit::HeapSize(max))));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs a single instruction, returns false if the machine halted
+    pub fn step(&mut self) -> RuntimeResult<bool> {
+        self.check_time_limits()?;
+        self.n_instructions += 1;
+
+        let byte = self.heap.read::<u8>(self.instruction_pointer)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidAddress))?;
+        let instruction = Instruction::from_byte(byte)
+            .ok_or_else(|| self.error(RuntimeErrorKind::InvalidInstruction(byte)))?;
+        let mut next = self.instruction_pointer + 1 + instruction.operand_size();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(instruction, self.line_table.get(self.instruction_pointer));
+        }
+
+        use Instruction::*;
+        match instruction {
+            Halt => return Ok(false),
+            PushInt => {
+                let value = self.read_operand::<i64>(0)?;
+                self.stack.push(value);
+            },
+            PushConstant => {
+                let index = self.read_operand::<u32>(0)?;
+                let value = *self.constants.get(index as usize)
+                    .ok_or_else(|| self.error(RuntimeErrorKind::InvalidConstant(index)))?;
+                self.stack.push(value);
+            },
+            Pop => { self.pop::<u64>()?; },
+            Dup => {
+                let value = self.pop::<u64>()?;
+                self.stack.push(value);
+                self.stack.push(value);
+            },
+            LoadLocal => {
+                let index = self.local_index(self.read_operand::<u32>(0)?)?;
+                let value = self.stack.read::<u64>(index).unwrap();
+                self.stack.push(value);
+            },
+            StoreLocal => {
+                let index = self.local_index(self.read_operand::<u32>(0)?)?;
+                let value = self.pop::<u64>()?;
+                self.stack.write(index, value);
+            },
+            AddInt => self.checked_int_op(i64::checked_add)?,
+            SubInt => self.checked_int_op(i64::checked_sub)?,
+            MulInt => self.checked_int_op(i64::checked_mul)?,
+            DivInt | ModInt => {
+                let b = self.pop::<i64>()?;
+                let a = self.pop::<i64>()?;
+                if b == 0 {
+                    return Err(self.error(RuntimeErrorKind::DivisionByZero));
+                }
+
+                let value = if instruction == DivInt { a.checked_div(b) } else { a.checked_rem(b) };
+                let value = value.ok_or_else(|| self.error(RuntimeErrorKind::IntegerOverflow))?;
+                self.stack.push(value);
+            },
+            AddFloat => self.binary_op(|a: f64, b: f64| a + b)?,
+            SubFloat => self.binary_op(|a: f64, b: f64| a - b)?,
+            MulFloat => self.binary_op(|a: f64, b: f64| a * b)?,
+            DivFloat => self.binary_op(|a: f64, b: f64| a / b)?,
+            EqualsInt => self.binary_op(|a: i64, b: i64| (a == b) as i64)?,
+            EqualsFloat => self.binary_op(|a: f64, b: f64| (a == b) as i64)?,
+            LessInt => self.binary_op(|a: i64, b: i64| (a < b) as i64)?,
+            LessFloat => self.binary_op(|a: f64, b: f64| (a < b) as i64)?,
+            IntToFloat => {
+                let value = self.pop::<i64>()?;
+                self.stack.push(value as f64);
+            },
+            FloatToInt => {
+                let value = self.pop::<f64>()?;
+                // i64::MAX as f64 rounds up, so it has to be a strict comparison
+                if !(value >= i64::MIN as f64 && value < i64::MAX as f64) {
+                    return Err(self.error(RuntimeErrorKind::InvalidCast(value)));
+                }
+                self.stack.push(value as i64);
+            },
+            NewArray => {
+                let length = self.read_operand::<u32>(0)?;
+                self.new_array(length)?;
+            },
+            Index => self.index()?,
+            SetIndex => self.set_index()?,
+            Jump => next = self.read_operand::<u64>(0)? as usize,
+            JumpIfZero => {
+                let address = self.read_operand::<u64>(0)? as usize;
+                if self.pop::<i64>()? == 0 {
+                    next = address;
+                }
+            },
+            Call => {
+                let function_index = self.read_operand::<u32>(0)?;
+                next = self.call(function_index, next)?;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter(function_index as usize);
+                }
+            },
+            CallNative => {
+                let index = self.read_operand::<u32>(0)?;
+                self.call_native(index)?;
+            },
+            Return => {
+                next = self.return_from_call()?;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+            }
+        }
+
+        self.check_memory_limits()?;
+        self.instruction_pointer = next;
+        Ok(true)
+    }
+
+    fn binary_op<T: Copy, R, F>(&mut self, op: F) -> RuntimeResult<()>
+            where F: Fn(T, T) -> R {
+        let b = self.pop::<T>()?;
+        let a = self.pop::<T>()?;
+        self.stack.push(op(a, b));
+        Ok(())
+    }
+
+    fn checked_int_op<F>(&mut self, op: F) -> RuntimeResult<()>
+            where F: Fn(i64, i64) -> Option<i64> {
+        let b = self.pop::<i64>()?;
+        let a = self.pop::<i64>()?;
+        let value = op(a, b).ok_or_else(|| self.error(RuntimeErrorKind::IntegerOverflow))?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Runs until the machine halts or an error occurs
+    pub fn run(&mut self) -> RuntimeResult<()> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Pops the value on the top of the stack, this is how you get a result
+    pub fn pop_result<T: Copy>(&mut self) -> Option<T> {
+        self.stack.pop::<T>()
+    }
+
+    /// Reads a string from the heap address that a string value holds
+    pub fn get_string(&self, address: u64) -> Option<String> {
+        let address = address as usize;
+        let length = self.heap.read::<u64>(address)? as usize;
+        if length > self.heap.get_index() - address - SLOT_SIZE {
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(length);
+        for i in 0..length {
+            bytes.push(self.heap.read::<u8>(address + SLOT_SIZE + i)?);
+        }
+        String::from_utf8(bytes).ok()
+    }
+}
+
+/// Gives back the values the constants get pushed as, storing the strings in the heap
+fn load_constants(heap: &mut ByteVec, program_constants: &[Constant]) -> Vec<u64> {
+    let mut constants = Vec::with_capacity(program_constants.len());
+    for constant in program_constants.iter() {
+        match constant {
+            Constant::Float(value) => constants.push(value.to_bits()),
+            Constant::Str(string) => constants.push(store_string(heap, &string[..]))
+        }
+    }
+    constants
+}
+
+/// Stores the length and then the bytes, gives back the address
+fn store_string(heap: &mut ByteVec, string: &str) -> u64 {
+    let address = heap.get_index() as u64;
+    heap.push(string.len() as u64);
+    for byte in string.bytes() {
+        heap.push_byte(byte);
     }
+    address
 }
 
-pub fn test_vm() {
-    let mut bytes = ByteVec::new();
-    bytes.push::<Option<i64>>(None);
-    bytes.print();
-    println!("Number: {}", bytes.peek_from_top::<i64>(8).unwrap());
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,201 @@
+//! Functions written in Rust that TrolledLang code can call.
+//!
+//! The host registers them in a ``NativeRegistry`` under a name and a signature.
+//! The parser learns the signatures through ``NativeRegistry::declare``, so calls are type checked,
+//! and a program only refers to the natives it calls by name and signature, see ``NativeImport``.
+//! They're looked up when the program is linked to a registry with ``VirtualMachine::link_natives``.
+
+use super::super::lexer::{ Type, ScopePool };
+use super::conversion::IntoNative;
+use std::rc::Rc;
+
+/// A value passed between TrolledLang code and a native function
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// The elements all have the ``element`` type, so an empty array still has a type
+    Array { element: Type, values: Vec<Value> }
+}
+
+impl Value {
+    pub fn get_type(&self) -> Type {
+        match self {
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::Str(_) => Type::Str,
+            Value::Array { element, .. } => Type::Array(Box::new(element.clone()))
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Str(string) => write!(f, "{}", string),
+            Value::Array { values, .. } => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    match value {
+                        Value::Str(string) => write!(f, "{:?}", string)?,
+                        value => write!(f, "{}", value)?
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct NativeSignature {
+    pub args: Vec<Type>,
+    pub returns: Option<Type>
+}
+
+impl std::fmt::Display for NativeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 { write!(f, ", ")?; }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, "]")?;
+        if let Some(returns) = &self.returns {
+            write!(f, " -> {}", returns)?;
+        }
+        Ok(())
+    }
+}
+
+/// A native function that a program calls, the operand of ``CallNative`` indexes these
+#[derive(Clone, PartialEq, Debug)]
+pub struct NativeImport {
+    pub name: String,
+    pub signature: NativeSignature
+}
+
+impl std::fmt::Display for NativeImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.name, self.signature)
+    }
+}
+
+/// What a native function gives back, the error stops the program
+pub type NativeResult = Result<Option<Value>, String>;
+
+/// Shared, so a registry can be copied to add functions while machines are using the old one
+pub type NativeFn = Rc<dyn Fn(&[Value]) -> NativeResult>;
+
+#[derive(Clone)]
+enum NativeBody {
+    Function(NativeFn),
+    /// Stops the program with its int argument as the exit status, the machine does this itself
+    Exit
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub signature: NativeSignature,
+    body: NativeBody
+}
+
+impl NativeFunction {
+    /// The arguments are guaranteed to match the signature
+    pub fn call(&self, args: &[Value]) -> NativeResult {
+        match &self.body {
+            NativeBody::Function(function) => function(args),
+            NativeBody::Exit => Err(String::from("Only a machine running a program can exit it"))
+        }
+    }
+
+    /// Whether calling the function stops the program, see ``NativeRegistry::register_exit``
+    pub fn is_exit(&self) -> bool {
+        match self.body {
+            NativeBody::Exit => true,
+            NativeBody::Function(_) => false
+        }
+    }
+}
+
+/// The native functions a host makes available. A name can be registered more than once
+/// with different argument types, calls pick the one that fits their arguments
+#[derive(Clone)]
+pub struct NativeRegistry {
+    functions: Vec<NativeFunction>
+}
+
+impl NativeRegistry {
+    pub fn new() -> NativeRegistry {
+        NativeRegistry {
+            functions: Vec::new()
+        }
+    }
+
+    /// A registry with the functions every program can use, ``print`` writes to stdout
+    /// and ``exit`` stops the program
+    pub fn standard() -> NativeRegistry {
+        NativeRegistry::with_output(|text| println!("{}", text))
+    }
+
+    /// Like ``standard``, but ``print`` gives the text to ``output`` instead
+    pub fn with_output<F: Fn(&str) + Clone + 'static>(output: F) -> NativeRegistry {
+        let mut registry = NativeRegistry::new();
+        for arg in [Type::Str, Type::Int, Type::Float].iter() {
+            let output = output.clone();
+            registry.register("print", vec![arg.clone()], None, move |args| {
+                output(&format!("{}", args[0]));
+                Ok(None)
+            });
+        }
+        registry.register_exit("exit");
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, args: Vec<Type>, returns: Option<Type>, function: F)
+            where F: Fn(&[Value]) -> NativeResult + 'static {
+        self.insert(name, NativeSignature { args: args, returns: returns }, NativeBody::Function(Rc::new(function)));
+    }
+
+    /// Like ``register``, but the signature comes from the types of the Rust function,
+    /// arguments it can't take and errors it gives back stop the program
+    pub fn register_fn<Args, F: IntoNative<Args>>(&mut self, name: &str, function: F) {
+        self.insert(name, F::get_signature(), NativeBody::Function(function.into_native()));
+    }
+
+    /// Makes ``name[int]`` stop the program, with the int as the exit status.
+    /// The machine gives back a ``RuntimeErrorKind::Exit`` error, see ``RuntimeError::get_exit_status``
+    pub fn register_exit(&mut self, name: &str) {
+        self.insert(name, NativeSignature { args: vec![Type::Int], returns: None }, NativeBody::Exit);
+    }
+
+    fn insert(&mut self, name: &str, signature: NativeSignature, body: NativeBody) {
+        // Registering the same signature again replaces the old function
+        self.functions.retain(|native| !(native.name == name && native.signature == signature));
+        self.functions.push(NativeFunction {
+            name: String::from(name),
+            signature: signature,
+            body: body
+        });
+    }
+
+    /// Makes the parser aware of the functions, do this before parsing code that calls them
+    pub fn declare(&self, scopes: &mut ScopePool) {
+        for native in self.functions.iter() {
+            scopes.declare_native(&native.name[..], native.signature.clone());
+        }
+    }
+
+    pub fn find(&self, import: &NativeImport) -> Option<usize> {
+        self.functions.iter().position(|native| native.name == import.name && native.signature == import.signature)
+    }
+
+    pub fn get(&self, index: usize) -> &NativeFunction {
+        &self.functions[index]
+    }
+}
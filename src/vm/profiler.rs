@@ -0,0 +1,190 @@
+//! Counts what the virtual machine spends its time on, see ``VirtualMachine::enable_profiling``
+
+use super::{ Instruction, Function };
+use super::super::needle::Loc;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{ Duration, Instant };
+
+/// The name the code outside of any function gets in the report
+const TOP_LEVEL: &str = "<top level>";
+
+#[derive(Clone, Default)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    /// Instructions executed in the function itself, not in the functions it called
+    pub instructions: u64,
+    /// Time spent in the function including the functions it called,
+    /// recursive calls are only counted once
+    pub total_time: Duration,
+    pub self_time: Duration
+}
+
+/// A call that hasn't returned yet
+struct ActiveCall {
+    function: usize,
+    start: Instant,
+    /// Time spent in the calls it made
+    child_time: Duration
+}
+
+pub struct Profiler {
+    function_names: Vec<String>,
+    /// Indexed by the instruction byte
+    opcode_counts: Vec<u64>,
+    /// Indexed by the line, starting at 0
+    line_counts: HashMap<usize, u64>,
+    functions: Vec<FunctionProfile>,
+    top_level_instructions: u64,
+    active_calls: Vec<ActiveCall>,
+    /// The functions of the active calls, outermost first
+    stack: Vec<usize>,
+    /// Instructions executed with the given functions on the call stack, outermost first
+    stacks: HashMap<Vec<usize>, u64>,
+    n_instructions: u64,
+    start: Instant,
+    total_time: Duration
+}
+
+impl Profiler {
+    pub fn new(functions: &[Function]) -> Profiler {
+        Profiler {
+            function_names: functions.iter().map(|function| function.name.clone()).collect(),
+            opcode_counts: vec![0; 256],
+            line_counts: HashMap::new(),
+            functions: vec![FunctionProfile::default(); functions.len()],
+            top_level_instructions: 0,
+            active_calls: Vec::new(),
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            n_instructions: 0,
+            start: Instant::now(),
+            total_time: Duration::default()
+        }
+    }
+
+    /// Called before every instruction the machine runs
+    pub(super) fn record(&mut self, instruction: Instruction, loc: Option<Loc>) {
+        self.n_instructions += 1;
+        self.opcode_counts[instruction as usize] += 1;
+        if let Some(loc) = loc {
+            *self.line_counts.entry(loc.line).or_insert(0) += 1;
+        }
+
+        match self.active_calls.last() {
+            Some(call) => self.functions[call.function].instructions += 1,
+            None => self.top_level_instructions += 1
+        }
+
+        match self.stacks.get_mut(&self.stack[..]) {
+            Some(count) => *count += 1,
+            None => { self.stacks.insert(self.stack.clone(), 1); }
+        }
+    }
+
+    pub(super) fn enter(&mut self, function: usize) {
+        self.functions[function].calls += 1;
+        self.stack.push(function);
+        self.active_calls.push(ActiveCall {
+            function: function,
+            start: Instant::now(),
+            child_time: Duration::default()
+        });
+    }
+
+    pub(super) fn exit(&mut self) {
+        let call = match self.active_calls.pop() {
+            Some(call) => call,
+            None => return
+        };
+        self.stack.pop();
+
+        let elapsed = call.start.elapsed();
+        let profile = &mut self.functions[call.function];
+        profile.self_time += elapsed.saturating_sub(call.child_time);
+        if !self.active_calls.iter().any(|active| active.function == call.function) {
+            profile.total_time += elapsed;
+        }
+        if let Some(parent) = self.active_calls.last_mut() {
+            parent.child_time += elapsed;
+        }
+    }
+
+    /// Stops the clock, calls that never returned are ended here
+    pub(super) fn finish(&mut self) {
+        while !self.active_calls.is_empty() {
+            self.exit();
+        }
+        self.total_time = self.start.elapsed();
+    }
+
+    pub fn get_instruction_count(&self) -> u64 {
+        self.n_instructions
+    }
+
+    pub fn get_opcode_count(&self, instruction: Instruction) -> u64 {
+        self.opcode_counts[instruction as usize]
+    }
+
+    pub fn get_function(&self, index: usize) -> Option<&FunctionProfile> {
+        self.functions.get(index)
+    }
+
+    /// One line per call stack, the functions separated by ``;`` and then the number of instructions,
+    /// which is what flamegraph tools take
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = self.stacks.iter().map(|(stack, count)| {
+            let mut line = String::from(TOP_LEVEL);
+            for function in stack.iter() {
+                line.push(';');
+                line.push_str(&self.function_names[*function]);
+            }
+            format!("{} {}", line, count)
+        }).collect();
+        lines.sort();
+
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// A human readable summary, with the source the lines are shown next to if there is any
+    pub fn report(&self, source: Option<&str>) -> String {
+        let mut text = String::new();
+        writeln!(text, "Profile: {} instructions in {:?}", self.n_instructions, self.total_time).unwrap();
+
+        writeln!(text, "\nFunctions:").unwrap();
+        writeln!(text, "    {:>8}  {:>12}  {:>12}  {:>12}  name", "calls", "instructions", "total time", "self time").unwrap();
+        let mut functions: Vec<(usize, &FunctionProfile)> = self.functions.iter().enumerate()
+            .filter(|(_, profile)| profile.calls > 0)
+            .collect();
+        functions.sort_by(|a, b| b.1.instructions.cmp(&a.1.instructions).then(a.0.cmp(&b.0)));
+        writeln!(text, "    {:>8}  {:>12}  {:>12}  {:>12}  {}", "", self.top_level_instructions, "", "", TOP_LEVEL).unwrap();
+        for (index, profile) in functions {
+            writeln!(text, "    {:>8}  {:>12}  {:>12}  {:>12}  {}", profile.calls, profile.instructions,
+                format!("{:.3?}", profile.total_time), format!("{:.3?}", profile.self_time),
+                self.function_names[index]).unwrap();
+        }
+
+        writeln!(text, "\nLines:").unwrap();
+        let source_lines: Vec<&str> = source.map(|source| source.lines().collect()).unwrap_or_default();
+        let mut lines: Vec<(&usize, &u64)> = self.line_counts.iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (line, count) in lines {
+            let code = source_lines.get(*line).map(|code| code.trim()).unwrap_or("");
+            writeln!(text, "    {:>8}  {:>12}  {}", line + 1, count, code).unwrap();
+        }
+
+        writeln!(text, "\nOpcodes:").unwrap();
+        let mut opcodes: Vec<(Instruction, u64)> = self.opcode_counts.iter().enumerate()
+            .filter(|(_, count)| **count > 0)
+            .filter_map(|(byte, count)| Instruction::from_byte(byte as u8).map(|instruction| (instruction, *count)))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then((a.0 as u8).cmp(&(b.0 as u8))));
+        for (instruction, count) in opcodes {
+            writeln!(text, "    {:<14}{:>12}", format!("{:?}", instruction), count).unwrap();
+        }
+
+        text
+    }
+}
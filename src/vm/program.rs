@@ -0,0 +1,40 @@
+use super::{ ByteVec, LineTable };
+use super::natives::NativeImport;
+
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    pub address: usize,
+    pub n_args: usize,
+    /// Local slots that aren't arguments
+    pub n_locals: usize,
+    pub returns: bool
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Constant {
+    Str(String),
+    Float(f64)
+}
+
+/// Everything the virtual machine needs to run some code
+#[derive(Clone)]
+pub struct Program {
+    pub code: ByteVec,
+    pub functions: Vec<Function>,
+    pub constants: Vec<Constant>,
+    pub natives: Vec<NativeImport>,
+    pub line_table: LineTable
+}
+
+impl Program {
+    pub fn new(code: ByteVec, functions: Vec<Function>) -> Program {
+        Program {
+            code: code,
+            functions: functions,
+            constants: Vec::new(),
+            natives: Vec::new(),
+            line_table: LineTable::new()
+        }
+    }
+}
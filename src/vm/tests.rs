@@ -0,0 +1,651 @@
+use super::{ ByteVec, Instruction, Program, Function, Constant, VirtualMachine, Limits, Limit, RuntimeErrorKind, DEFAULT_MAX_CALL_DEPTH };
+use super::natives::{ NativeRegistry, NativeImport, NativeSignature, Value };
+use super::super::lexer::Type;
+use super::super::needle::Loc;
+use super::{ LineTable, bytecode_file };
+use std::rc::Rc;
+use super::verifier::{ verify, VerifyErrorKind };
+use Instruction::*;
+
+/// A program with just the top level code, which has to end with a Halt
+fn top_level(build: impl FnOnce(&mut ByteVec)) -> Program {
+    let mut code = ByteVec::new();
+    build(&mut code);
+    Program::new(code, Vec::new())
+}
+
+fn rejection(program: &Program) -> VerifyErrorKind {
+    match verify(program) {
+        Ok(()) => panic!("The program was accepted"),
+        Err(error) => error.kind
+    }
+}
+
+#[test]
+fn accepts_function_calls() {
+    let mut code = ByteVec::new();
+    code.push(PushInt as u8);
+    code.push(4i64);
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Halt as u8);
+    let address = code.get_index();
+    code.push(LoadLocal as u8);
+    code.push(0u32);
+    code.push(Return as u8);
+
+    let program = Program::new(code, vec![Function {
+        name: String::from("identity"),
+        address: address,
+        n_args: 1,
+        n_locals: 0,
+        returns: true
+    }]);
+    assert!(verify(&program).is_ok());
+}
+
+#[test]
+fn rejects_invalid_instructions() {
+    let program = top_level(|code| code.push(200u8));
+    assert!(matches!(rejection(&program), VerifyErrorKind::InvalidInstruction(200)));
+
+    let program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1u32);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::TruncatedInstruction));
+}
+
+#[test]
+fn rejects_jumps_into_operands() {
+    let program = top_level(|code| {
+        code.push(Jump as u8);
+        code.push(3u64);
+        code.push(Halt as u8);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::InvalidJump(3)));
+}
+
+#[test]
+fn rejects_invalid_constants() {
+    let mut program = top_level(|code| {
+        code.push(PushConstant as u8);
+        code.push(1u32);
+        code.push(Halt as u8);
+    });
+    program.constants.push(Constant::Float(1.0));
+    assert!(matches!(rejection(&program), VerifyErrorKind::InvalidConstant(1)));
+}
+
+#[test]
+fn rejects_stack_underflow_and_wrong_types() {
+    let program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(AddInt as u8);
+        code.push(Halt as u8);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::StackUnderflow));
+
+    let mut program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(PushConstant as u8);
+        code.push(0u32);
+        code.push(AddInt as u8);
+        code.push(Halt as u8);
+    });
+    program.constants.push(Constant::Str(String::from("one")));
+    assert!(matches!(rejection(&program), VerifyErrorKind::TypeMismatch { .. }));
+}
+
+#[test]
+fn rejects_different_stacks_at_merge_points() {
+    // One path pushes an extra value before both paths meet at the Halt
+    let program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(0i64);
+        code.push(JumpIfZero as u8);
+        code.push(27u64);
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(Halt as u8);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::StackDepthMismatch { .. }));
+
+    let mut program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(0i64);
+        code.push(JumpIfZero as u8);
+        code.push(36u64);
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(Jump as u8);
+        code.push(41u64);
+        code.push(PushConstant as u8);
+        code.push(0u32);
+        code.push(Halt as u8);
+    });
+    program.constants.push(Constant::Float(1.0));
+    assert!(matches!(rejection(&program), VerifyErrorKind::StackTypeMismatch { .. }));
+}
+
+#[test]
+fn rejects_running_past_the_end() {
+    let program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::EndOfCode));
+}
+
+#[test]
+fn profiles_instructions_and_calls() {
+    let mut code = ByteVec::new();
+    code.push(PushInt as u8);
+    code.push(4i64);
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Halt as u8);
+    let address = code.get_index();
+    code.push(LoadLocal as u8);
+    code.push(0u32);
+    code.push(Return as u8);
+
+    let program = Program::new(code, vec![Function {
+        name: String::from("identity"),
+        address: address,
+        n_args: 1,
+        n_locals: 0,
+        returns: true
+    }]);
+    let mut machine = VirtualMachine::new(program);
+    machine.enable_profiling();
+    assert!(machine.run().is_ok());
+    let profile = machine.take_profile().unwrap();
+
+    assert_eq!(profile.get_instruction_count(), 5);
+    assert_eq!(profile.get_opcode_count(PushInt), 1);
+    assert_eq!(profile.get_opcode_count(Return), 1);
+    assert_eq!(profile.get_opcode_count(AddInt), 0);
+
+    let identity = profile.get_function(0).unwrap();
+    assert_eq!(identity.calls, 1);
+    assert_eq!(identity.instructions, 2);
+    assert_eq!(profile.folded_stacks(), "<top level> 3\n<top level>;identity 2\n");
+}
+
+/// Runs ``a`` ``instruction`` ``b`` and gives back why it failed
+fn int_op_error(a: i64, b: i64, instruction: Instruction) -> RuntimeErrorKind {
+    let mut machine = VirtualMachine::new(top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(a);
+        code.push(PushInt as u8);
+        code.push(b);
+        code.push(instruction as u8);
+        code.push(Halt as u8);
+    }));
+    machine.run().err().unwrap().kind
+}
+
+#[test]
+fn reports_arithmetic_errors() {
+    assert!(matches!(int_op_error(1, 0, DivInt), RuntimeErrorKind::DivisionByZero));
+    assert!(matches!(int_op_error(1, 0, ModInt), RuntimeErrorKind::DivisionByZero));
+    assert!(matches!(int_op_error(i64::MAX, 1, AddInt), RuntimeErrorKind::IntegerOverflow));
+    assert!(matches!(int_op_error(i64::MIN, 1, SubInt), RuntimeErrorKind::IntegerOverflow));
+    assert!(matches!(int_op_error(i64::MAX, 2, MulInt), RuntimeErrorKind::IntegerOverflow));
+    assert!(matches!(int_op_error(i64::MIN, -1, DivInt), RuntimeErrorKind::IntegerOverflow));
+}
+
+#[test]
+fn maps_addresses_to_lines() {
+    let mut table = LineTable::new();
+    table.add(0, Loc::new(1, 1));
+    table.add(9, Loc::new(2, 3));
+    // Another instruction from the same place doesn't need an entry
+    table.add(12, Loc::new(2, 3));
+    table.add(20, Loc::new(2, 5));
+    table.add(20, Loc::new(4, 1));
+    assert_eq!(table.entries().len(), 3);
+
+    assert!(table.get(0) == Some(Loc::new(1, 1)));
+    assert!(table.get(8) == Some(Loc::new(1, 1)));
+    assert!(table.get(9) == Some(Loc::new(2, 3)));
+    assert!(table.get(15) == Some(Loc::new(2, 3)));
+    assert!(table.get(20) == Some(Loc::new(4, 1)));
+    assert!(table.get(1000) == Some(Loc::new(4, 1)));
+
+    let mut table = LineTable::new();
+    table.add(5, Loc::new(1, 1));
+    assert!(table.get(4).is_none());
+}
+
+#[test]
+fn reports_where_errors_happened() {
+    // outer() = inner[0], inner(a) = 1 / a, every instruction is on its own line
+    let mut code = ByteVec::new();
+    let mut line_table = LineTable::new();
+    let mut line = |code: &ByteVec| line_table.add(code.get_index(), Loc::new(code.get_index(), 1));
+    line(&code);
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Halt as u8);
+    let outer = code.get_index();
+    line(&code);
+    code.push(PushInt as u8);
+    code.push(0i64);
+    let inner_call = code.get_index();
+    line(&code);
+    code.push(Call as u8);
+    code.push(1u32);
+    code.push(Return as u8);
+    let inner = code.get_index();
+    line(&code);
+    code.push(PushInt as u8);
+    code.push(1i64);
+    code.push(LoadLocal as u8);
+    code.push(0u32);
+    let division = code.get_index();
+    line(&code);
+    code.push(DivInt as u8);
+    code.push(Return as u8);
+
+    let mut program = Program::new(code, vec![
+        Function { name: String::from("outer"), address: outer, n_args: 0, n_locals: 0, returns: true },
+        Function { name: String::from("inner"), address: inner, n_args: 1, n_locals: 0, returns: true }
+    ]);
+    program.line_table = line_table;
+    let error = VirtualMachine::new(program).run().err().unwrap();
+
+    assert!(matches!(error.kind, RuntimeErrorKind::DivisionByZero));
+    assert_eq!(error.address, division);
+    assert!(error.loc == Some(Loc::new(division, 1)));
+    let trace: Vec<(&str, Option<Loc>)> = error.trace.iter()
+        .map(|entry| (&entry.function[..], entry.loc))
+        .collect();
+    assert!(trace == vec![("inner", Some(Loc::new(division, 1))), ("outer", Some(Loc::new(inner_call, 1)))]);
+}
+
+/// An array of 1 and 2 with the element at ``index`` set to 9, then the element at 1 is read
+fn set_element(index: i64) -> Program {
+    top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(PushInt as u8);
+        code.push(2i64);
+        code.push(NewArray as u8);
+        code.push(2u32);
+        code.push(Dup as u8);
+        code.push(PushInt as u8);
+        code.push(index);
+        code.push(PushInt as u8);
+        code.push(9i64);
+        code.push(SetIndex as u8);
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(Index as u8);
+        code.push(Halt as u8);
+    })
+}
+
+#[test]
+fn sets_array_elements() {
+    let program = set_element(1);
+    assert!(verify(&program).is_ok());
+    let mut machine = VirtualMachine::new(program);
+    assert!(machine.run().is_ok());
+    assert_eq!(machine.pop_result::<i64>(), Some(9));
+
+    let mut machine = VirtualMachine::new(set_element(2));
+    assert!(matches!(machine.run().err().unwrap().kind, RuntimeErrorKind::IndexOutOfBounds { index: 2, length: 2 }));
+}
+
+/// Loops forever, pushing a new array every time around if ``allocate`` is set
+fn endless_loop(allocate: bool) -> Program {
+    top_level(|code| {
+        if allocate {
+            code.push(NewArray as u8);
+            code.push(0u32);
+            code.push(Pop as u8);
+        }
+        code.push(Jump as u8);
+        code.push(0u64);
+        code.push(Halt as u8);
+    })
+}
+
+fn limit_hit(program: Program, limits: Limits) -> Limit {
+    let mut machine = VirtualMachine::new(program);
+    machine.limits = limits;
+    match machine.run() {
+        Ok(()) => panic!("The program finished"),
+        Err(error) => error.get_limit().expect("The program stopped without hitting a limit")
+    }
+}
+
+#[test]
+fn stops_at_the_instruction_limit() {
+    let limits = Limits { max_instructions: Some(100), ..Limits::default() };
+    assert_eq!(limit_hit(endless_loop(false), limits), Limit::Instructions(100));
+}
+
+#[test]
+fn stops_at_the_deadline() {
+    let limits = Limits { deadline: Some(std::time::Instant::now()), ..Limits::default() };
+    assert_eq!(limit_hit(endless_loop(false), limits), Limit::Deadline);
+}
+
+#[test]
+fn stops_at_the_memory_limits() {
+    let limits = Limits { max_heap_size: Some(1000), ..Limits::default() };
+    assert_eq!(limit_hit(endless_loop(true), limits), Limit::HeapSize(1000));
+
+    let program = top_level(|code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(Jump as u8);
+        code.push(0u64);
+        code.push(Halt as u8);
+    });
+    let limits = Limits { max_stack_size: Some(80), ..Limits::default() };
+    assert_eq!(limit_hit(program, limits), Limit::StackSize(80));
+}
+
+/// A function that calls itself forever
+fn endless_recursion() -> Program {
+    let mut code = ByteVec::new();
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Halt as u8);
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Return as u8);
+    Program::new(code, vec![Function {
+        name: String::from("forever"),
+        address: 6,
+        n_args: 0,
+        n_locals: 0,
+        returns: false
+    }])
+}
+
+#[test]
+fn stops_at_the_call_depth_limit() {
+    let mut machine = VirtualMachine::new(endless_recursion());
+    machine.limits.max_call_depth = Some(10);
+    let error = machine.run().err().unwrap();
+    assert_eq!(error.get_limit(), Some(Limit::CallDepth(10)));
+    assert_eq!(error.trace.len(), 10);
+    assert_eq!(error.address, 6);
+
+    // Without a limit the default depth still stops it, as a stack overflow
+    let mut machine = VirtualMachine::new(endless_recursion());
+    let error = machine.run().err().unwrap();
+    assert!(matches!(error.kind, RuntimeErrorKind::StackOverflow));
+    assert_eq!(error.get_limit(), None);
+    assert_eq!(error.trace.len(), DEFAULT_MAX_CALL_DEPTH);
+    assert!(format!("{}", error.kind).starts_with("Stack overflow"));
+}
+
+#[test]
+fn passes_arguments_through_nested_calls() {
+    // outer(a, b) = { c = minus[b, a]; c + 1 }, minus(a, b) = a - b
+    let mut code = ByteVec::new();
+    code.push(PushInt as u8);
+    code.push(3i64);
+    code.push(PushInt as u8);
+    code.push(10i64);
+    code.push(Call as u8);
+    code.push(0u32);
+    code.push(Halt as u8);
+    let outer = code.get_index();
+    code.push(LoadLocal as u8);
+    code.push(1u32);
+    code.push(LoadLocal as u8);
+    code.push(0u32);
+    code.push(Call as u8);
+    code.push(1u32);
+    code.push(StoreLocal as u8);
+    code.push(2u32);
+    code.push(LoadLocal as u8);
+    code.push(2u32);
+    code.push(PushInt as u8);
+    code.push(1i64);
+    code.push(AddInt as u8);
+    code.push(Return as u8);
+    let minus = code.get_index();
+    code.push(LoadLocal as u8);
+    code.push(0u32);
+    code.push(LoadLocal as u8);
+    code.push(1u32);
+    code.push(SubInt as u8);
+    code.push(Return as u8);
+
+    let program = Program::new(code, vec![
+        Function { name: String::from("outer"), address: outer, n_args: 2, n_locals: 1, returns: true },
+        Function { name: String::from("minus"), address: minus, n_args: 2, n_locals: 0, returns: true }
+    ]);
+    assert!(verify(&program).is_ok());
+    let mut machine = VirtualMachine::new(program);
+    assert!(machine.run().is_ok());
+    assert_eq!(machine.pop_result::<i64>(), Some(8));
+    // The frames are gone, so only the result was left on the stack
+    assert_eq!(machine.pop_result::<i64>(), None);
+}
+
+fn calls_native(args: Vec<Type>, returns: Option<Type>, build: impl FnOnce(&mut ByteVec)) -> Program {
+    let mut program = top_level(build);
+    program.natives.push(NativeImport {
+        name: String::from("native"),
+        signature: NativeSignature { args: args, returns: returns }
+    });
+    program
+}
+
+#[test]
+fn calls_native_functions() {
+    let program = calls_native(vec![Type::Int, Type::Str], Some(Type::Str), |code| {
+        code.push(PushInt as u8);
+        code.push(3i64);
+        code.push(PushConstant as u8);
+        code.push(0u32);
+        code.push(CallNative as u8);
+        code.push(0u32);
+        code.push(Halt as u8);
+    });
+    let mut program = program;
+    program.constants.push(Constant::Str(String::from("ab")));
+    assert!(verify(&program).is_ok());
+
+    let mut registry = NativeRegistry::new();
+    registry.register("native", vec![Type::Int, Type::Str], Some(Type::Str), |args| match args {
+        [Value::Int(n), Value::Str(string)] => Ok(Some(Value::Str(string.repeat(*n as usize)))),
+        _ => Err(String::from("Wrong arguments"))
+    });
+    let mut machine = VirtualMachine::new(program);
+    assert!(machine.link_natives(Rc::new(registry)).is_ok());
+    assert!(machine.run().is_ok());
+    let address = machine.pop_result::<u64>().unwrap();
+    assert_eq!(machine.get_string(address), Some(String::from("ababab")));
+}
+
+#[test]
+fn reports_native_errors() {
+    let build = |code: &mut ByteVec| {
+        code.push(CallNative as u8);
+        code.push(0u32);
+        code.push(Halt as u8);
+    };
+
+    // Linking fails if the registry doesn't have the function with the same signature
+    let mut registry = NativeRegistry::new();
+    registry.register("native", vec![Type::Int], None, |_| Ok(None));
+    let mut machine = VirtualMachine::new(calls_native(Vec::new(), None, build));
+    assert_eq!(machine.link_natives(Rc::new(registry)).err().map(|import| import.name), Some(String::from("native")));
+    assert!(matches!(machine.run().err().unwrap().kind, RuntimeErrorKind::UnlinkedNative(_)));
+
+    let mut registry = NativeRegistry::new();
+    registry.register("native", Vec::new(), None, |_| Err(String::from("Something went wrong")));
+    let mut machine = VirtualMachine::new(calls_native(Vec::new(), None, build));
+    assert!(machine.link_natives(Rc::new(registry)).is_ok());
+    match machine.run().err().unwrap().kind {
+        RuntimeErrorKind::NativeError { function, message } => {
+            assert_eq!(function, "native");
+            assert_eq!(message, "Something went wrong");
+        },
+        _ => panic!("Expected an error from the native function")
+    }
+}
+
+#[test]
+fn rejects_wrong_native_arguments() {
+    let program = calls_native(vec![Type::Str], None, |code| {
+        code.push(PushInt as u8);
+        code.push(1i64);
+        code.push(CallNative as u8);
+        code.push(0u32);
+        code.push(Halt as u8);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::TypeMismatch { .. }));
+
+    let program = calls_native(Vec::new(), None, |code| {
+        code.push(CallNative as u8);
+        code.push(1u32);
+        code.push(Halt as u8);
+    });
+    assert!(matches!(rejection(&program), VerifyErrorKind::InvalidNative(1)));
+}
+
+#[test]
+fn converts_values() {
+    use super::conversion::{ FromValue, IntoValue, ConversionError };
+
+    assert_eq!(u8::from_value(Value::Int(200)), Ok(200));
+    assert_eq!(u8::from_value(Value::Int(300)), Err(ConversionError::OutOfRange { value: 300, target: "u8" }));
+    assert_eq!(f64::from_value(Value::Int(2)), Ok(2.0));
+    assert_eq!(bool::from_value(true.into_value()), Ok(true));
+    assert_eq!(bool::from_value(Value::Int(2)), Err(ConversionError::OutOfRange { value: 2, target: "bool" }));
+    assert_eq!(String::from_value(Value::Float(1.5)),
+        Err(ConversionError::WrongType { expected: Type::Str, found: Type::Float }));
+    assert_eq!("hi".into_value(), Value::Str(String::from("hi")));
+
+    let array = vec![1u8, 2].into_value();
+    assert_eq!(array, Value::Array { element: Type::Int, values: vec![Value::Int(1), Value::Int(2)] });
+    assert_eq!(format!("{}", array), "[1, 2]");
+    assert_eq!(Vec::<i64>::from_value(array.clone()), Ok(vec![1, 2]));
+    assert_eq!(Vec::<String>::from_value(Value::Int(1)),
+        Err(ConversionError::WrongType { expected: Type::Array(Box::new(Type::Str)), found: Type::Int }));
+    assert_eq!(Vec::<Vec<f64>>::new().into_value().get_type(), Type::Array(Box::new(Type::Array(Box::new(Type::Float)))));
+}
+
+#[test]
+fn calls_typed_native_functions() {
+    let mut registry = NativeRegistry::new();
+    registry.register_fn("repeat", |text: String, times: u32| text.repeat(times as usize));
+    let signature = NativeSignature { args: vec![Type::Str, Type::Int], returns: Some(Type::Str) };
+    let import = NativeImport { name: String::from("repeat"), signature: signature };
+    let index = registry.find(&import).expect("The signature comes from the argument types");
+
+    let native = registry.get(index);
+    let args = [Value::Str(String::from("ab")), Value::Int(2)];
+    assert_eq!(native.call(&args), Ok(Some(Value::Str(String::from("abab")))));
+    let args = [Value::Str(String::from("ab")), Value::Int(-1)];
+    assert_eq!(native.call(&args), Err(String::from("The int -1 doesn't fit in a u32")));
+}
+
+/// A program that uses every section of the bytecode file
+fn full_program() -> Program {
+    let mut program = calls_native(vec![Type::Str], Some(Type::Int), |code| {
+        code.push(PushConstant as u8);
+        code.push(0u32);
+        code.push(CallNative as u8);
+        code.push(0u32);
+        code.push(Call as u8);
+        code.push(0u32);
+        code.push(Halt as u8);
+        code.push(LoadLocal as u8);
+        code.push(0u32);
+        code.push(Return as u8);
+    });
+    program.constants.push(Constant::Str(String::from("text")));
+    program.constants.push(Constant::Float(1.5));
+    program.functions.push(Function {
+        name: String::from("identity"),
+        address: 15,
+        n_args: 1,
+        n_locals: 0,
+        returns: true
+    });
+    program.line_table.add(0, Loc::new(1, 1));
+    program.line_table.add(15, Loc::new(2, 4));
+    program
+}
+
+fn file_bytes(program: &Program) -> Vec<u8> {
+    bytecode_file::write_program(program).ok().unwrap().iter().collect()
+}
+
+fn load_bytes(data: &[u8]) -> Result<Program, bytecode_file::LoadError> {
+    let mut bytes = ByteVec::new();
+    for byte in data {
+        bytes.push_byte(*byte);
+    }
+    bytecode_file::read_program(&bytes)
+}
+
+#[test]
+fn saves_and_loads_bytecode_files() {
+    let program = full_program();
+    let path = std::env::temp_dir().join(format!("tlang_round_trip_{}.tlbc", std::process::id()));
+    let path = path.to_string_lossy();
+    assert!(bytecode_file::save(&program, &path).is_ok());
+    let loaded = bytecode_file::load(&path).ok().unwrap();
+    std::fs::remove_file(&*path).ok();
+
+    assert!(loaded.code.iter().eq(program.code.iter()));
+    assert!(loaded.constants == program.constants);
+    let function = &loaded.functions[0];
+    assert_eq!((&function.name[..], function.address, function.n_args, function.n_locals, function.returns),
+        ("identity", 15, 1, 0, true));
+    assert_eq!(loaded.natives.len(), 1);
+    assert_eq!(loaded.natives[0].name, "native");
+    assert_eq!(loaded.natives[0].signature, program.natives[0].signature);
+    assert!(loaded.line_table.entries() == program.line_table.entries());
+}
+
+#[test]
+fn rejects_invalid_bytecode_files() {
+    use bytecode_file::LoadError;
+    let data = file_bytes(&full_program());
+
+    assert!(matches!(load_bytes(&data[..data.len() - 3]), Err(LoadError::Truncated("code"))));
+    assert!(matches!(load_bytes(&data[..20]), Err(LoadError::Truncated("constant pool"))));
+    assert!(matches!(load_bytes(&data[..2]), Err(LoadError::NotBytecode)));
+
+    let mut magic = data.clone();
+    magic[0] = b'X';
+    assert!(matches!(load_bytes(&magic), Err(LoadError::NotBytecode)));
+
+    let mut version = data.clone();
+    version[4..6].copy_from_slice(&(bytecode_file::VERSION - 1).to_le_bytes());
+    assert!(matches!(load_bytes(&version), Err(LoadError::WrongVersion(old)) if old == bytecode_file::VERSION - 1));
+
+    let mut corrupt = data.clone();
+    *corrupt.last_mut().unwrap() ^= 0xff;
+    assert!(matches!(load_bytes(&corrupt), Err(LoadError::ChecksumMismatch)));
+
+    assert!(load_bytes(&data).is_ok());
+}
+
+#[test]
+fn refuses_to_save_natives_with_other_types() {
+    let program = calls_native(vec![Type::Array(Box::new(Type::Int))], None, |code| code.push(Halt as u8));
+    match bytecode_file::write_program(&program) {
+        Err(bytecode_file::SaveError::UnstorableType { function, value_type }) => {
+            assert_eq!(function, "native");
+            assert_eq!(value_type, Type::Array(Box::new(Type::Int)));
+        },
+        _ => panic!("Expected the array argument to be refused")
+    }
+}
@@ -0,0 +1,379 @@
+//! Checks that a program can't make the virtual machine read garbage,
+//! by simulating the types on the stack along every path through the code.
+
+use super::{ Instruction, Program, Constant };
+use super::super::lexer::Type;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SlotType {
+    Int,
+    Float,
+    Str,
+    Array,
+    /// Arguments, return values and array elements, we don't know what they are
+    Unknown
+}
+
+impl SlotType {
+    fn fits(self, expected: SlotType) -> bool {
+        self == expected || self == SlotType::Unknown || expected == SlotType::Unknown
+    }
+
+    /// What a slot can be after two paths join, None if they conflict
+    fn merge(self, other: SlotType) -> Option<SlotType> {
+        if self == other {
+            Some(self)
+        }else if self == SlotType::Unknown || other == SlotType::Unknown {
+            Some(SlotType::Unknown)
+        }else{
+            None
+        }
+    }
+}
+
+impl From<Type> for SlotType {
+    fn from(value_type: Type) -> SlotType {
+        match value_type {
+            Type::Int => SlotType::Int,
+            Type::Float => SlotType::Float,
+            Type::Str => SlotType::Str,
+            // A struct is an array of its fields
+            Type::Array(_) | Type::Struct { .. } => SlotType::Array,
+            Type::Nil | Type::Function { .. } => SlotType::Unknown
+        }
+    }
+}
+
+impl std::fmt::Display for SlotType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SlotType::Int => "int",
+            SlotType::Float => "float",
+            SlotType::Str => "string",
+            SlotType::Array => "array",
+            SlotType::Unknown => "unknown"
+        })
+    }
+}
+
+pub enum VerifyErrorKind {
+    InvalidInstruction(u8),
+    TruncatedInstruction,
+    InvalidJump(u64),
+    InvalidFunctionAddress(usize),
+    InvalidConstant(u32),
+    InvalidFunction(u32),
+    InvalidNative(u32),
+    InvalidLocal(u32),
+    StackUnderflow,
+    TypeMismatch { expected: &'static str, found: String },
+    StackDepthMismatch { first: usize, second: usize },
+    StackTypeMismatch { first: String, second: String },
+    JumpBetweenFunctions,
+    ReturnOutsideFunction,
+    HaltInsideFunction,
+    EndOfCode
+}
+
+impl std::fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use VerifyErrorKind::*;
+        match self {
+            InvalidInstruction(byte) => write!(f, "Invalid instruction '{:x}'", byte),
+            TruncatedInstruction => write!(f, "The instruction is cut off by the end of the code"),
+            InvalidJump(address) => write!(f, "Jump to {:04x}, which is not the start of an instruction", address),
+            InvalidFunctionAddress(address) =>
+                write!(f, "A function starts at {:04x}, which is not the start of an instruction", address),
+            InvalidConstant(index) => write!(f, "Invalid constant index '{}'", index),
+            InvalidFunction(index) => write!(f, "Invalid function index '{}'", index),
+            InvalidNative(index) => write!(f, "Invalid native function index '{}'", index),
+            InvalidLocal(index) => write!(f, "Invalid local slot '{}'", index),
+            StackUnderflow => write!(f, "Stack underflow"),
+            TypeMismatch { expected, found } => write!(f, "Expected {} on the stack, found {}", expected, found),
+            StackDepthMismatch { first, second } =>
+                write!(f, "Paths meet with different stack depths, {} and {}", first, second),
+            StackTypeMismatch { first, second } =>
+                write!(f, "Paths meet with different types on the stack, {} and {}", first, second),
+            JumpBetweenFunctions => write!(f, "Code is reachable from more than one function"),
+            ReturnOutsideFunction => write!(f, "Return outside of a function"),
+            HaltInsideFunction => write!(f, "Halt inside of a function"),
+            EndOfCode => write!(f, "Execution can run past the end of the code")
+        }
+    }
+}
+
+pub struct VerifyError {
+    pub address: usize,
+    pub kind: VerifyErrorKind
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({:04x}): {}", self.address, self.kind)
+    }
+}
+
+/// What we know at the start of an instruction
+#[derive(Clone, PartialEq)]
+struct State {
+    /// None for the top level code
+    function: Option<usize>,
+    stack: Vec<SlotType>,
+    locals: Vec<SlotType>
+}
+
+impl State {
+    /// Merges another path into this one, returns true if anything changed
+    fn merge(&mut self, other: &State) -> Result<bool, VerifyErrorKind> {
+        if self.function != other.function {
+            return Err(VerifyErrorKind::JumpBetweenFunctions);
+        }
+        if self.stack.len() != other.stack.len() {
+            return Err(VerifyErrorKind::StackDepthMismatch { first: self.stack.len(), second: other.stack.len() });
+        }
+
+        let mut changed = false;
+        for (slot, other_slot) in self.stack.iter_mut().zip(other.stack.iter()) {
+            let merged = slot.merge(*other_slot).ok_or_else(|| VerifyErrorKind::StackTypeMismatch {
+                first: format!("{}", slot),
+                second: format!("{}", other_slot)
+            })?;
+            changed |= merged != *slot;
+            *slot = merged;
+        }
+
+        // Locals are allowed to be reused for different types, they just become unknown
+        for (local, other_local) in self.locals.iter_mut().zip(other.locals.iter()) {
+            let merged = local.merge(*other_local).unwrap_or(SlotType::Unknown);
+            changed |= merged != *local;
+            *local = merged;
+        }
+
+        Ok(changed)
+    }
+
+    fn pop(&mut self, expected: SlotType) -> Result<SlotType, VerifyErrorKind> {
+        let slot = self.stack.pop().ok_or(VerifyErrorKind::StackUnderflow)?;
+        if !slot.fits(expected) {
+            return Err(VerifyErrorKind::TypeMismatch {
+                expected: match expected {
+                    SlotType::Int => "an int",
+                    SlotType::Float => "a float",
+                    SlotType::Str => "a string",
+                    SlotType::Array => "an array",
+                    SlotType::Unknown => "a value"
+                },
+                found: format!("{}", slot)
+            });
+        }
+        Ok(slot)
+    }
+
+    fn binary_op(&mut self, operand: SlotType, result: SlotType) -> Result<(), VerifyErrorKind> {
+        self.pop(operand)?;
+        self.pop(operand)?;
+        self.stack.push(result);
+        Ok(())
+    }
+}
+
+/// Decodes every instruction from the start, gives back the addresses they start at
+fn find_instructions(program: &Program) -> Result<Vec<bool>, VerifyError> {
+    let length = program.code.get_index();
+    let mut starts = vec![false; length];
+    let mut address = 0;
+
+    while address < length {
+        let byte = program.code.read::<u8>(address).unwrap();
+        let instruction = Instruction::from_byte(byte)
+            .ok_or(VerifyError { address: address, kind: VerifyErrorKind::InvalidInstruction(byte) })?;
+        if address + 1 + instruction.operand_size() > length {
+            return Err(VerifyError { address: address, kind: VerifyErrorKind::TruncatedInstruction });
+        }
+
+        starts[address] = true;
+        address += 1 + instruction.operand_size();
+    }
+
+    Ok(starts)
+}
+
+/// Simulates one instruction, gives back the addresses execution can continue at
+fn step(program: &Program, address: usize, state: &mut State) -> Result<Vec<usize>, VerifyErrorKind> {
+    use Instruction::*;
+    use SlotType::*;
+
+    let instruction = Instruction::from_byte(program.code.read::<u8>(address).unwrap()).unwrap();
+    let next = address + 1 + instruction.operand_size();
+    let read_u32 = || program.code.read::<u32>(address + 1).unwrap();
+
+    match instruction {
+        Halt => {
+            if state.function.is_some() {
+                return Err(VerifyErrorKind::HaltInsideFunction);
+            }
+            return Ok(Vec::new());
+        },
+        PushInt => state.stack.push(Int),
+        PushConstant => {
+            let index = read_u32();
+            state.stack.push(match program.constants.get(index as usize) {
+                Some(Constant::Float(_)) => Float,
+                Some(Constant::Str(_)) => Str,
+                None => return Err(VerifyErrorKind::InvalidConstant(index))
+            });
+        },
+        Pop => { state.pop(Unknown)?; },
+        Dup => {
+            let slot = state.pop(Unknown)?;
+            state.stack.push(slot);
+            state.stack.push(slot);
+        },
+        LoadLocal => {
+            let slot = read_u32();
+            let local = *state.locals.get(slot as usize).ok_or(VerifyErrorKind::InvalidLocal(slot))?;
+            state.stack.push(local);
+        },
+        StoreLocal => {
+            let slot = read_u32();
+            if slot as usize >= state.locals.len() {
+                return Err(VerifyErrorKind::InvalidLocal(slot));
+            }
+            state.locals[slot as usize] = state.pop(Unknown)?;
+        },
+        AddInt | SubInt | MulInt | DivInt | ModInt => state.binary_op(Int, Int)?,
+        AddFloat | SubFloat | MulFloat | DivFloat => state.binary_op(Float, Float)?,
+        EqualsInt | LessInt => state.binary_op(Int, Int)?,
+        EqualsFloat | LessFloat => state.binary_op(Float, Int)?,
+        IntToFloat => {
+            state.pop(Int)?;
+            state.stack.push(Float);
+        },
+        FloatToInt => {
+            state.pop(Float)?;
+            state.stack.push(Int);
+        },
+        NewArray => {
+            for _ in 0..read_u32() {
+                state.pop(Unknown)?;
+            }
+            state.stack.push(Array);
+        },
+        Index => {
+            state.pop(Int)?;
+            state.pop(Array)?;
+            state.stack.push(Unknown);
+        },
+        SetIndex => {
+            state.pop(Unknown)?;
+            state.pop(Int)?;
+            state.pop(Array)?;
+        },
+        Jump => return Ok(vec![program.code.read::<u64>(address + 1).unwrap() as usize]),
+        JumpIfZero => {
+            state.pop(Int)?;
+            return Ok(vec![next, program.code.read::<u64>(address + 1).unwrap() as usize]);
+        },
+        Call => {
+            let index = read_u32();
+            let function = program.functions.get(index as usize)
+                .ok_or(VerifyErrorKind::InvalidFunction(index))?;
+            for _ in 0..function.n_args {
+                state.pop(Unknown)?;
+            }
+            if function.returns {
+                state.stack.push(Unknown);
+            }
+        },
+        CallNative => {
+            let index = read_u32();
+            let native = program.natives.get(index as usize)
+                .ok_or(VerifyErrorKind::InvalidNative(index))?;
+            for arg in native.signature.args.iter().rev() {
+                state.pop(SlotType::from(arg.clone()))?;
+            }
+            if let Some(returns) = &native.signature.returns {
+                state.stack.push(SlotType::from(returns.clone()));
+            }
+        },
+        Return => {
+            let function = state.function.ok_or(VerifyErrorKind::ReturnOutsideFunction)?;
+            if program.functions[function].returns {
+                state.pop(Unknown)?;
+            }
+            return Ok(Vec::new());
+        }
+    }
+
+    Ok(vec![next])
+}
+
+pub fn verify(program: &Program) -> Result<(), VerifyError> {
+    let length = program.code.get_index();
+    let starts = find_instructions(program)?;
+
+    let mut states: Vec<Option<State>> = vec![None; length];
+    let mut work = Vec::new();
+
+    let mut entries = Vec::new();
+    if length > 0 {
+        entries.push((0, State { function: None, stack: Vec::new(), locals: Vec::new() }));
+    }
+    for (i, function) in program.functions.iter().enumerate() {
+        if function.address >= length || !starts[function.address] {
+            return Err(VerifyError {
+                address: function.address,
+                kind: VerifyErrorKind::InvalidFunctionAddress(function.address)
+            });
+        }
+
+        entries.push((function.address, State {
+            function: Some(i),
+            stack: Vec::new(),
+            locals: vec![SlotType::Unknown; function.n_args + function.n_locals]
+        }));
+    }
+
+    for (address, state) in entries {
+        match &mut states[address] {
+            Some(existing) => {
+                existing.merge(&state).map_err(|kind| VerifyError { address: address, kind: kind })?;
+            },
+            None => states[address] = Some(state)
+        }
+        work.push(address);
+    }
+
+    while let Some(address) = work.pop() {
+        let mut state = states[address].clone().unwrap();
+        let successors = step(program, address, &mut state)
+            .map_err(|kind| VerifyError { address: address, kind: kind })?;
+
+        for successor in successors {
+            if successor >= length {
+                let kind = if successor == length { VerifyErrorKind::EndOfCode }
+                    else { VerifyErrorKind::InvalidJump(successor as u64) };
+                return Err(VerifyError { address: address, kind: kind });
+            }
+            if !starts[successor] {
+                return Err(VerifyError { address: address, kind: VerifyErrorKind::InvalidJump(successor as u64) });
+            }
+
+            match &mut states[successor] {
+                Some(existing) => {
+                    let changed = existing.merge(&state)
+                        .map_err(|kind| VerifyError { address: successor, kind: kind })?;
+                    if changed {
+                        work.push(successor);
+                    }
+                },
+                None => {
+                    states[successor] = Some(state.clone());
+                    work.push(successor);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Noticing when files change, for re-running a program while it's being edited.
+//!
+//! Files are polled rather than watched through the OS, so it works the same everywhere.
+//! A file counts as changed when its modification time or length is different,
+//! or when it appears or disappears.
+
+use std::path::PathBuf;
+use std::time::{ Duration, SystemTime };
+
+/// What a file looked like the last time it was polled, None if it couldn't be read
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &PathBuf) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+pub struct Watcher {
+    files: Vec<(PathBuf, Stamp)>
+}
+
+impl Watcher {
+    /// Starts watching the files as they are now
+    pub fn new(paths: Vec<PathBuf>) -> Watcher {
+        let mut watcher = Watcher {
+            files: Vec::new()
+        };
+        watcher.set_files(paths);
+        watcher
+    }
+
+    /// Replaces the files that are watched, the ones that were already watched keep their state
+    pub fn set_files(&mut self, paths: Vec<PathBuf>) {
+        let old_files = std::mem::take(&mut self.files);
+        self.files = paths.into_iter().map(|path| {
+            let old = old_files.iter().find(|(old_path, _)| *old_path == path);
+            let file_stamp = match old {
+                Some((_, file_stamp)) => *file_stamp,
+                None => stamp(&path)
+            };
+            (path, file_stamp)
+        }).collect();
+    }
+
+    pub fn get_files(&self) -> Vec<&PathBuf> {
+        self.files.iter().map(|(path, _)| path).collect()
+    }
+
+    /// Whether any of the files changed since the last poll
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, file_stamp) in self.files.iter_mut() {
+            let new_stamp = stamp(path);
+            if new_stamp != *file_stamp {
+                *file_stamp = new_stamp;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Blocks until one of the files changes, looking every ``interval``
+    pub fn wait(&mut self, interval: Duration) {
+        while !self.poll() {
+            std::thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
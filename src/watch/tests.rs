@@ -0,0 +1,43 @@
+use super::Watcher;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tlang_watch_{}_{}.tlang", name, std::process::id()))
+}
+
+#[test]
+fn notices_changes() {
+    let path = temp_path("changes");
+    std::fs::write(&path, "1").unwrap();
+    let mut watcher = Watcher::new(vec![path.clone()]);
+    assert!(!watcher.poll());
+
+    // The length changes too, in case the modification time doesn't
+    std::fs::write(&path, "12").unwrap();
+    assert!(watcher.poll());
+    assert!(!watcher.poll());
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(watcher.poll());
+    std::fs::write(&path, "1").unwrap();
+    assert!(watcher.poll());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keeps_the_state_of_files_when_they_are_replaced() {
+    let first = temp_path("first");
+    let second = temp_path("second");
+    std::fs::write(&first, "1").unwrap();
+    std::fs::write(&second, "2").unwrap();
+
+    let mut watcher = Watcher::new(vec![first.clone()]);
+    std::fs::write(&first, "11").unwrap();
+    watcher.set_files(vec![first.clone(), second.clone()]);
+    assert_eq!(watcher.get_files(), vec![&first, &second]);
+    assert!(watcher.poll());
+    assert!(!watcher.poll());
+
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+}
@@ -0,0 +1,112 @@
+//! Runs the ``tlang`` binary, to check what scripts calling it can rely on
+
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+
+fn tlang(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tlang")).args(args).output().expect("Couldn't start tlang")
+}
+
+/// Writes the code to a file of its own, so the tests can run at the same time
+fn source_file(name: &str, code: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tlang_cli_{}_{}.tlang", name, std::process::id()));
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+fn run_code(name: &str, code: &str) -> Output {
+    let path = source_file(name, code);
+    let output = tlang(&["run", path.to_str().unwrap()]);
+    std::fs::remove_file(path).ok();
+    output
+}
+
+#[test]
+fn runs_files() {
+    let output = run_code("runs", "(x = 3; print[x]; x)");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n= 3\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn runs_scripts() {
+    let path = source_file("script", "#!/usr/bin/env tlang\n(print[arg[0]]; arg_count[])");
+    let output = tlang(&[path.to_str().unwrap(), "--first", "second"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "--first\n");
+
+    let output = tlang(&["file", path.to_str().unwrap(), "only"]);
+    assert_eq!(output.status.code(), Some(1));
+    std::fs::remove_file(path).ok();
+
+    let output = run_code("exit", "(print[\"before\"]; exit[7]; print[\"after\"]; 0)");
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+}
+
+#[test]
+fn runs_code_from_the_command_line() {
+    let output = tlang(&["eval", "(print[arg[0]]; 2.5)", "first"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "first\n= 2.5\n");
+
+    assert_eq!(tlang(&["eval", "(x = 1"]).status.code(), Some(4));
+    assert_eq!(tlang(&["eval"]).status.code(), Some(2));
+}
+
+#[test]
+fn profiles_with_the_option_before_the_path() {
+    let path = source_file("profile", "(x = 3; x)");
+    let output = tlang(&["run", "--profile", path.to_str().unwrap(), "--profile"]);
+    let folded = path.with_extension("folded");
+    std::fs::remove_file(&path).ok();
+    std::fs::remove_file(&folded).ok();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Wrote folded stacks"));
+}
+
+#[test]
+fn exits_with_the_kind_of_error() {
+    let output = run_code("parse", "(x = 1");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expected ')'"));
+
+    assert_eq!(run_code("type", "print[1, 2]").status.code(), Some(5));
+
+    let path = source_file("debug", "print[1, 2]");
+    assert_eq!(tlang(&["debug", path.to_str().unwrap()]).status.code(), Some(5));
+    std::fs::remove_file(path).ok();
+
+    let path = source_file("trailing", "\"a\" garbage ) ) )");
+    let output = tlang(&["check", path.to_str().unwrap()]);
+    std::fs::remove_file(path).ok();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expected the end of the code"));
+    assert_eq!(tlang(&["run", "missing.tlang"]).status.code(), Some(1));
+}
+
+#[test]
+fn checks_the_command_line() {
+    assert_eq!(tlang(&[]).status.code(), Some(2));
+    assert_eq!(tlang(&["frobnicate"]).status.code(), Some(2));
+    assert_eq!(tlang(&["run"]).status.code(), Some(2));
+    assert_eq!(tlang(&["run", "--fast", "x.tlang"]).status.code(), Some(2));
+
+    let output = tlang(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: tlang"));
+}
+
+#[test]
+fn prints_parts_of_the_compiler() {
+    let path = source_file("parts", "(x = 1; x)");
+    let path = path.to_str().unwrap();
+    for command in ["check", "tokens", "ast", "scopes", "disasm"].iter() {
+        let output = tlang(&[command, path]);
+        assert_eq!(output.status.code(), Some(0), "'tlang {}' failed", command);
+    }
+    assert!(String::from_utf8_lossy(&tlang(&["scopes", path]).stdout).contains("x: number"));
+    std::fs::remove_file(path).ok();
+}